@@ -0,0 +1,16 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use lsc::emit::decode;
+use lsc::emit::wat::Style;
+
+// Decoding must reject malformed modules rather than panic, and whatever it
+// accepts must print and encode again.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(module) = decode::decode(data) {
+        let _ = module.objdump();
+        let _ = module.wat(Style::Flat);
+        let _ = module.wat(Style::Folded);
+        assert!(decode::decode(&module.encode()).is_ok());
+    }
+});
@@ -0,0 +1,48 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use lsc::ast::visit::{self, Visitor};
+use lsc::ast::Expression;
+use lsc::lex::TokenStream;
+use lsc::parse::Parser;
+
+/// Walks every node, passes over the tree recurse just as deeply.
+struct CountExpressions(usize);
+
+impl Visitor for CountExpressions {
+    fn visit_expression(&mut self, expression: &Expression) {
+        self.0 += 1;
+        visit::walk_expression(self, expression);
+    }
+}
+
+// Lexing and parsing must never panic, whatever bytes they are given. The driver
+// reads files the same way, replacing invalid UTF-8 before lexing.
+fuzz_target!(|data: &[u8]| {
+    let src = String::from_utf8_lossy(data);
+
+    let mut parser = Parser::new(TokenStream::new(&src));
+    let (program, errors) = parser.parse();
+
+    CountExpressions(0).visit_program(&program);
+    let captures = lsc::capture::analyze(&program);
+    let (resolution, _) = lsc::resolve::resolve(&program);
+    let _ = lsc::lint::lint(&program);
+    let (types, _) = lsc::check::check(&program);
+    let (folded, _) = lsc::fold::fold(program.clone(), &resolution);
+    let _ = lsc::flow::analyze(&folded, &resolution);
+
+    // Lowering expects a program without errors, but mustn't fall over on one
+    // that has them either.
+    let (mut module, _) = lsc::ir::lower::lower(&folded, &resolution, &types, &captures);
+    lsc::ir::pass::PassManager::standard().run(&mut module);
+    let _ = module.to_string();
+    let _ = lsc::emit::ModuleEmitter::default().emit(&module);
+    let _ = lsc::emit::ModuleEmitter::default().wat(&module, lsc::emit::wat::Style::Folded);
+
+    // Reporting the errors has to be just as robust.
+    for e in errors {
+        let _ = e.span().line_col(&src);
+        let _ = e.to_string();
+    }
+});
@@ -0,0 +1,190 @@
+//! The syntax tree produced by `parse::Parser`.
+//!
+//! Every expression, statement, block, declaration and identifier carries a `NodeId`,
+//! unique within one parse and assigned in source order, and the `Span` of source
+//! text it was parsed from. Passes key their side tables by `NodeId` rather than
+//! mutating the tree. See `visit` for traversing it.
+
+pub use crate::lex::{Operator, Span};
+
+pub mod visit;
+
+/// Identifies a node within the tree of one parse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(pub u32);
+
+/// A name as written in the source, both where it is declared and where it is used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ident {
+    pub id: NodeId,
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub statements: Vec<Statement>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionInvocation {
+    pub func: Box<Expression>,
+    pub parameters: Vec<Expression>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueType {
+    Number(i32),
+    String(String),
+    Float(f32),
+    Boolean(bool),
+}
+
+/// A `key: value` entry of an object literal, `{ shorthand }` is stored as `shorthand: shorthand`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectProperty {
+    pub key: Ident,
+    pub value: Expression,
+}
+
+/// An anonymous function, `(x, y) => x + y` or `func (x) { ... }`.
+/// An expression body is stored as a block returning it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lambda {
+    pub parameters: Vec<Parameter>,
+    pub return_type: Option<TypeAnnotation>,
+    pub body: Block,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    pub id: NodeId,
+    pub span: Span,
+    pub kind: ExpressionKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExpressionKind {
+    Singleton(ValueType),
+    Identifier(Ident),
+    Object(Vec<ObjectProperty>),
+    Array(Vec<Expression>),
+    Invocation(FunctionInvocation),
+    Member(Box<Expression>, Ident),
+    Index(Box<Expression>, Box<Expression>),
+    Unary(Operator, Box<Expression>),
+    Operation(Box<Expression>, Operator, Box<Expression>),
+    Assignment(Box<Expression>, Operator, Box<Expression>),
+    Lambda(Lambda),
+}
+
+impl Expression {
+    /// Whether the expression names a storage location that can be assigned to.
+    pub fn is_lvalue(&self) -> bool {
+        matches!(
+            self.kind,
+            ExpressionKind::Identifier(_) | ExpressionKind::Member(..) | ExpressionKind::Index(..)
+        )
+    }
+}
+
+/// A type as written after a `:`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeAnnotation {
+    pub id: NodeId,
+    pub span: Span,
+    pub kind: TypeAnnotationKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeAnnotationKind {
+    /// `i32`, `f32`, `bool`, `string` or the name of a class.
+    Named(Ident),
+    /// `[T]`
+    Array(Box<TypeAnnotation>),
+    /// `{T}`, a map from strings to `T`.
+    Map(Box<TypeAnnotation>),
+    /// `func(T, U): R`, a function without a result type returns nothing.
+    Function(Vec<TypeAnnotation>, Option<Box<TypeAnnotation>>),
+}
+
+/// A function or lambda parameter, `name` or `name: T`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub name: Ident,
+    pub ty: Option<TypeAnnotation>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub id: NodeId,
+    pub span: Span,
+    pub statements: Vec<Statement>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableDeclaration {
+    pub id: NodeId,
+    pub span: Span,
+    pub name: Ident,
+    pub ty: Option<TypeAnnotation>,
+    pub value: Option<Expression>,
+    /// Declared with `const`, the value is known at compile time and never reassigned.
+    pub constant: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionDeclaration {
+    pub id: NodeId,
+    pub span: Span,
+    pub name: Ident,
+    pub parameters: Vec<Parameter>,
+    pub return_type: Option<TypeAnnotation>,
+    pub body: Block,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClassMember {
+    /// `prop name = value;`, stored on every instance.
+    Property(VariableDeclaration),
+    /// `let name = value;`, shared by the class.
+    Static(VariableDeclaration),
+    Method(FunctionDeclaration),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassDeclaration {
+    pub id: NodeId,
+    pub span: Span,
+    pub name: Ident,
+    pub members: Vec<ClassMember>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportDeclaration {
+    pub names: Vec<Ident>,
+    pub module: Ident,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub id: NodeId,
+    pub span: Span,
+    pub kind: StatementKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatementKind {
+    Import(ImportDeclaration),
+    Let(VariableDeclaration),
+    Function(FunctionDeclaration),
+    Class(ClassDeclaration),
+    /// `if condition { body } else ...`, the else branch is a block or another `if`.
+    If(Expression, Block, Option<Box<Statement>>),
+    While(Expression, Block),
+    Return(Option<Expression>),
+    Break,
+    Continue,
+    Block(Block),
+    Expression(Expression),
+}
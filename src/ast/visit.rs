@@ -0,0 +1,666 @@
+//! Traversals over the syntax tree.
+//!
+//! `Visitor` walks a borrowed tree, `VisitorMut` walks it mutably in place and
+//! `Folder` consumes it and rebuilds it. Each trait method defaults to the matching
+//! `walk_*` function (`fold_*` for `Folder`), which visits the node's children in
+//! source order. An implementation overrides only the nodes it cares about and
+//! calls that function itself to keep descending.
+
+use super::*;
+
+pub trait Visitor: Sized {
+    fn visit_program(&mut self, program: &Program) {
+        walk_program(self, program)
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) {
+        walk_statement(self, statement)
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        walk_block(self, block)
+    }
+
+    fn visit_import(&mut self, import: &ImportDeclaration) {
+        walk_import(self, import)
+    }
+
+    fn visit_variable(&mut self, variable: &VariableDeclaration) {
+        walk_variable(self, variable)
+    }
+
+    fn visit_function(&mut self, function: &FunctionDeclaration) {
+        walk_function(self, function)
+    }
+
+    fn visit_class(&mut self, class: &ClassDeclaration) {
+        walk_class(self, class)
+    }
+
+    fn visit_class_member(&mut self, member: &ClassMember) {
+        walk_class_member(self, member)
+    }
+
+    fn visit_parameter(&mut self, parameter: &Parameter) {
+        walk_parameter(self, parameter)
+    }
+
+    fn visit_type(&mut self, ty: &TypeAnnotation) {
+        walk_type(self, ty)
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        walk_expression(self, expression)
+    }
+
+    fn visit_ident(&mut self, _ident: &Ident) {}
+}
+
+pub fn walk_program<V: Visitor>(visitor: &mut V, program: &Program) {
+    for statement in program.statements.iter() {
+        visitor.visit_statement(statement);
+    }
+}
+
+pub fn walk_statement<V: Visitor>(visitor: &mut V, statement: &Statement) {
+    match &statement.kind {
+        StatementKind::Import(import) => visitor.visit_import(import),
+        StatementKind::Let(variable) => visitor.visit_variable(variable),
+        StatementKind::Function(function) => visitor.visit_function(function),
+        StatementKind::Class(class) => visitor.visit_class(class),
+        StatementKind::If(condition, body, otherwise) => {
+            visitor.visit_expression(condition);
+            visitor.visit_block(body);
+            if let Some(otherwise) = otherwise {
+                visitor.visit_statement(otherwise);
+            }
+        }
+        StatementKind::While(condition, body) => {
+            visitor.visit_expression(condition);
+            visitor.visit_block(body);
+        }
+        StatementKind::Return(value) => {
+            if let Some(value) = value {
+                visitor.visit_expression(value);
+            }
+        }
+        StatementKind::Break | StatementKind::Continue => {}
+        StatementKind::Block(block) => visitor.visit_block(block),
+        StatementKind::Expression(expression) => visitor.visit_expression(expression),
+    }
+}
+
+pub fn walk_block<V: Visitor>(visitor: &mut V, block: &Block) {
+    for statement in block.statements.iter() {
+        visitor.visit_statement(statement);
+    }
+}
+
+pub fn walk_import<V: Visitor>(visitor: &mut V, import: &ImportDeclaration) {
+    for name in import.names.iter() {
+        visitor.visit_ident(name);
+    }
+    visitor.visit_ident(&import.module);
+}
+
+pub fn walk_variable<V: Visitor>(visitor: &mut V, variable: &VariableDeclaration) {
+    visitor.visit_ident(&variable.name);
+    if let Some(ty) = &variable.ty {
+        visitor.visit_type(ty);
+    }
+    if let Some(value) = &variable.value {
+        visitor.visit_expression(value);
+    }
+}
+
+pub fn walk_function<V: Visitor>(visitor: &mut V, function: &FunctionDeclaration) {
+    visitor.visit_ident(&function.name);
+    for parameter in function.parameters.iter() {
+        visitor.visit_parameter(parameter);
+    }
+    if let Some(ty) = &function.return_type {
+        visitor.visit_type(ty);
+    }
+    visitor.visit_block(&function.body);
+}
+
+pub fn walk_class<V: Visitor>(visitor: &mut V, class: &ClassDeclaration) {
+    visitor.visit_ident(&class.name);
+    for member in class.members.iter() {
+        visitor.visit_class_member(member);
+    }
+}
+
+pub fn walk_class_member<V: Visitor>(visitor: &mut V, member: &ClassMember) {
+    match member {
+        ClassMember::Property(variable) | ClassMember::Static(variable) => {
+            visitor.visit_variable(variable)
+        }
+        ClassMember::Method(function) => visitor.visit_function(function),
+    }
+}
+
+pub fn walk_parameter<V: Visitor>(visitor: &mut V, parameter: &Parameter) {
+    visitor.visit_ident(&parameter.name);
+    if let Some(ty) = &parameter.ty {
+        visitor.visit_type(ty);
+    }
+}
+
+pub fn walk_type<V: Visitor>(visitor: &mut V, ty: &TypeAnnotation) {
+    match &ty.kind {
+        TypeAnnotationKind::Named(name) => visitor.visit_ident(name),
+        TypeAnnotationKind::Array(element) | TypeAnnotationKind::Map(element) => {
+            visitor.visit_type(element)
+        }
+        TypeAnnotationKind::Function(parameters, result) => {
+            for parameter in parameters.iter() {
+                visitor.visit_type(parameter);
+            }
+            if let Some(result) = result {
+                visitor.visit_type(result);
+            }
+        }
+    }
+}
+
+pub fn walk_expression<V: Visitor>(visitor: &mut V, expression: &Expression) {
+    match &expression.kind {
+        ExpressionKind::Singleton(_) => {}
+        ExpressionKind::Identifier(ident) => visitor.visit_ident(ident),
+        ExpressionKind::Object(properties) => {
+            for property in properties.iter() {
+                visitor.visit_ident(&property.key);
+                visitor.visit_expression(&property.value);
+            }
+        }
+        ExpressionKind::Array(elements) => {
+            for element in elements.iter() {
+                visitor.visit_expression(element);
+            }
+        }
+        ExpressionKind::Invocation(invocation) => {
+            visitor.visit_expression(&invocation.func);
+            for parameter in invocation.parameters.iter() {
+                visitor.visit_expression(parameter);
+            }
+        }
+        ExpressionKind::Member(object, name) => {
+            visitor.visit_expression(object);
+            visitor.visit_ident(name);
+        }
+        ExpressionKind::Index(object, index) => {
+            visitor.visit_expression(object);
+            visitor.visit_expression(index);
+        }
+        ExpressionKind::Unary(_, operand) => visitor.visit_expression(operand),
+        ExpressionKind::Operation(lhs, _, rhs) | ExpressionKind::Assignment(lhs, _, rhs) => {
+            visitor.visit_expression(lhs);
+            visitor.visit_expression(rhs);
+        }
+        ExpressionKind::Lambda(lambda) => {
+            for parameter in lambda.parameters.iter() {
+                visitor.visit_parameter(parameter);
+            }
+            if let Some(ty) = &lambda.return_type {
+                visitor.visit_type(ty);
+            }
+            visitor.visit_block(&lambda.body);
+        }
+    }
+}
+
+pub trait VisitorMut: Sized {
+    fn visit_program_mut(&mut self, program: &mut Program) {
+        walk_program_mut(self, program)
+    }
+
+    fn visit_statement_mut(&mut self, statement: &mut Statement) {
+        walk_statement_mut(self, statement)
+    }
+
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        walk_block_mut(self, block)
+    }
+
+    fn visit_import_mut(&mut self, import: &mut ImportDeclaration) {
+        walk_import_mut(self, import)
+    }
+
+    fn visit_variable_mut(&mut self, variable: &mut VariableDeclaration) {
+        walk_variable_mut(self, variable)
+    }
+
+    fn visit_function_mut(&mut self, function: &mut FunctionDeclaration) {
+        walk_function_mut(self, function)
+    }
+
+    fn visit_class_mut(&mut self, class: &mut ClassDeclaration) {
+        walk_class_mut(self, class)
+    }
+
+    fn visit_class_member_mut(&mut self, member: &mut ClassMember) {
+        walk_class_member_mut(self, member)
+    }
+
+    fn visit_parameter_mut(&mut self, parameter: &mut Parameter) {
+        walk_parameter_mut(self, parameter)
+    }
+
+    fn visit_type_mut(&mut self, ty: &mut TypeAnnotation) {
+        walk_type_mut(self, ty)
+    }
+
+    fn visit_expression_mut(&mut self, expression: &mut Expression) {
+        walk_expression_mut(self, expression)
+    }
+
+    fn visit_ident_mut(&mut self, _ident: &mut Ident) {}
+}
+
+pub fn walk_program_mut<V: VisitorMut>(visitor: &mut V, program: &mut Program) {
+    for statement in program.statements.iter_mut() {
+        visitor.visit_statement_mut(statement);
+    }
+}
+
+pub fn walk_statement_mut<V: VisitorMut>(visitor: &mut V, statement: &mut Statement) {
+    match &mut statement.kind {
+        StatementKind::Import(import) => visitor.visit_import_mut(import),
+        StatementKind::Let(variable) => visitor.visit_variable_mut(variable),
+        StatementKind::Function(function) => visitor.visit_function_mut(function),
+        StatementKind::Class(class) => visitor.visit_class_mut(class),
+        StatementKind::If(condition, body, otherwise) => {
+            visitor.visit_expression_mut(condition);
+            visitor.visit_block_mut(body);
+            if let Some(otherwise) = otherwise {
+                visitor.visit_statement_mut(otherwise);
+            }
+        }
+        StatementKind::While(condition, body) => {
+            visitor.visit_expression_mut(condition);
+            visitor.visit_block_mut(body);
+        }
+        StatementKind::Return(value) => {
+            if let Some(value) = value {
+                visitor.visit_expression_mut(value);
+            }
+        }
+        StatementKind::Break | StatementKind::Continue => {}
+        StatementKind::Block(block) => visitor.visit_block_mut(block),
+        StatementKind::Expression(expression) => visitor.visit_expression_mut(expression),
+    }
+}
+
+pub fn walk_block_mut<V: VisitorMut>(visitor: &mut V, block: &mut Block) {
+    for statement in block.statements.iter_mut() {
+        visitor.visit_statement_mut(statement);
+    }
+}
+
+pub fn walk_import_mut<V: VisitorMut>(visitor: &mut V, import: &mut ImportDeclaration) {
+    for name in import.names.iter_mut() {
+        visitor.visit_ident_mut(name);
+    }
+    visitor.visit_ident_mut(&mut import.module);
+}
+
+pub fn walk_variable_mut<V: VisitorMut>(visitor: &mut V, variable: &mut VariableDeclaration) {
+    visitor.visit_ident_mut(&mut variable.name);
+    if let Some(ty) = &mut variable.ty {
+        visitor.visit_type_mut(ty);
+    }
+    if let Some(value) = &mut variable.value {
+        visitor.visit_expression_mut(value);
+    }
+}
+
+pub fn walk_function_mut<V: VisitorMut>(visitor: &mut V, function: &mut FunctionDeclaration) {
+    visitor.visit_ident_mut(&mut function.name);
+    for parameter in function.parameters.iter_mut() {
+        visitor.visit_parameter_mut(parameter);
+    }
+    if let Some(ty) = &mut function.return_type {
+        visitor.visit_type_mut(ty);
+    }
+    visitor.visit_block_mut(&mut function.body);
+}
+
+pub fn walk_class_mut<V: VisitorMut>(visitor: &mut V, class: &mut ClassDeclaration) {
+    visitor.visit_ident_mut(&mut class.name);
+    for member in class.members.iter_mut() {
+        visitor.visit_class_member_mut(member);
+    }
+}
+
+pub fn walk_class_member_mut<V: VisitorMut>(visitor: &mut V, member: &mut ClassMember) {
+    match member {
+        ClassMember::Property(variable) | ClassMember::Static(variable) => {
+            visitor.visit_variable_mut(variable)
+        }
+        ClassMember::Method(function) => visitor.visit_function_mut(function),
+    }
+}
+
+pub fn walk_parameter_mut<V: VisitorMut>(visitor: &mut V, parameter: &mut Parameter) {
+    visitor.visit_ident_mut(&mut parameter.name);
+    if let Some(ty) = &mut parameter.ty {
+        visitor.visit_type_mut(ty);
+    }
+}
+
+pub fn walk_type_mut<V: VisitorMut>(visitor: &mut V, ty: &mut TypeAnnotation) {
+    match &mut ty.kind {
+        TypeAnnotationKind::Named(name) => visitor.visit_ident_mut(name),
+        TypeAnnotationKind::Array(element) | TypeAnnotationKind::Map(element) => {
+            visitor.visit_type_mut(element)
+        }
+        TypeAnnotationKind::Function(parameters, result) => {
+            for parameter in parameters.iter_mut() {
+                visitor.visit_type_mut(parameter);
+            }
+            if let Some(result) = result {
+                visitor.visit_type_mut(result);
+            }
+        }
+    }
+}
+
+pub fn walk_expression_mut<V: VisitorMut>(visitor: &mut V, expression: &mut Expression) {
+    match &mut expression.kind {
+        ExpressionKind::Singleton(_) => {}
+        ExpressionKind::Identifier(ident) => visitor.visit_ident_mut(ident),
+        ExpressionKind::Object(properties) => {
+            for property in properties.iter_mut() {
+                visitor.visit_ident_mut(&mut property.key);
+                visitor.visit_expression_mut(&mut property.value);
+            }
+        }
+        ExpressionKind::Array(elements) => {
+            for element in elements.iter_mut() {
+                visitor.visit_expression_mut(element);
+            }
+        }
+        ExpressionKind::Invocation(invocation) => {
+            visitor.visit_expression_mut(&mut invocation.func);
+            for parameter in invocation.parameters.iter_mut() {
+                visitor.visit_expression_mut(parameter);
+            }
+        }
+        ExpressionKind::Member(object, name) => {
+            visitor.visit_expression_mut(object);
+            visitor.visit_ident_mut(name);
+        }
+        ExpressionKind::Index(object, index) => {
+            visitor.visit_expression_mut(object);
+            visitor.visit_expression_mut(index);
+        }
+        ExpressionKind::Unary(_, operand) => visitor.visit_expression_mut(operand),
+        ExpressionKind::Operation(lhs, _, rhs) | ExpressionKind::Assignment(lhs, _, rhs) => {
+            visitor.visit_expression_mut(lhs);
+            visitor.visit_expression_mut(rhs);
+        }
+        ExpressionKind::Lambda(lambda) => {
+            for parameter in lambda.parameters.iter_mut() {
+                visitor.visit_parameter_mut(parameter);
+            }
+            if let Some(ty) = &mut lambda.return_type {
+                visitor.visit_type_mut(ty);
+            }
+            visitor.visit_block_mut(&mut lambda.body);
+        }
+    }
+}
+
+/// Rebuilds the tree by value, each node is replaced by whatever its fold returns.
+/// Node ids and spans are carried over unless a fold replaces them.
+pub trait Folder: Sized {
+    fn fold_program(&mut self, program: Program) -> Program {
+        fold_program(self, program)
+    }
+
+    fn fold_statement(&mut self, statement: Statement) -> Statement {
+        fold_statement(self, statement)
+    }
+
+    fn fold_block(&mut self, block: Block) -> Block {
+        fold_block(self, block)
+    }
+
+    fn fold_import(&mut self, import: ImportDeclaration) -> ImportDeclaration {
+        fold_import(self, import)
+    }
+
+    fn fold_variable(&mut self, variable: VariableDeclaration) -> VariableDeclaration {
+        fold_variable(self, variable)
+    }
+
+    fn fold_function(&mut self, function: FunctionDeclaration) -> FunctionDeclaration {
+        fold_function(self, function)
+    }
+
+    fn fold_class(&mut self, class: ClassDeclaration) -> ClassDeclaration {
+        fold_class(self, class)
+    }
+
+    fn fold_class_member(&mut self, member: ClassMember) -> ClassMember {
+        fold_class_member(self, member)
+    }
+
+    fn fold_parameter(&mut self, parameter: Parameter) -> Parameter {
+        fold_parameter(self, parameter)
+    }
+
+    fn fold_type(&mut self, ty: TypeAnnotation) -> TypeAnnotation {
+        fold_type(self, ty)
+    }
+
+    fn fold_expression(&mut self, expression: Expression) -> Expression {
+        fold_expression(self, expression)
+    }
+
+    fn fold_ident(&mut self, ident: Ident) -> Ident {
+        ident
+    }
+}
+
+pub fn fold_program<F: Folder>(folder: &mut F, program: Program) -> Program {
+    Program {
+        statements: program
+            .statements
+            .into_iter()
+            .map(|statement| folder.fold_statement(statement))
+            .collect(),
+    }
+}
+
+pub fn fold_statement<F: Folder>(folder: &mut F, statement: Statement) -> Statement {
+    let kind = match statement.kind {
+        StatementKind::Import(import) => StatementKind::Import(folder.fold_import(import)),
+        StatementKind::Let(variable) => StatementKind::Let(folder.fold_variable(variable)),
+        StatementKind::Function(function) => {
+            StatementKind::Function(folder.fold_function(function))
+        }
+        StatementKind::Class(class) => StatementKind::Class(folder.fold_class(class)),
+        StatementKind::If(condition, body, otherwise) => StatementKind::If(
+            folder.fold_expression(condition),
+            folder.fold_block(body),
+            otherwise.map(|otherwise| Box::new(folder.fold_statement(*otherwise))),
+        ),
+        StatementKind::While(condition, body) => {
+            StatementKind::While(folder.fold_expression(condition), folder.fold_block(body))
+        }
+        StatementKind::Return(value) => {
+            StatementKind::Return(value.map(|value| folder.fold_expression(value)))
+        }
+        StatementKind::Break => StatementKind::Break,
+        StatementKind::Continue => StatementKind::Continue,
+        StatementKind::Block(block) => StatementKind::Block(folder.fold_block(block)),
+        StatementKind::Expression(expression) => {
+            StatementKind::Expression(folder.fold_expression(expression))
+        }
+    };
+
+    Statement { kind, ..statement }
+}
+
+pub fn fold_block<F: Folder>(folder: &mut F, block: Block) -> Block {
+    Block {
+        statements: block
+            .statements
+            .into_iter()
+            .map(|statement| folder.fold_statement(statement))
+            .collect(),
+        ..block
+    }
+}
+
+pub fn fold_import<F: Folder>(folder: &mut F, import: ImportDeclaration) -> ImportDeclaration {
+    ImportDeclaration {
+        names: import
+            .names
+            .into_iter()
+            .map(|name| folder.fold_ident(name))
+            .collect(),
+        module: folder.fold_ident(import.module),
+    }
+}
+
+pub fn fold_variable<F: Folder>(
+    folder: &mut F,
+    variable: VariableDeclaration,
+) -> VariableDeclaration {
+    VariableDeclaration {
+        name: folder.fold_ident(variable.name),
+        ty: variable.ty.map(|ty| folder.fold_type(ty)),
+        value: variable.value.map(|value| folder.fold_expression(value)),
+        ..variable
+    }
+}
+
+pub fn fold_function<F: Folder>(
+    folder: &mut F,
+    function: FunctionDeclaration,
+) -> FunctionDeclaration {
+    FunctionDeclaration {
+        name: folder.fold_ident(function.name),
+        parameters: function
+            .parameters
+            .into_iter()
+            .map(|parameter| folder.fold_parameter(parameter))
+            .collect(),
+        return_type: function.return_type.map(|ty| folder.fold_type(ty)),
+        body: folder.fold_block(function.body),
+        ..function
+    }
+}
+
+pub fn fold_class<F: Folder>(folder: &mut F, class: ClassDeclaration) -> ClassDeclaration {
+    ClassDeclaration {
+        name: folder.fold_ident(class.name),
+        members: class
+            .members
+            .into_iter()
+            .map(|member| folder.fold_class_member(member))
+            .collect(),
+        ..class
+    }
+}
+
+pub fn fold_class_member<F: Folder>(folder: &mut F, member: ClassMember) -> ClassMember {
+    match member {
+        ClassMember::Property(variable) => ClassMember::Property(folder.fold_variable(variable)),
+        ClassMember::Static(variable) => ClassMember::Static(folder.fold_variable(variable)),
+        ClassMember::Method(function) => ClassMember::Method(folder.fold_function(function)),
+    }
+}
+
+pub fn fold_parameter<F: Folder>(folder: &mut F, parameter: Parameter) -> Parameter {
+    Parameter {
+        name: folder.fold_ident(parameter.name),
+        ty: parameter.ty.map(|ty| folder.fold_type(ty)),
+    }
+}
+
+pub fn fold_type<F: Folder>(folder: &mut F, ty: TypeAnnotation) -> TypeAnnotation {
+    let kind = match ty.kind {
+        TypeAnnotationKind::Named(name) => TypeAnnotationKind::Named(folder.fold_ident(name)),
+        TypeAnnotationKind::Array(element) => {
+            TypeAnnotationKind::Array(Box::new(folder.fold_type(*element)))
+        }
+        TypeAnnotationKind::Map(element) => {
+            TypeAnnotationKind::Map(Box::new(folder.fold_type(*element)))
+        }
+        TypeAnnotationKind::Function(parameters, result) => TypeAnnotationKind::Function(
+            parameters
+                .into_iter()
+                .map(|parameter| folder.fold_type(parameter))
+                .collect(),
+            result.map(|result| Box::new(folder.fold_type(*result))),
+        ),
+    };
+
+    TypeAnnotation { kind, ..ty }
+}
+
+pub fn fold_expression<F: Folder>(folder: &mut F, expression: Expression) -> Expression {
+    let kind = match expression.kind {
+        ExpressionKind::Singleton(value) => ExpressionKind::Singleton(value),
+        ExpressionKind::Identifier(ident) => ExpressionKind::Identifier(folder.fold_ident(ident)),
+        ExpressionKind::Object(properties) => ExpressionKind::Object(
+            properties
+                .into_iter()
+                .map(|property| ObjectProperty {
+                    key: folder.fold_ident(property.key),
+                    value: folder.fold_expression(property.value),
+                })
+                .collect(),
+        ),
+        ExpressionKind::Array(elements) => ExpressionKind::Array(
+            elements
+                .into_iter()
+                .map(|element| folder.fold_expression(element))
+                .collect(),
+        ),
+        ExpressionKind::Invocation(invocation) => ExpressionKind::Invocation(FunctionInvocation {
+            func: Box::new(folder.fold_expression(*invocation.func)),
+            parameters: invocation
+                .parameters
+                .into_iter()
+                .map(|parameter| folder.fold_expression(parameter))
+                .collect(),
+        }),
+        ExpressionKind::Member(object, name) => ExpressionKind::Member(
+            Box::new(folder.fold_expression(*object)),
+            folder.fold_ident(name),
+        ),
+        ExpressionKind::Index(object, index) => ExpressionKind::Index(
+            Box::new(folder.fold_expression(*object)),
+            Box::new(folder.fold_expression(*index)),
+        ),
+        ExpressionKind::Unary(op, operand) => {
+            ExpressionKind::Unary(op, Box::new(folder.fold_expression(*operand)))
+        }
+        ExpressionKind::Operation(lhs, op, rhs) => ExpressionKind::Operation(
+            Box::new(folder.fold_expression(*lhs)),
+            op,
+            Box::new(folder.fold_expression(*rhs)),
+        ),
+        ExpressionKind::Assignment(lhs, op, rhs) => ExpressionKind::Assignment(
+            Box::new(folder.fold_expression(*lhs)),
+            op,
+            Box::new(folder.fold_expression(*rhs)),
+        ),
+        ExpressionKind::Lambda(lambda) => ExpressionKind::Lambda(Lambda {
+            parameters: lambda
+                .parameters
+                .into_iter()
+                .map(|parameter| folder.fold_parameter(parameter))
+                .collect(),
+            return_type: lambda.return_type.map(|ty| folder.fold_type(ty)),
+            body: folder.fold_block(lambda.body),
+        }),
+    };
+
+    Expression { kind, ..expression }
+}
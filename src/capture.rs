@@ -0,0 +1,159 @@
+//! Capture analysis for anonymous functions.
+//!
+//! A lambda captures every name it uses that is declared by an enclosing function,
+//! including names used only by lambdas nested inside it, since it has to hand them
+//! on when it creates them. Module level names are globals and are never captured.
+//! Captured values are copied into the closure's environment when it is created, so
+//! later assignments in the enclosing function are not seen by the closure, and the
+//! closure can't assign them, which lowering reports.
+
+use std::collections::HashMap;
+
+use crate::ast::visit::{self, Visitor};
+use crate::ast::*;
+
+/// The names captured by each lambda, keyed by the lambda expression's `NodeId`,
+/// in the order they are first used.
+pub type Captures = HashMap<NodeId, Vec<String>>;
+
+pub fn analyze(program: &Program) -> Captures {
+    let mut analysis = CaptureAnalysis {
+        frames: vec![Frame::new(None)],
+        captures: Captures::new(),
+    };
+
+    analysis.visit_program(program);
+    analysis.captures
+}
+
+/// The names declared by one function body, one scope per nested block.
+struct Frame {
+    /// The lambda this frame belongs to, `None` for the module and declared functions.
+    lambda: Option<NodeId>,
+    scopes: Vec<Vec<String>>,
+}
+
+impl Frame {
+    fn new(lambda: Option<NodeId>) -> Self {
+        Frame {
+            lambda,
+            scopes: vec![Vec::new()],
+        }
+    }
+
+    fn declares(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.iter().any(|n| n == name))
+    }
+}
+
+struct CaptureAnalysis {
+    frames: Vec<Frame>,
+    captures: Captures,
+}
+
+impl CaptureAnalysis {
+    fn declare(&mut self, name: &Ident) {
+        if let Some(scope) = self.frames.last_mut().and_then(|f| f.scopes.last_mut()) {
+            scope.push(name.name.clone());
+        }
+    }
+
+    fn enter_function(&mut self, lambda: Option<NodeId>, parameters: &[Parameter]) {
+        self.frames.push(Frame::new(lambda));
+        for parameter in parameters {
+            self.declare(&parameter.name);
+        }
+    }
+
+    fn resolve(&mut self, name: &str) {
+        let declared_in = match self.frames.iter().rposition(|frame| frame.declares(name)) {
+            // Globals and unknown names are never captured.
+            Some(0) | None => return,
+            Some(index) => index,
+        };
+
+        for frame in self.frames[declared_in + 1..].iter() {
+            if let Some(lambda) = frame.lambda {
+                let captured = self.captures.entry(lambda).or_default();
+                if !captured.iter().any(|n| n == name) {
+                    captured.push(name.to_string());
+                }
+            }
+        }
+    }
+}
+
+impl Visitor for CaptureAnalysis {
+    fn visit_block(&mut self, block: &Block) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.scopes.push(Vec::new());
+        }
+        visit::walk_block(self, block);
+        if let Some(frame) = self.frames.last_mut() {
+            frame.scopes.pop();
+        }
+    }
+
+    fn visit_import(&mut self, import: &ImportDeclaration) {
+        for name in import.names.iter() {
+            self.declare(name);
+        }
+    }
+
+    fn visit_variable(&mut self, variable: &VariableDeclaration) {
+        // The name is only in scope after its initializer.
+        if let Some(value) = &variable.value {
+            self.visit_expression(value);
+        }
+        self.declare(&variable.name);
+    }
+
+    fn visit_function(&mut self, function: &FunctionDeclaration) {
+        // Declared before the body so a function can call itself.
+        self.declare(&function.name);
+        self.enter_function(None, &function.parameters);
+        self.visit_block(&function.body);
+        self.frames.pop();
+    }
+
+    fn visit_class(&mut self, class: &ClassDeclaration) {
+        self.declare(&class.name);
+
+        for member in class.members.iter() {
+            match member {
+                ClassMember::Property(variable) | ClassMember::Static(variable) => {
+                    if let Some(value) = &variable.value {
+                        self.visit_expression(value);
+                    }
+                }
+                ClassMember::Method(method) => {
+                    self.enter_function(None, &method.parameters);
+                    if let Some(scope) = self.frames.last_mut().and_then(|f| f.scopes.last_mut()) {
+                        scope.push("this".to_string());
+                    }
+                    self.visit_block(&method.body);
+                    self.frames.pop();
+                }
+            }
+        }
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        match &expression.kind {
+            ExpressionKind::Identifier(ident) => self.resolve(&ident.name),
+            // Property names are not variables.
+            ExpressionKind::Member(object, _) => self.visit_expression(object),
+            ExpressionKind::Object(properties) => {
+                for property in properties.iter() {
+                    self.visit_expression(&property.value);
+                }
+            }
+            ExpressionKind::Lambda(lambda) => {
+                self.enter_function(Some(expression.id), &lambda.parameters);
+                self.visit_block(&lambda.body);
+                self.frames.pop();
+            }
+            _ => visit::walk_expression(self, expression),
+        }
+    }
+}
@@ -0,0 +1,701 @@
+//! Infers a type for every expression and checks values against the annotations
+//! written for them.
+//!
+//! Typing is gradual. A name without an annotation takes the type of the value it
+//! is initialized with, a function without a result annotation takes the type it
+//! returns, and whatever the checker can't work out is `Type::Unknown`, which is
+//! accepted wherever a type is expected.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::ast::visit::{self, Visitor};
+use crate::ast::*;
+use crate::types::Type;
+
+#[derive(Clone, Debug)]
+pub enum TypeErrorKind {
+    /// An annotation names neither a builtin type nor a class.
+    UnknownType(String),
+    Mismatch { expected: Type, found: Type },
+    ArgumentCount { expected: usize, found: usize },
+    InvalidOperands { op: Operator, lhs: Type, rhs: Type },
+    InvalidOperand { op: Operator, operand: Type },
+    NotCallable(Type),
+    NotIndexable(Type),
+    /// Member access on a value that isn't an instance, class or map.
+    NoMembers(Type),
+    /// A value the checker can type but the backend has no representation for.
+    Unsupported(&'static str),
+}
+
+#[derive(Clone, Debug)]
+pub struct TypeError {
+    pub kind: TypeErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            TypeErrorKind::UnknownType(name) => write!(f, "unknown type `{}`", name),
+            TypeErrorKind::Mismatch { expected, found } => {
+                write!(f, "mismatched types, expected `{}`, found `{}`", expected, found)
+            }
+            TypeErrorKind::ArgumentCount { expected, found } => write!(
+                f,
+                "expected {} argument{}, found {}",
+                expected,
+                if *expected == 1 { "" } else { "s" },
+                found
+            ),
+            TypeErrorKind::InvalidOperands { op, lhs, rhs } => {
+                write!(f, "cannot apply `{}` to `{}` and `{}`", op, lhs, rhs)
+            }
+            TypeErrorKind::InvalidOperand { op, operand } => {
+                write!(f, "cannot apply `{}` to `{}`", op, operand)
+            }
+            TypeErrorKind::NotCallable(ty) => write!(f, "`{}` is not a function", ty),
+            TypeErrorKind::NotIndexable(ty) => write!(f, "`{}` cannot be indexed", ty),
+            TypeErrorKind::NoMembers(ty) => write!(f, "`{}` has no members", ty),
+            TypeErrorKind::Unsupported(what) => write!(f, "{} cannot be compiled yet", what),
+        }
+    }
+}
+
+/// The types the checker settled on, keyed by node. Code generation reads its
+/// wasm value types from here.
+#[derive(Debug, Default)]
+pub struct TypeTable {
+    /// The type of every expression.
+    pub expressions: HashMap<NodeId, Type>,
+    /// The type of every variable, property, parameter and import, keyed by the
+    /// `Ident` that declares it.
+    pub bindings: HashMap<NodeId, Type>,
+    /// The signature of every function, method and lambda, keyed by the declaration
+    /// or the lambda expression, with inferred result types filled in.
+    pub functions: HashMap<NodeId, Type>,
+}
+
+/// Checks a program that parsed without errors, returning the inferred types along
+/// with every error sorted by position.
+pub fn check(program: &Program) -> (TypeTable, Vec<TypeError>) {
+    let mut names = ClassNames(HashSet::new());
+    names.visit_program(program);
+
+    let mut members = ClassMembers {
+        names: &names.0,
+        classes: HashMap::new(),
+    };
+    members.visit_program(program);
+    let classes = members.classes;
+
+    let mut checker = Checker {
+        class_names: names.0,
+        classes,
+        scopes: vec![HashMap::new()],
+        returns: Vec::new(),
+        table: TypeTable::default(),
+        errors: Vec::new(),
+    };
+
+    checker.statements(&program.statements);
+
+    checker.errors.sort_by_key(|e| e.span.start);
+    (checker.table, checker.errors)
+}
+
+/// Resolves an optional annotation without reporting errors, a missing or
+/// unresolvable annotation is `Unknown`.
+fn annotation_type(annotation: &Option<TypeAnnotation>, class_names: &HashSet<String>) -> Type {
+    annotation
+        .as_ref()
+        .and_then(|annotation| {
+            Type::from_annotation(annotation, &|name: &str| class_names.contains(name)).ok()
+        })
+        .unwrap_or(Type::Unknown)
+}
+
+fn signature_type(
+    parameters: &[Parameter],
+    return_type: &Option<TypeAnnotation>,
+    class_names: &HashSet<String>,
+) -> Type {
+    let parameters = parameters
+        .iter()
+        .map(|parameter| annotation_type(&parameter.ty, class_names))
+        .collect();
+    Type::Function(parameters, Box::new(annotation_type(return_type, class_names)))
+}
+
+/// Collects the name of every class, wherever it is declared, so annotations can
+/// refer to classes declared later in the file.
+struct ClassNames(HashSet<String>);
+
+impl Visitor for ClassNames {
+    fn visit_class(&mut self, class: &ClassDeclaration) {
+        self.0.insert(class.name.name.clone());
+        visit::walk_class(self, class);
+    }
+}
+
+/// The members of a class as far as they are known before checking its body.
+#[derive(Debug, Default)]
+struct ClassInfo {
+    properties: HashMap<String, Type>,
+    statics: HashMap<String, Type>,
+    methods: HashMap<String, Type>,
+}
+
+/// Collects the annotated member types of every class, so members can be used
+/// before the class body is checked.
+struct ClassMembers<'a> {
+    names: &'a HashSet<String>,
+    classes: HashMap<String, ClassInfo>,
+}
+
+impl<'a> Visitor for ClassMembers<'a> {
+    fn visit_class(&mut self, class: &ClassDeclaration) {
+        let mut info = ClassInfo::default();
+
+        for member in class.members.iter() {
+            match member {
+                ClassMember::Property(variable) => {
+                    let ty = annotation_type(&variable.ty, self.names);
+                    info.properties.insert(variable.name.name.clone(), ty);
+                }
+                ClassMember::Static(variable) => {
+                    let ty = annotation_type(&variable.ty, self.names);
+                    info.statics.insert(variable.name.name.clone(), ty);
+                }
+                ClassMember::Method(method) => {
+                    let ty = signature_type(&method.parameters, &method.return_type, self.names);
+                    info.methods.insert(method.name.name.clone(), ty);
+                }
+            }
+        }
+
+        self.classes.insert(class.name.name.clone(), info);
+        visit::walk_class(self, class);
+    }
+}
+
+/// What the function being checked returns.
+struct Returns {
+    /// The annotated result type, `Unknown` when it is to be inferred.
+    expected: Type,
+    /// The type of every `return` seen so far.
+    found: Vec<Type>,
+}
+
+struct Checker {
+    class_names: HashSet<String>,
+    classes: HashMap<String, ClassInfo>,
+    scopes: Vec<HashMap<String, Type>>,
+    /// The function being checked, innermost last.
+    returns: Vec<Returns>,
+    table: TypeTable,
+    errors: Vec<TypeError>,
+}
+
+impl Checker {
+    fn error(&mut self, kind: TypeErrorKind, span: Span) {
+        self.errors.push(TypeError { kind, span });
+    }
+
+    /// Reports an error unless a value of type `found` can be used as `expected`.
+    fn expect(&mut self, expected: &Type, found: &Type, span: Span) {
+        if !expected.accepts(found) {
+            let kind = TypeErrorKind::Mismatch {
+                expected: expected.clone(),
+                found: found.clone(),
+            };
+            self.error(kind, span);
+        }
+    }
+
+    fn declare(&mut self, name: &Ident, ty: Type) {
+        self.table.bindings.insert(name.id, ty.clone());
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.name.clone(), ty);
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Type {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .cloned()
+            .unwrap_or(Type::Unknown)
+    }
+
+    /// Resolves an optional annotation, reporting names that aren't types.
+    fn annotation(&mut self, annotation: &Option<TypeAnnotation>) -> Type {
+        let annotation = match annotation {
+            Some(annotation) => annotation,
+            None => return Type::Unknown,
+        };
+
+        let class_names = &self.class_names;
+        match Type::from_annotation(annotation, &|name: &str| class_names.contains(name)) {
+            Ok(ty) => ty,
+            Err(name) => {
+                self.error(TypeErrorKind::UnknownType(name.name.clone()), name.span);
+                Type::Unknown
+            }
+        }
+    }
+
+    fn signature(&mut self, parameters: &[Parameter], return_type: &Option<TypeAnnotation>) -> Type {
+        let parameters = parameters
+            .iter()
+            .map(|parameter| self.annotation(&parameter.ty))
+            .collect();
+        let result = self.annotation(return_type);
+        Type::Function(parameters, Box::new(result))
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        // Functions can be called before they are declared in the same block.
+        for statement in statements.iter() {
+            if let StatementKind::Function(function) = &statement.kind {
+                let signature =
+                    signature_type(&function.parameters, &function.return_type, &self.class_names);
+                self.declare(&function.name, signature);
+            }
+        }
+
+        for statement in statements.iter() {
+            self.statement(statement);
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.scopes.push(HashMap::new());
+        self.statements(&block.statements);
+        self.scopes.pop();
+    }
+
+    fn condition(&mut self, condition: &Expression) {
+        let ty = self.expression(condition);
+        self.expect(&Type::Bool, &ty, condition.span);
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match &statement.kind {
+            StatementKind::Import(import) => {
+                for name in import.names.iter() {
+                    self.declare(name, Type::Unknown);
+                }
+            }
+            StatementKind::Let(variable) => {
+                let ty = self.variable(variable);
+                self.declare(&variable.name, ty);
+            }
+            StatementKind::Function(function) => {
+                let signature = self.function(
+                    function.id,
+                    &function.parameters,
+                    &function.return_type,
+                    &function.body,
+                    None,
+                );
+                // Later calls see the inferred result type.
+                self.declare(&function.name, signature);
+            }
+            StatementKind::Class(class) => self.class(class),
+            StatementKind::If(condition, body, otherwise) => {
+                self.condition(condition);
+                self.block(body);
+                if let Some(otherwise) = otherwise {
+                    self.statement(otherwise);
+                }
+            }
+            StatementKind::While(condition, body) => {
+                self.condition(condition);
+                self.block(body);
+            }
+            StatementKind::Return(value) => {
+                let (found, span) = match value {
+                    Some(value) => (self.expression(value), value.span),
+                    None => (Type::Void, statement.span),
+                };
+
+                if let Some(returns) = self.returns.last_mut() {
+                    returns.found.push(found.clone());
+                    let expected = returns.expected.clone();
+                    self.expect(&expected, &found, span);
+                }
+            }
+            StatementKind::Break | StatementKind::Continue => {}
+            StatementKind::Block(block) => self.block(block),
+            StatementKind::Expression(expression) => {
+                self.expression(expression);
+            }
+        }
+    }
+
+    /// Checks a declaration's value against its annotation and returns the type of the name.
+    fn variable(&mut self, variable: &VariableDeclaration) -> Type {
+        let annotated = self.annotation(&variable.ty);
+
+        let value = match &variable.value {
+            Some(value) => {
+                let found = self.expression(value);
+                self.expect(&annotated, &found, value.span);
+                found
+            }
+            None => Type::Unknown,
+        };
+
+        match variable.ty {
+            Some(_) => annotated,
+            None => value,
+        }
+    }
+
+    fn class(&mut self, class: &ClassDeclaration) {
+        let name = class.name.name.clone();
+        self.declare(&class.name, Type::Constructor(name.clone()));
+
+        for member in class.members.iter() {
+            match member {
+                ClassMember::Property(variable) | ClassMember::Static(variable) => {
+                    let ty = self.variable(variable);
+                    self.table.bindings.insert(variable.name.id, ty.clone());
+
+                    // Unannotated members take the type of their initializer.
+                    if let Some(info) = self.classes.get_mut(&name) {
+                        let members = match member {
+                            ClassMember::Property(_) => &mut info.properties,
+                            _ => &mut info.statics,
+                        };
+                        members.insert(variable.name.name.clone(), ty);
+                    }
+                }
+                ClassMember::Method(method) => {
+                    let this = Type::Class(name.clone());
+                    let signature = self.function(
+                        method.id,
+                        &method.parameters,
+                        &method.return_type,
+                        &method.body,
+                        Some(this),
+                    );
+
+                    if let Some(info) = self.classes.get_mut(&name) {
+                        info.methods.insert(method.name.name.clone(), signature);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Checks a function body and returns its signature, with the result type
+    /// inferred from its `return` statements when it isn't annotated.
+    fn function(
+        &mut self,
+        id: NodeId,
+        parameters: &[Parameter],
+        return_type: &Option<TypeAnnotation>,
+        body: &Block,
+        this: Option<Type>,
+    ) -> Type {
+        let (parameter_types, expected) = match self.signature(parameters, return_type) {
+            Type::Function(parameter_types, result) => (parameter_types, *result),
+            _ => (vec![Type::Unknown; parameters.len()], Type::Unknown),
+        };
+
+        self.scopes.push(HashMap::new());
+        if let (Some(this), Some(scope)) = (this, self.scopes.last_mut()) {
+            scope.insert("this".to_string(), this);
+        }
+        for (parameter, ty) in parameters.iter().zip(parameter_types.iter()) {
+            self.declare(&parameter.name, ty.clone());
+        }
+
+        self.returns.push(Returns {
+            expected: expected.clone(),
+            found: Vec::new(),
+        });
+        self.block(body);
+        let returns = self.returns.pop();
+        self.scopes.pop();
+
+        let result = match (return_type, returns) {
+            (Some(_), _) | (None, None) => expected,
+            (None, Some(returns)) => {
+                let mut found = returns.found.into_iter();
+                match found.next() {
+                    None => Type::Void,
+                    Some(first) if found.all(|ty| ty == first) => first,
+                    Some(_) => Type::Unknown,
+                }
+            }
+        };
+
+        let signature = Type::Function(parameter_types, Box::new(result));
+        self.table.functions.insert(id, signature.clone());
+        signature
+    }
+
+    fn expression(&mut self, expression: &Expression) -> Type {
+        let ty = self.expression_kind(expression);
+        self.table.expressions.insert(expression.id, ty.clone());
+        ty
+    }
+
+    /// Checks that every element has the same type and returns it.
+    fn elements<'e, I>(&mut self, elements: I) -> Type
+    where
+        I: Iterator<Item = &'e Expression>,
+    {
+        let mut element = Type::Unknown;
+
+        for expression in elements {
+            let ty = self.expression(expression);
+            if element == Type::Unknown {
+                element = ty;
+            } else {
+                self.expect(&element, &ty, expression.span);
+            }
+        }
+
+        element
+    }
+
+    fn expression_kind(&mut self, expression: &Expression) -> Type {
+        match &expression.kind {
+            ExpressionKind::Singleton(value) => match value {
+                ValueType::Number(_) => Type::I32,
+                ValueType::Float(_) => Type::F32,
+                ValueType::String(_) => Type::String,
+                ValueType::Boolean(_) => Type::Bool,
+            },
+            ExpressionKind::Identifier(ident) => self.lookup(&ident.name),
+            ExpressionKind::Object(properties) => {
+                self.error(TypeErrorKind::Unsupported("object literals"), expression.span);
+
+                // Objects are maps, properties of different types make it a map of unknowns.
+                let types: Vec<Type> = properties
+                    .iter()
+                    .map(|property| self.expression(&property.value))
+                    .collect();
+                let element = match types.split_first() {
+                    Some((first, rest)) if rest.iter().all(|ty| ty == first) => first.clone(),
+                    _ => Type::Unknown,
+                };
+                Type::Map(Box::new(element))
+            }
+            ExpressionKind::Array(elements) => {
+                Type::Array(Box::new(self.elements(elements.iter())))
+            }
+            ExpressionKind::Invocation(invocation) => self.invocation(expression, invocation),
+            ExpressionKind::Member(object, name) => {
+                let object = self.expression(object);
+                match &object {
+                    Type::Class(_) | Type::Constructor(_) | Type::Unknown => {}
+                    Type::Map(_) => self.error(TypeErrorKind::Unsupported("members of maps"), name.span),
+                    ty => self.error(TypeErrorKind::NoMembers(ty.clone()), name.span),
+                }
+                self.member(&object, &name.name)
+            }
+            ExpressionKind::Index(object, index) => {
+                let object = self.expression(object);
+                let index_type = self.expression(index);
+
+                match object {
+                    Type::Array(element) => {
+                        self.expect(&Type::I32, &index_type, index.span);
+                        *element
+                    }
+                    Type::Map(element) => {
+                        self.expect(&Type::String, &index_type, index.span);
+                        self.error(TypeErrorKind::Unsupported("indexing maps"), expression.span);
+                        *element
+                    }
+                    Type::String => {
+                        self.expect(&Type::I32, &index_type, index.span);
+                        self.error(TypeErrorKind::Unsupported("indexing strings"), expression.span);
+                        Type::String
+                    }
+                    Type::Unknown => Type::Unknown,
+                    ty => {
+                        self.error(TypeErrorKind::NotIndexable(ty), expression.span);
+                        Type::Unknown
+                    }
+                }
+            }
+            ExpressionKind::Unary(op, operand) => {
+                let ty = self.expression(operand);
+                let result = match (op, &ty) {
+                    (Operator::Not(_), Type::Bool) | (Operator::Not(_), Type::Unknown) => Some(Type::Bool),
+                    (Operator::Subtract(_), Type::I32)
+                    | (Operator::Subtract(_), Type::F32)
+                    | (Operator::Subtract(_), Type::Unknown) => Some(ty.clone()),
+                    _ => None,
+                };
+
+                result.unwrap_or_else(|| {
+                    let kind = TypeErrorKind::InvalidOperand {
+                        op: op.clone(),
+                        operand: ty,
+                    };
+                    self.error(kind, expression.span);
+                    Type::Unknown
+                })
+            }
+            ExpressionKind::Operation(lhs, op, rhs) => {
+                let lhs = self.expression(lhs);
+                let rhs = self.expression(rhs);
+                self.operation(op, lhs, rhs, expression.span)
+            }
+            ExpressionKind::Assignment(target, op, value) => {
+                let expected = self.expression(target);
+                let found = self.expression(value);
+                match op {
+                    Operator::Assign(_) => self.expect(&expected, &found, value.span),
+                    _ => {
+                        let result = self.operation(op, expected.clone(), found, expression.span);
+                        self.expect(&expected, &result, expression.span);
+                    }
+                }
+                expected
+            }
+            ExpressionKind::Lambda(lambda) => self.function(
+                expression.id,
+                &lambda.parameters,
+                &lambda.return_type,
+                &lambda.body,
+                None,
+            ),
+        }
+    }
+
+    fn invocation(&mut self, expression: &Expression, invocation: &FunctionInvocation) -> Type {
+        let callee = self.expression(&invocation.func);
+        let arguments: Vec<Type> = invocation
+            .parameters
+            .iter()
+            .map(|argument| self.expression(argument))
+            .collect();
+
+        let (parameters, result) = match callee {
+            Type::Function(parameters, result) => (parameters, *result),
+            // Calling a class creates an instance.
+            Type::Constructor(name) => (Vec::new(), Type::Class(name)),
+            Type::Unknown => return Type::Unknown,
+            ty => {
+                self.error(TypeErrorKind::NotCallable(ty), invocation.func.span);
+                return Type::Unknown;
+            }
+        };
+
+        if parameters.len() != arguments.len() {
+            let kind = TypeErrorKind::ArgumentCount {
+                expected: parameters.len(),
+                found: arguments.len(),
+            };
+            self.error(kind, expression.span);
+        } else {
+            for ((expected, found), argument) in parameters
+                .iter()
+                .zip(arguments.iter())
+                .zip(invocation.parameters.iter())
+            {
+                self.expect(expected, found, argument.span);
+            }
+        }
+
+        result
+    }
+
+    /// The type of `object.name`, `Unknown` for members the checker doesn't know.
+    fn member(&self, object: &Type, name: &str) -> Type {
+        let found = match object {
+            Type::Class(class) => self.classes.get(class).and_then(|info| {
+                info.properties
+                    .get(name)
+                    .or_else(|| info.methods.get(name))
+                    .or_else(|| info.statics.get(name))
+            }),
+            Type::Constructor(class) => self.classes.get(class).and_then(|info| info.statics.get(name)),
+            Type::Map(element) => Some(element.as_ref()),
+            _ => None,
+        };
+
+        found.cloned().unwrap_or(Type::Unknown)
+    }
+
+    /// The result of a binary operator, compound assignments are checked like the
+    /// operator they apply.
+    fn operation(&mut self, op: &Operator, lhs: Type, rhs: Type, span: Span) -> Type {
+        let is_numeric = |ty: &Type| matches!(ty, Type::I32 | Type::F32);
+
+        // An unknown operand takes the type of the other one.
+        let (l, r) = match (&lhs, &rhs) {
+            (Type::Unknown, ty) | (ty, Type::Unknown) => (ty.clone(), ty.clone()),
+            (l, r) => (l.clone(), r.clone()),
+        };
+
+        let result = match op {
+            Operator::Add(_) | Operator::AddAssign(_) => match (&l, &r) {
+                (Type::Unknown, _) => Some(Type::Unknown),
+                (l, r) if l == r && (is_numeric(l) || *l == Type::String) => Some(l.clone()),
+                _ => None,
+            },
+            Operator::Subtract(_)
+            | Operator::SubtractAssign(_)
+            | Operator::Multiply(_)
+            | Operator::MultiplyAssign(_)
+            | Operator::Divide(_)
+            | Operator::DivideAssign(_) => match (&l, &r) {
+                (Type::Unknown, _) => Some(Type::Unknown),
+                (l, r) if l == r && is_numeric(l) => Some(l.clone()),
+                _ => None,
+            },
+            Operator::Equal(_) | Operator::NotEqual(_) => {
+                if lhs.accepts(&rhs) || rhs.accepts(&lhs) {
+                    Some(Type::Bool)
+                } else {
+                    None
+                }
+            }
+            Operator::Less(_)
+            | Operator::LessEqual(_)
+            | Operator::Greater(_)
+            | Operator::GreaterEqual(_) => match (&l, &r) {
+                (Type::Unknown, _) => Some(Type::Bool),
+                (l, r) if l == r && (is_numeric(l) || *l == Type::String) => Some(Type::Bool),
+                _ => None,
+            },
+            Operator::In(_) => match &rhs {
+                Type::Map(_) | Type::Class(_) => {
+                    Type::String.accepts(&lhs).then_some(Type::Bool)
+                }
+                Type::Array(element) => element.accepts(&lhs).then_some(Type::Bool),
+                Type::Unknown => Some(Type::Bool),
+                _ => None,
+            },
+            Operator::And(_) | Operator::Or(_) => {
+                if Type::Bool.accepts(&lhs) && Type::Bool.accepts(&rhs) {
+                    Some(Type::Bool)
+                } else {
+                    None
+                }
+            }
+            _ => Some(Type::Unknown),
+        };
+
+        result.unwrap_or_else(|| {
+            let kind = TypeErrorKind::InvalidOperands {
+                op: op.clone(),
+                lhs,
+                rhs,
+            };
+            self.error(kind, span);
+            Type::Unknown
+        })
+    }
+}
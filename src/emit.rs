@@ -1,6 +1,21 @@
-use webassembly;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+
+use crate::ir::{self, BinaryOp, Operand, UnaryOp, ValType};
 use webassembly::TypeWasmExt;
 
+pub mod builder;
+pub mod decode;
+pub mod instruction;
+pub mod wat;
+
+use builder::{FuncIndex, GlobalIndex, ModuleBuilder};
+use instruction::{BlockType, Instruction, MemArg};
+
+/// Added to the spec after the `webassembly` crate's constants were.
+const SECTION_DATA_COUNT: u8 = 12;
+
 trait Encoded {
     fn encode(&self) -> Vec<u8>;
 }
@@ -50,13 +65,8 @@ struct EncodedSection {
 
 impl Encoded for EncodedSection {
     fn encode(&self) -> Vec<u8> {
-        println!("Encoding section: {}", self.section_type);
-
         let content_size = self.section_content.len();
 
-        println!("Section Size: {}", content_size);
-        println!("Section Content: {:?}", self.section_content);
-
         let mut section: Vec<u8> = Vec::new();
         section.push(self.section_type);
         section.extend_from_slice(&content_size.to_wasm_bytes());
@@ -68,7 +78,7 @@ impl Encoded for EncodedSection {
 #[derive(Clone)]
 struct CodeBody {
     locals: Vec<CodeLocal>,
-    instructions: Vec<u8>,
+    instructions: Vec<Instruction>,
 }
 
 impl Encoded for CodeBody {
@@ -82,7 +92,9 @@ impl Encoded for CodeBody {
         };
 
         content.extend_from_slice(&locals.encode());
-        content.extend_from_slice(self.instructions.as_ref());
+        for instruction in self.instructions.iter() {
+            content.extend_from_slice(&instruction.encode());
+        }
         content.push(webassembly::END);
 
         encoded.extend_from_slice(&content.len().to_wasm_bytes());
@@ -98,6 +110,22 @@ struct CodeLocal {
     local_type: u8,
 }
 
+impl CodeLocal {
+    /// Declares locals of the given types in order, runs of the same wasm type share a declaration.
+    fn declare(types: &[ValType]) -> Vec<CodeLocal> {
+        let mut locals: Vec<CodeLocal> = Vec::new();
+
+        for local_type in types.iter().copied().map(encode_val_type) {
+            match locals.last_mut() {
+                Some(last) if last.local_type == local_type => last.count += 1,
+                _ => locals.push(CodeLocal { count: 1, local_type }),
+            }
+        }
+
+        locals
+    }
+}
+
 impl Encoded for CodeLocal {
     fn encode(&self) -> Vec<u8> {
         let mut encoded: Vec<u8> = Vec::new();
@@ -109,100 +137,241 @@ impl Encoded for CodeLocal {
     }
 }
 
+/// A module whose sections don't agree with each other, which wasm engines
+/// would reject.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EmitError {
+    /// The function section declares a different number of functions than the
+    /// code section has bodies for.
+    FunctionCount { functions: usize, bodies: usize },
+    /// The data count section disagrees with the data section.
+    DataCount { declared: u32, segments: usize },
+    /// A function refers to a signature the type section doesn't have.
+    UnknownType(u32),
+    DuplicateExport(String),
+    /// A function of the program named like an export the emitter adds itself,
+    /// the entry point or the memory.
+    ReservedExport(String),
+    /// A custom section is placed after a section id the spec doesn't define.
+    UnknownPlacement { name: String, after: u8 },
+    /// An import added after a definition of its kind, which it would have
+    /// shifted the index of.
+    LateImport(String),
+    /// A function declared but never given a body.
+    Undefined(u32),
+    /// A function given a body twice, or an imported one given a body.
+    Redefined(u32),
+}
+
+impl fmt::Display for EmitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmitError::FunctionCount { functions, bodies } => write!(
+                f,
+                "the module declares {} functions but has {} function bodies",
+                functions, bodies
+            ),
+            EmitError::DataCount { declared, segments } => write!(
+                f,
+                "the data count section declares {} segments but the data section has {}",
+                declared, segments
+            ),
+            EmitError::UnknownType(index) => write!(f, "function type {} is not defined", index),
+            EmitError::DuplicateExport(name) => write!(f, "`{}` is exported more than once", name),
+            EmitError::ReservedExport(name) => write!(
+                f,
+                "function `{}` can't be exported, the module exports its entry point or memory under that name",
+                name
+            ),
+            EmitError::UnknownPlacement { name, after } => {
+                write!(f, "custom section `{}` is placed after unknown section {}", name, after)
+            }
+            EmitError::LateImport(name) => {
+                write!(f, "`{}` is imported after the module's own definitions", name)
+            }
+            EmitError::Undefined(index) => write!(f, "function {} is declared but has no body", index),
+            EmitError::Redefined(index) => write!(f, "function {} is given a body twice", index),
+        }
+    }
+}
+
+impl Error for EmitError {}
+
+/// Where a custom section goes relative to the module's other sections.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Placement {
+    /// Right after the header, before the type section.
+    First,
+    /// After the section with this id, or where it would be if the module has none.
+    After(u8),
+    Last,
+}
+
+/// A section engines ignore, for tools or the host to read.
+#[derive(Clone, Debug)]
+pub struct CustomSection {
+    pub name: String,
+    pub content: Vec<u8>,
+    pub placement: Placement,
+}
+
+impl Encoded for CustomSection {
+    fn encode(&self) -> Vec<u8> {
+        let mut content = EncodedString { val: self.name.clone() }.encode();
+        content.extend_from_slice(&self.content);
+
+        EncodedSection {
+            section_type: webassembly::SECTION_CUSTOM,
+            section_content: content,
+        }.encode()
+    }
+}
+
+#[derive(Default)]
 struct EncodedModule {
-    custom_section: Option<EncodedSection>,
+    custom_sections: Vec<CustomSection>,
     type_section: Option<TypeSection>,
-    import_section: Option<EncodedSection>,
+    import_section: Option<ImportSection>,
     function_section: Option<FuncSection>,
-    table_section: Option<EncodedSection>,
-    memory_section: Option<MemSection>,
-    global_section: Option<EncodedSection>,
+    table_section: Option<TableSection>,
+    memory_section: Option<MemorySection>,
+    global_section: Option<GlobalSection>,
     export_section: Option<ExportSection>,
-    start_section: Option<EncodedSection>,
-    element_section: Option<EncodedSection>,
+    start_section: Option<StartSection>,
+    element_section: Option<ElementSection>,
     code_section: Option<CodeSection>,
-    data_section: Option<EncodedSection>,
-    data_count_section: Option<EncodedSection>
+    data_section: Option<DataSection>,
+    data_count_section: Option<DataCountSection>
 }
 
-impl Encoded for EncodedModule {
-    fn encode(&self) -> Vec<u8> {
-        let mut content: Vec<u8> = Vec::new();
+impl EncodedModule {
+    /// The id and encoding of every section the module has, in the order the
+    /// spec requires. The data count section comes before the code section
+    /// although its id is higher.
+    fn sections(&self) -> Vec<(u8, Option<Vec<u8>>)> {
+        fn encode<E: Encoded>(section: &Option<E>) -> Option<Vec<u8>> {
+            section.as_ref().map(Encoded::encode)
+        }
 
-        content.extend_from_slice(webassembly::MAGIC_NUMBER);
-        content.extend_from_slice(webassembly::VERSION_1);
+        vec![
+            (webassembly::SECTION_TYPE, encode(&self.type_section)),
+            (webassembly::SECTION_IMPORT, encode(&self.import_section)),
+            (webassembly::SECTION_FUNCTION, encode(&self.function_section)),
+            (webassembly::SECTION_TABLE, encode(&self.table_section)),
+            (webassembly::SECTION_MEMORY, encode(&self.memory_section)),
+            (webassembly::SECTION_GLOBAL, encode(&self.global_section)),
+            (webassembly::SECTION_EXPORT, encode(&self.export_section)),
+            (webassembly::SECTION_START, encode(&self.start_section)),
+            (webassembly::SECTION_ELEMENT, encode(&self.element_section)),
+            (SECTION_DATA_COUNT, encode(&self.data_count_section)),
+            (webassembly::SECTION_CODE, encode(&self.code_section)),
+            (webassembly::SECTION_DATA, encode(&self.data_section)),
+        ]
+    }
 
-        if self.type_section.is_some() {
-            content.extend_from_slice(
-                &self.type_section.as_ref().unwrap().encode()
-            );
+    /// Checks that the sections agree with each other.
+    fn check(&self) -> Result<(), EmitError> {
+        let functions = self.function_section.as_ref().map_or(0, |section| section.signature_ids.len());
+        let bodies = self.code_section.as_ref().map_or(0, |section| section.blocks.len());
+        if functions != bodies {
+            return Err(EmitError::FunctionCount { functions, bodies });
         }
 
-        if self.function_section.is_some() {
-            content.extend_from_slice(
-                &self.function_section.as_ref().unwrap().encode()
-            );
+        if let Some(section) = &self.data_count_section {
+            let segments = self.data_section.as_ref().map_or(0, |section| section.segments.len());
+            if section.count as usize != segments {
+                return Err(EmitError::DataCount {
+                    declared: section.count,
+                    segments,
+                });
+            }
         }
 
-        if self.memory_section.is_some() {
-            content.extend_from_slice(
-                &self.memory_section.as_ref().unwrap().encode()
-            );
+        let types = self.type_section.as_ref().map_or(0, |section| section.signatures.len() as u32);
+        let signature_ids = self.function_section.iter().flat_map(|section| section.signature_ids.iter());
+        let imported = self.import_section.iter().flat_map(|section| section.imports.iter());
+        let imported_ids = imported.filter_map(|import| match import.desc {
+            ImportDesc::Function(index) => Some(index),
+            _ => None,
+        });
+        if let Some(index) = signature_ids.copied().chain(imported_ids).find(|index| *index >= types) {
+            return Err(EmitError::UnknownType(index));
         }
 
-        if self.export_section.is_some() {
-            content.extend_from_slice(
-                &self.export_section.as_ref().unwrap().encode()
-            );
+        if let Some(section) = &self.export_section {
+            for (i, export) in section.exports.iter().enumerate() {
+                if section.exports[..i].iter().any(|other| other.name.val == export.name.val) {
+                    return Err(EmitError::DuplicateExport(export.name.val.clone()));
+                }
+            }
         }
 
-        if self.code_section.is_some() {
-            content.extend_from_slice(
-                &self.code_section.as_ref().unwrap().encode()
-            );
+        let ids: Vec<u8> = self.sections().iter().map(|(id, _)| *id).collect();
+        for custom in self.custom_sections.iter() {
+            if let Placement::After(after) = custom.placement {
+                if !ids.contains(&after) {
+                    return Err(EmitError::UnknownPlacement {
+                        name: custom.name.clone(),
+                        after,
+                    });
+                }
+            }
         }
 
-        content
+        Ok(())
     }
 }
 
-impl Default for EncodedModule {
-    fn default() -> Self {
-        Self {
-            custom_section: None,
-            type_section: None,
-            import_section: None,
-            function_section: None,
-            table_section: None,
-            memory_section: None,
-            global_section: None,
-            export_section: None,
-            start_section: None,
-            element_section: None,
-            code_section: None,
-            data_section: None,
-            data_count_section: None
+impl Encoded for EncodedModule {
+    fn encode(&self) -> Vec<u8> {
+        let mut content: Vec<u8> = Vec::new();
+
+        content.extend_from_slice(webassembly::MAGIC_NUMBER);
+        content.extend_from_slice(webassembly::VERSION_1);
+
+        let custom = |content: &mut Vec<u8>, placement: Placement| {
+            for section in self.custom_sections.iter().filter(|section| section.placement == placement) {
+                content.extend_from_slice(&section.encode());
+            }
+        };
+
+        custom(&mut content, Placement::First);
+
+        for (id, section) in self.sections() {
+            if let Some(section) = section {
+                content.extend_from_slice(&section);
+            }
+            custom(&mut content, Placement::After(id));
         }
+
+        custom(&mut content, Placement::Last);
+
+        content
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 struct TypeSignature {
     type_sig: u8,
     inputs: Option<Vec<TypeSignature>>,
     outputs: Option<Vec<TypeSignature>>,
-    min: Option<u32>,
-    max: Option<u32>,
 }
 
-impl Default for TypeSignature {
-    fn default() -> Self {
-        Self {
-            type_sig: 0,
-            inputs: None,
-            outputs: None,
-            min: None,
-            max: None,
+impl TypeSignature {
+    fn value(type_sig: u8) -> Self {
+        TypeSignature {
+            type_sig,
+            .. TypeSignature::default()
+        }
+    }
+
+    fn signature(signature: &ir::Signature) -> Self {
+        let value = |ty: &ValType| TypeSignature::value(encode_val_type(*ty));
+
+        TypeSignature {
+            type_sig: webassembly::FUNC,
+            inputs: Some(signature.parameters.iter().map(value).collect()),
+            outputs: Some(signature.result.iter().map(value).collect()),
         }
     }
 }
@@ -232,15 +401,6 @@ impl Encoded for TypeSignature {
                 
                 content
             },
-            webassembly::LIMIT_MIN_MAX => {
-                let min: u32 = self.min.unwrap();
-                let max: u32 = self.max.unwrap();
-
-                content.extend_from_slice(&min.to_wasm_bytes());
-                content.extend_from_slice(&max.to_wasm_bytes());
-
-                content
-            },
             _ => {
                 content
             }
@@ -284,21 +444,319 @@ impl Encoded for FuncSection {
     }
 }
 
-struct MemSection {
-    blocks: Vec<TypeSignature>,
+struct TableSection {
+    tables: Vec<Limits>,
 }
 
-impl Encoded for MemSection {
+impl Encoded for TableSection {
     fn encode(&self) -> Vec<u8> {
-        let blocks = self.blocks.to_vec();
+        let mut content: Vec<u8> = Vec::new();
+        content.extend_from_slice(&self.tables.len().to_wasm_bytes());
+        for limits in self.tables.iter() {
+            content.push(webassembly::ANYFUNC);
+            content.extend_from_slice(&limits.encode());
+        }
+
+        EncodedSection {
+            section_type: webassembly::SECTION_TABLE,
+            section_content: content,
+        }.encode()
+    }
+}
+
+struct MemorySection {
+    memories: Vec<Limits>,
+}
 
+impl Encoded for MemorySection {
+    fn encode(&self) -> Vec<u8> {
         EncodedSection {
             section_type: webassembly::SECTION_MEMORY,
             section_content: EncodedVec {
-                vector: blocks
+                vector: self.memories.to_vec()
+            }.encode()
+        }.encode()
+    }
+}
+
+/// The size of a memory in pages or of a table in elements.
+#[derive(Clone, Copy)]
+struct Limits {
+    min: u32,
+    max: Option<u32>,
+}
+
+impl Encoded for Limits {
+    fn encode(&self) -> Vec<u8> {
+        let mut encoded: Vec<u8> = Vec::new();
+
+        match self.max {
+            Some(max) => {
+                encoded.push(webassembly::LIMIT_MIN_MAX);
+                encoded.extend_from_slice(&self.min.to_wasm_bytes());
+                encoded.extend_from_slice(&max.to_wasm_bytes());
+            }
+            None => {
+                encoded.push(webassembly::LIMIT_MIN);
+                encoded.extend_from_slice(&self.min.to_wasm_bytes());
+            }
+        }
+
+        encoded
+    }
+}
+
+/// What an import provides, with the type the host's definition must have.
+#[derive(Clone)]
+enum ImportDesc {
+    /// A function with the signature at this index of the type section.
+    Function(u32),
+    /// A `funcref` table.
+    Table(Limits),
+    Memory(Limits),
+    Global { value_type: u8, mutable: bool },
+}
+
+impl ImportDesc {
+    fn kind(&self) -> u8 {
+        match self {
+            ImportDesc::Function(_) => webassembly::DESC_FUNCTION,
+            ImportDesc::Table(_) => webassembly::DESC_TABLE,
+            ImportDesc::Memory(_) => webassembly::DESC_MEMORY,
+            ImportDesc::Global { .. } => webassembly::DESC_GLOBAL,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ImportEntry {
+    module: EncodedString,
+    name: EncodedString,
+    desc: ImportDesc,
+}
+
+impl Encoded for ImportEntry {
+    fn encode(&self) -> Vec<u8> {
+        let mut encoded: Vec<u8> = Vec::new();
+
+        encoded.extend_from_slice(&self.module.encode());
+        encoded.extend_from_slice(&self.name.encode());
+        encoded.push(self.desc.kind());
+
+        match &self.desc {
+            ImportDesc::Function(type_index) => {
+                encoded.extend_from_slice(&type_index.to_wasm_bytes());
+            }
+            ImportDesc::Table(limits) => {
+                encoded.push(webassembly::ANYFUNC);
+                encoded.extend_from_slice(&limits.encode());
+            }
+            ImportDesc::Memory(limits) => {
+                encoded.extend_from_slice(&limits.encode());
+            }
+            ImportDesc::Global { value_type, mutable } => {
+                encoded.push(*value_type);
+                encoded.push(*mutable as u8);
+            }
+        }
+
+        encoded
+    }
+}
+
+/// The entities a module takes from the host. Imports of each kind come before
+/// the module's own definitions in that kind's index space.
+#[derive(Default)]
+struct ImportSection {
+    imports: Vec<ImportEntry>,
+}
+
+impl ImportSection {
+    fn import(&mut self, module: &str, name: &str, desc: ImportDesc) -> &mut Self {
+        self.imports.push(ImportEntry {
+            module: EncodedString { val: module.to_string() },
+            name: EncodedString { val: name.to_string() },
+            desc,
+        });
+        self
+    }
+
+    /// The number of imports of a kind, which offsets the indices of the module's
+    /// own definitions of that kind.
+    fn count(&self, kind: u8) -> u32 {
+        self.imports.iter().filter(|import| import.desc.kind() == kind).count() as u32
+    }
+}
+
+impl Encoded for ImportSection {
+    fn encode(&self) -> Vec<u8> {
+        EncodedSection {
+            section_type: webassembly::SECTION_IMPORT,
+            section_content: EncodedVec {
+                vector: self.imports.to_vec()
+            }.encode()
+        }.encode()
+    }
+}
+
+#[derive(Clone)]
+struct GlobalEntry {
+    value_type: u8,
+    mutable: bool,
+    /// The constant expression computing the initial value, without its `end`.
+    init: Instruction,
+}
+
+impl Encoded for GlobalEntry {
+    fn encode(&self) -> Vec<u8> {
+        let mut encoded: Vec<u8> = Vec::new();
+
+        encoded.push(self.value_type);
+        encoded.push(self.mutable as u8);
+        encoded.extend_from_slice(&self.init.encode());
+        encoded.push(webassembly::END);
+
+        encoded
+    }
+}
+
+/// The module's own globals, which are numbered after the imported ones.
+#[derive(Default)]
+struct GlobalSection {
+    globals: Vec<GlobalEntry>,
+}
+
+impl Encoded for GlobalSection {
+    fn encode(&self) -> Vec<u8> {
+        EncodedSection {
+            section_type: webassembly::SECTION_GLOBAL,
+            section_content: EncodedVec {
+                vector: self.globals.to_vec()
+            }.encode()
+        }.encode()
+    }
+}
+
+/// An active segment, copied into memory 0 at `offset` when the module is instantiated.
+#[derive(Clone)]
+struct DataSegment {
+    offset: u32,
+    bytes: Vec<u8>,
+}
+
+impl Encoded for DataSegment {
+    fn encode(&self) -> Vec<u8> {
+        let mut encoded: Vec<u8> = Vec::new();
+
+        encoded.push(0x00);
+        encoded.extend_from_slice(&Instruction::I32Const(self.offset as i32).encode());
+        encoded.push(webassembly::END);
+        encoded.extend_from_slice(&self.bytes.len().to_wasm_bytes());
+        encoded.extend_from_slice(&self.bytes);
+
+        encoded
+    }
+}
+
+#[derive(Default)]
+struct DataSection {
+    segments: Vec<DataSegment>,
+}
+
+impl DataSection {
+    /// The data count section, which lets validation check data indices before
+    /// the data section is reached.
+    fn count(&self) -> DataCountSection {
+        DataCountSection {
+            count: self.segments.len() as u32,
+        }
+    }
+}
+
+struct DataCountSection {
+    count: u32,
+}
+
+impl Encoded for DataCountSection {
+    fn encode(&self) -> Vec<u8> {
+        EncodedSection {
+            section_type: SECTION_DATA_COUNT,
+            section_content: self.count.to_wasm_bytes(),
+        }.encode()
+    }
+}
+
+impl Encoded for DataSection {
+    fn encode(&self) -> Vec<u8> {
+        EncodedSection {
+            section_type: webassembly::SECTION_DATA,
+            section_content: EncodedVec {
+                vector: self.segments.to_vec()
+            }.encode()
+        }.encode()
+    }
+}
+
+struct StartSection {
+    function: u32,
+}
+
+impl Encoded for StartSection {
+    fn encode(&self) -> Vec<u8> {
+        EncodedSection {
+            section_type: webassembly::SECTION_START,
+            section_content: self.function.to_wasm_bytes(),
+        }.encode()
+    }
+}
+
+/// An active segment, copied into a table at `offset` when the module is instantiated.
+#[derive(Clone)]
+struct ElementSegment {
+    table: u32,
+    offset: u32,
+    functions: Vec<u32>,
+}
+
+impl Encoded for ElementSegment {
+    fn encode(&self) -> Vec<u8> {
+        let mut encoded: Vec<u8> = Vec::new();
+
+        // Segments for other tables than 0 name theirs, and the kind of their elements.
+        if self.table == 0 {
+            encoded.push(0x00);
+        } else {
+            encoded.push(0x02);
+            encoded.extend_from_slice(&self.table.to_wasm_bytes());
+        }
+        encoded.extend_from_slice(&Instruction::I32Const(self.offset as i32).encode());
+        encoded.push(webassembly::END);
+        if self.table != 0 {
+            encoded.push(0x00);
+        }
+
+        encoded.extend_from_slice(&self.functions.len().to_wasm_bytes());
+        for function in self.functions.iter() {
+            encoded.extend_from_slice(&function.to_wasm_bytes());
+        }
+
+        encoded
+    }
+}
+
+struct ElementSection {
+    segments: Vec<ElementSegment>,
+}
+
+impl Encoded for ElementSection {
+    fn encode(&self) -> Vec<u8> {
+        EncodedSection {
+            section_type: webassembly::SECTION_ELEMENT,
+            section_content: EncodedVec {
+                vector: self.segments.to_vec()
             }.encode()
         }.encode()
-    } 
+    }
 }
 
 #[derive(Clone)]
@@ -350,121 +808,482 @@ impl Encoded for CodeSection {
     }
 }
 
-pub struct ModuleEmitter;
+/// The instruction pushing a constant operand, `None` for a local.
+fn encode_operand(operand: &Operand) -> Option<Instruction> {
+    match operand {
+        Operand::Local(_) => None,
+        Operand::I32(n) => Some(Instruction::I32Const(*n)),
+        Operand::F32(x) => Some(Instruction::F32Const(*x)),
+    }
+}
+
+/// What the entities of a module are called in the source, by index, for the
+/// text format.
+#[derive(Default)]
+struct Names {
+    functions: HashMap<u32, String>,
+    /// The names of the locals of each function, parameters included.
+    locals: HashMap<u32, BTreeMap<u32, String>>,
+    globals: HashMap<u32, String>,
+}
 
-impl ModuleEmitter {
-    pub fn emit(&self) -> Vec<u8> {
-        let mut module = EncodedModule::default();
-        // For right now we're just gonna build a simple module that exports a add function that accepts 2 f32's and returns 1 f32
-        
-        // Lets build the func section.
-        let func_section = FuncSection {
-            // The signature id is a vector full of pointers to a type signature, representing the signature of the function at that index.
-            signature_ids: vec![0] // Our add function will be the only function, so just pick the first signature.
+/// Where the entities IR functions refer to are in the wasm module.
+struct Indices {
+    imports: Vec<FuncIndex>,
+    functions: Vec<FuncIndex>,
+    globals: Vec<GlobalIndex>,
+    /// The global holding the address of the next free byte of memory.
+    heap_pointer: GlobalIndex,
+}
+
+fn encode_val_type(ty: ValType) -> u8 {
+    match ty {
+        ValType::I32 => webassembly::I32,
+        ValType::F32 => webassembly::F32,
+    }
+}
+
+/// The text format name of an encoded value type.
+fn value_type(code: u8) -> &'static str {
+    match code {
+        webassembly::I32 => "i32",
+        webassembly::I64 => "i64",
+        webassembly::F32 => "f32",
+        webassembly::F64 => "f64",
+        _ => "unknown",
+    }
+}
+
+/// The enclosing wasm blocks a branch can target.
+#[derive(Clone, Copy, PartialEq)]
+enum Label {
+    If,
+    /// A `block` wrapping a `loop`, `break` targets the block and `continue` the loop.
+    Loop,
+}
+
+/// Lowers the instructions of IR function bodies.
+struct BodyEncoder<'b> {
+    builder: &'b mut ModuleBuilder,
+    indices: &'b Indices,
+    labels: Vec<Label>,
+    encoded: Vec<Instruction>,
+}
+
+impl<'b> BodyEncoder<'b> {
+    fn push(&mut self, instruction: Instruction) {
+        self.encoded.push(instruction);
+    }
+
+    fn operand(&mut self, operand: &Operand) {
+        match operand {
+            Operand::Local(local) => self.push(Instruction::LocalGet(local.0)),
+            Operand::I32(n) => self.push(Instruction::I32Const(*n)),
+            Operand::F32(x) => self.push(Instruction::F32Const(*x)),
+        }
+    }
+
+    fn set(&mut self, local: ir::Local) {
+        self.push(Instruction::LocalSet(local.0));
+    }
+
+    /// The relative depth of the innermost loop's `block`, or the `loop` itself for `continue`.
+    fn branch(&mut self, to_loop: bool) {
+        let depth = match self.labels.iter().rposition(|label| *label == Label::Loop) {
+            Some(position) => self.labels.len() - 1 - position,
+            None => return,
         };
 
-        module.function_section = Some(func_section);
-
-        // Lets build the type section.
-        let type_section = TypeSection {
-            signatures: vec![
-                TypeSignature {
-                    type_sig: webassembly::FUNC,
-                    inputs: Some(vec![
-                        TypeSignature {
-                            type_sig: webassembly::F32,
-                            .. TypeSignature::default()
-                        },
-                        TypeSignature {
-                            type_sig: webassembly::F32,
-                            .. TypeSignature::default()
-                        },
-                    ]),
-                    outputs: Some(vec![
-                        TypeSignature {
-                            type_sig: webassembly::F32,
-                            .. TypeSignature::default()
-                        }
-                    ]),
-                    .. TypeSignature::default()
+        let depth = if to_loop { depth } else { depth + 1 };
+        self.push(Instruction::Br(depth as u32));
+    }
+
+    fn instructions(&mut self, instructions: &[ir::Instruction]) {
+        for instruction in instructions.iter() {
+            self.instruction(instruction);
+        }
+    }
+
+    fn instruction(&mut self, instruction: &ir::Instruction) {
+        match instruction {
+            ir::Instruction::Copy { dest, value } => {
+                self.operand(value);
+                self.set(*dest);
+            }
+            ir::Instruction::Unary { dest, op, ty, operand } => {
+                match (op, ty) {
+                    (UnaryOp::Neg, ValType::F32) => {
+                        self.operand(operand);
+                        self.push(Instruction::F32Neg);
+                    }
+                    // There is no i32.neg, so compute 0 - operand.
+                    (UnaryOp::Neg, ValType::I32) => {
+                        self.push(Instruction::I32Const(0));
+                        self.operand(operand);
+                        self.push(Instruction::I32Sub);
+                    }
+                    (UnaryOp::Eqz, _) => {
+                        self.operand(operand);
+                        self.push(Instruction::I32Eqz);
+                    }
+                }
+                self.set(*dest);
+            }
+            ir::Instruction::Binary { dest, op, ty, lhs, rhs } => {
+                self.operand(lhs);
+                self.operand(rhs);
+                self.push(binary_instruction(*op, *ty));
+                self.set(*dest);
+            }
+            ir::Instruction::Load { dest, ty, address, offset } => {
+                self.operand(address);
+                let memarg = MemArg { align: 2, offset: *offset };
+                self.push(match ty {
+                    ValType::I32 => Instruction::I32Load(memarg),
+                    ValType::F32 => Instruction::F32Load(memarg),
+                });
+                self.set(*dest);
+            }
+            ir::Instruction::Store { ty, address, offset, value } => {
+                self.operand(address);
+                self.operand(value);
+                let memarg = MemArg { align: 2, offset: *offset };
+                self.push(match ty {
+                    ValType::I32 => Instruction::I32Store(memarg),
+                    ValType::F32 => Instruction::F32Store(memarg),
+                });
+            }
+            ir::Instruction::GlobalGet { dest, global } => {
+                let index = self.indices.globals[global.0 as usize];
+                self.push(Instruction::GlobalGet(index.index()));
+                self.set(*dest);
+            }
+            ir::Instruction::GlobalSet { global, value } => {
+                self.operand(value);
+                let index = self.indices.globals[global.0 as usize];
+                self.push(Instruction::GlobalSet(index.index()));
+            }
+            ir::Instruction::Drop(value) => {
+                self.operand(value);
+                self.push(Instruction::Drop);
+            }
+            ir::Instruction::Alloc { dest, size } => {
+                // A bump allocator, memory is never freed.
+                let heap_pointer = self.indices.heap_pointer.index();
+                self.push(Instruction::GlobalGet(heap_pointer));
+                self.push(Instruction::LocalTee(dest.0));
+                self.push(Instruction::I32Const(*size as i32));
+                self.push(Instruction::I32Add);
+                self.push(Instruction::GlobalSet(heap_pointer));
+            }
+            ir::Instruction::Call { dest, callee, arguments } => {
+                for argument in arguments.iter() {
+                    self.operand(argument);
                 }
-            ]
-        };
 
-        module.type_section = Some(type_section);
+                match callee {
+                    ir::Callee::Function(id) => {
+                        let index = self.indices.functions[id.0 as usize];
+                        self.push(Instruction::Call(index.index()));
+                    }
+                    ir::Callee::Import(id) => {
+                        let index = self.indices.imports[id.0 as usize];
+                        self.push(Instruction::Call(index.index()));
+                    }
+                    ir::Callee::Indirect { table_index, signature } => {
+                        self.operand(table_index);
+                        let type_index = self.builder.signature(signature).index();
+                        self.push(Instruction::CallIndirect { type_index, table: 0 });
+                    }
+                }
 
-        //Lets build ourselves a memory section.
-        let mem_section = MemSection {
-            blocks: vec![
-                TypeSignature {
-                    type_sig: webassembly::LIMIT_MIN_MAX,
-                    min: Some(2), // Allocate between 2 Ki and 5 Ki
-                    max: Some(10),
-                    .. TypeSignature::default()
+                if let Some(dest) = dest {
+                    self.set(*dest);
                 }
-            ]
-        };
+            }
+            ir::Instruction::If { condition, then, otherwise } => {
+                self.operand(condition);
+                self.push(Instruction::If(BlockType::Empty));
+                self.labels.push(Label::If);
+                self.instructions(then);
+                if !otherwise.is_empty() {
+                    self.push(Instruction::Else);
+                    self.instructions(otherwise);
+                }
+                self.labels.pop();
+                self.push(Instruction::End);
+            }
+            ir::Instruction::Loop(body) => {
+                self.push(Instruction::Block(BlockType::Empty));
+                self.push(Instruction::Loop(BlockType::Empty));
+                self.labels.push(Label::Loop);
+                self.instructions(body);
+                // Falling off the end of the body starts the next iteration.
+                self.branch(true);
+                self.labels.pop();
+                self.push(Instruction::End);
+                self.push(Instruction::End);
+            }
+            ir::Instruction::Break => self.branch(false),
+            ir::Instruction::Continue => self.branch(true),
+            ir::Instruction::Return(value) => {
+                if let Some(value) = value {
+                    self.operand(value);
+                }
+                self.push(Instruction::Return);
+            }
+        }
+    }
+}
+
+/// Whether any function calls through the table.
+fn calls_indirectly(module: &ir::Module) -> bool {
+    fn any(instructions: &[ir::Instruction]) -> bool {
+        instructions.iter().any(|instruction| match instruction {
+            ir::Instruction::Call {
+                callee: ir::Callee::Indirect { .. },
+                ..
+            } => true,
+            ir::Instruction::If { then, otherwise, .. } => any(then) || any(otherwise),
+            ir::Instruction::Loop(body) => any(body),
+            _ => false,
+        })
+    }
+
+    module.functions.iter().any(|function| any(&function.body))
+}
+
+fn binary_instruction(op: BinaryOp, ty: ValType) -> Instruction {
+    match (ty, op) {
+        (ValType::I32, BinaryOp::Add) => Instruction::I32Add,
+        (ValType::I32, BinaryOp::Sub) => Instruction::I32Sub,
+        (ValType::I32, BinaryOp::Mul) => Instruction::I32Mul,
+        (ValType::I32, BinaryOp::Div) => Instruction::I32DivS,
+        (ValType::I32, BinaryOp::Eq) => Instruction::I32Eq,
+        (ValType::I32, BinaryOp::Ne) => Instruction::I32Ne,
+        (ValType::I32, BinaryOp::Lt) => Instruction::I32LtS,
+        (ValType::I32, BinaryOp::Le) => Instruction::I32LeS,
+        (ValType::I32, BinaryOp::Gt) => Instruction::I32GtS,
+        (ValType::I32, BinaryOp::Ge) => Instruction::I32GeS,
+        (ValType::F32, BinaryOp::Add) => Instruction::F32Add,
+        (ValType::F32, BinaryOp::Sub) => Instruction::F32Sub,
+        (ValType::F32, BinaryOp::Mul) => Instruction::F32Mul,
+        (ValType::F32, BinaryOp::Div) => Instruction::F32Div,
+        (ValType::F32, BinaryOp::Eq) => Instruction::F32Eq,
+        (ValType::F32, BinaryOp::Ne) => Instruction::F32Ne,
+        (ValType::F32, BinaryOp::Lt) => Instruction::F32Lt,
+        (ValType::F32, BinaryOp::Le) => Instruction::F32Le,
+        (ValType::F32, BinaryOp::Gt) => Instruction::F32Gt,
+        (ValType::F32, BinaryOp::Ge) => Instruction::F32Ge,
+    }
+}
+
+/// Lowers the body of an IR function.
+fn encode_function(function: &ir::Function, builder: &mut ModuleBuilder, indices: &Indices) -> Vec<Instruction> {
+    let mut encoder = BodyEncoder {
+        builder,
+        indices,
+        labels: Vec::new(),
+        encoded: Vec::new(),
+    };
+    encoder.instructions(&function.body);
+
+    // A function with a result has returned before reaching its end.
+    if function.signature.result.is_some() {
+        encoder.push(Instruction::Unreachable);
+    }
+
+    encoder.encoded
+}
+
+/// How the host runs the module level statements.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Entry {
+    /// Through the start section, as the module is instantiated. The host can't
+    /// read the module's memory from imports called this early.
+    Start,
+    /// Through an export the host calls once the module is instantiated, `_start`
+    /// for WASI commands or `main`.
+    Export(String),
+}
 
-        module.memory_section = Some(mem_section);
-
-
-        // Define our exports.
-
-        let export_section = ExportSection {
-            exports: vec![
-                ExportSignature {
-                    name: EncodedString {
-                        val: "memory".to_string()
-                    },
-                    sig_type: webassembly::DESC_MEMORY,
-                    index: 0
-                },
-                ExportSignature {
-                    name: EncodedString {
-                        val: "add".to_string()
-                    },
-                    sig_type: webassembly::DESC_FUNCTION,
-                    index: 0
+impl Default for Entry {
+    fn default() -> Self {
+        Entry::Export("_start".to_string())
+    }
+}
+
+#[derive(Default)]
+pub struct ModuleEmitter {
+    pub entry: Entry,
+    pub custom_sections: Vec<CustomSection>,
+}
+
+impl ModuleEmitter {
+    /// Encodes a lowered module as a wasm binary.
+    pub fn emit(&self, module: &ir::Module) -> Result<Vec<u8>, EmitError> {
+        self.build(module)?.finish()
+    }
+
+    /// Renders the module `emit` would encode in the text format.
+    pub fn wat(&self, module: &ir::Module, style: wat::Style) -> Result<String, EmitError> {
+        self.build(module)?.wat(style)
+    }
+
+    fn build(&self, module: &ir::Module) -> Result<ModuleBuilder, EmitError> {
+        let mut builder = ModuleBuilder::new();
+
+        // `from io import { print }` is the function `print` of the host module `io`.
+        let imports = module
+            .imports
+            .iter()
+            .map(|import| builder.import_function(&import.module, &import.name, &import.signature))
+            .collect();
+
+        // Declared up front so bodies can call functions defined after them.
+        let functions = module
+            .functions
+            .iter()
+            .map(|function| {
+                let index = builder.function(&function.signature);
+                builder.name_function(index, &function.name);
+                for (local, name) in function.local_names.iter() {
+                    builder.name_local(index, local.0, name);
                 }
-            ]
+                index
+            })
+            .collect();
+
+        let mut globals = Vec::new();
+        for global in module.globals.iter() {
+            let zero = match global.ty {
+                ValType::I32 => Instruction::I32Const(0),
+                ValType::F32 => Instruction::F32Const(0.0),
+            };
+            // Lowering only initializes globals with constants.
+            let init = encode_operand(&global.init).unwrap_or(zero);
+            let index = builder.global(global.ty, global.mutable, init);
+            builder.name_global(index, &global.name);
+            globals.push(index);
+        }
+
+        // The heap pointer comes last, memory after the string literals is free.
+        let heap_start = Instruction::I32Const(module.strings.end() as i32);
+        let heap_pointer = builder.global(ValType::I32, true, heap_start);
+        builder.name_global(heap_pointer, "heap_pointer");
+
+        let indices = Indices {
+            imports,
+            functions,
+            globals,
+            heap_pointer,
         };
 
-        module.export_section = Some(export_section);
-
-        let instructions = vec![
-            webassembly::LOCAL_GET, 0,
-            webassembly::LOCAL_GET, 1,
-            webassembly::F32_ADD
-        ];
-        //instructions.extend_from_slice(&6f32.to_wasm_bytes());
-
-        let code_section = CodeSection {
-            blocks: vec![
-                CodeBody {
-                    locals: vec![
-                        CodeLocal {
-                            count: 3,
-                            local_type: webassembly::F32
-                        }
-                    ],
-                    instructions
+        // Closures are called through table 0, which holds every lambda. A module
+        // without closures has no table.
+        if !module.table.is_empty() || calls_indirectly(module) {
+            let table_size = module.table.len() as u32;
+            let table = builder.table(table_size, Some(table_size));
+            let lambdas: Vec<FuncIndex> = module.table.iter().map(|id| indices.functions[id.0 as usize]).collect();
+            if !lambdas.is_empty() {
+                builder.elements(table, 0, &lambdas);
+            }
+        }
+
+        // Allocate between 2 and 10 pages.
+        let memory = builder.memory(2, Some(10));
+        builder.export_memory("memory", memory);
+
+        // A string is its length in bytes followed by the bytes.
+        for segment in module.strings.segments.iter() {
+            let mut bytes: Vec<u8> = Vec::new();
+            bytes.extend_from_slice(&(segment.value.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(segment.value.as_bytes());
+            builder.data(segment.address, bytes);
+        }
+
+        for (function, index) in module.functions.iter().zip(indices.functions.iter()) {
+            let body = encode_function(function, &mut builder, &indices);
+            builder.define(*index, function.declared_locals(), body);
+        }
+
+        match (&self.entry, module.init) {
+            (Entry::Start, Some(init)) => builder.start(indices.functions[init.0 as usize]),
+            (Entry::Export(name), Some(init)) => builder.export_function(name, indices.functions[init.0 as usize]),
+            (_, None) => {}
+        }
+
+        for (function, index) in module.functions.iter().zip(indices.functions.iter()) {
+            if function.export {
+                if builder.is_exported(&function.name) {
+                    return Err(EmitError::ReservedExport(function.name.clone()));
                 }
-            ]
-        };
+                builder.export_function(&function.name, *index);
+            }
+        }
 
-        module.code_section = Some(code_section);
+        for section in self.custom_sections.iter() {
+            builder.custom(section.clone());
+        }
 
-        // Lets build the code section. This is the actual implmentation of our add function.
-        // We take 2 f32's and return 1, so our locals can just be 3 f32
-        /*let locals = self.encode_local(webassembly::F32, 3);
-        let instructions: Vec<u8> = Vec::new();
-        let add_func = self.encode_function(vec![locals], instructions);
+        Ok(builder)
+    }
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let func_vec = self.encode_vector(add_func.encode());
-        let code_section = self.encode_section(webassembly::SECTION_CODE, func_vec);
-        module.code_section = Some(code_section);*/
+    fn body() -> CodeBody {
+        CodeBody {
+            locals: Vec::new(),
+            instructions: Vec::new(),
+        }
+    }
 
-        module.encode()
+    fn void() -> TypeSection {
+        TypeSection {
+            signatures: vec![TypeSignature::signature(&ir::Signature {
+                parameters: Vec::new(),
+                result: None,
+            })],
+        }
+    }
+
+    #[test]
+    fn functions_without_bodies_are_rejected() {
+        let module = EncodedModule {
+            type_section: Some(void()),
+            function_section: Some(FuncSection { signature_ids: vec![0, 0] }),
+            code_section: Some(CodeSection { blocks: vec![body()] }),
+            ..EncodedModule::default()
+        };
+        assert_eq!(module.check(), Err(EmitError::FunctionCount { functions: 2, bodies: 1 }));
+    }
+
+    #[test]
+    fn data_counts_must_match_the_data_section() {
+        let module = EncodedModule {
+            data_count_section: Some(DataCountSection { count: 2 }),
+            data_section: Some(DataSection {
+                segments: vec![DataSegment {
+                    offset: 8,
+                    bytes: vec![0],
+                }],
+            }),
+            ..EncodedModule::default()
+        };
+        assert_eq!(module.check(), Err(EmitError::DataCount { declared: 2, segments: 1 }));
+    }
+
+    #[test]
+    fn functions_of_undefined_types_are_rejected() {
+        let module = EncodedModule {
+            type_section: Some(void()),
+            function_section: Some(FuncSection { signature_ids: vec![1] }),
+            code_section: Some(CodeSection { blocks: vec![body()] }),
+            ..EncodedModule::default()
+        };
+        assert_eq!(module.check(), Err(EmitError::UnknownType(1)));
     }
 }
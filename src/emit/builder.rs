@@ -0,0 +1,324 @@
+//! Assembles a module from declarations, handing back the index of each one.
+//!
+//! Imports come before definitions in every index space, so the imports of a
+//! kind have to be added before the first definition of that kind. Functions
+//! are declared with their signature and defined later, which lets bodies call
+//! functions defined after them.
+
+use super::*;
+
+/// A signature in the type section.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TypeIndex(u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FuncIndex(u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TableIndex(u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MemoryIndex(u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GlobalIndex(u32);
+
+impl TypeIndex {
+    pub fn index(self) -> u32 {
+        self.0
+    }
+}
+
+impl FuncIndex {
+    pub fn index(self) -> u32 {
+        self.0
+    }
+}
+
+impl TableIndex {
+    pub fn index(self) -> u32 {
+        self.0
+    }
+}
+
+impl MemoryIndex {
+    pub fn index(self) -> u32 {
+        self.0
+    }
+}
+
+impl GlobalIndex {
+    pub fn index(self) -> u32 {
+        self.0
+    }
+}
+
+#[derive(Default)]
+pub struct ModuleBuilder {
+    /// Every distinct signature, by type index.
+    types: Vec<ir::Signature>,
+    imports: ImportSection,
+    /// The type of each function the module defines, with its body once it has one.
+    functions: Vec<(TypeIndex, Option<CodeBody>)>,
+    tables: Vec<Limits>,
+    memories: Vec<Limits>,
+    globals: Vec<GlobalEntry>,
+    exports: Vec<ExportSignature>,
+    start: Option<FuncIndex>,
+    elements: Vec<ElementSegment>,
+    data: Vec<DataSegment>,
+    custom_sections: Vec<CustomSection>,
+    names: Names,
+    /// The first misuse of the builder, reported by `finish`.
+    error: Option<EmitError>,
+}
+
+impl ModuleBuilder {
+    pub fn new() -> Self {
+        ModuleBuilder::default()
+    }
+
+    /// The type index of a signature, added to the type section the first time it is used.
+    pub fn signature(&mut self, signature: &ir::Signature) -> TypeIndex {
+        match self.types.iter().position(|s| s == signature) {
+            Some(index) => TypeIndex(index as u32),
+            None => {
+                self.types.push(signature.clone());
+                TypeIndex(self.types.len() as u32 - 1)
+            }
+        }
+    }
+
+    fn import(&mut self, module: &str, name: &str, desc: ImportDesc, defined: usize) -> u32 {
+        let index = self.imports.count(desc.kind());
+
+        if defined > 0 && self.error.is_none() {
+            self.error = Some(EmitError::LateImport(format!("{}.{}", module, name)));
+        }
+
+        self.imports.import(module, name, desc);
+        index
+    }
+
+    pub fn import_function(&mut self, module: &str, name: &str, signature: &ir::Signature) -> FuncIndex {
+        let type_index = self.signature(signature);
+        let defined = self.functions.len();
+        let function = FuncIndex(self.import(module, name, ImportDesc::Function(type_index.0), defined));
+        self.name_function(function, &format!("{}.{}", module, name));
+        function
+    }
+
+    pub fn import_table(&mut self, module: &str, name: &str, min: u32, max: Option<u32>) -> TableIndex {
+        let defined = self.tables.len();
+        TableIndex(self.import(module, name, ImportDesc::Table(Limits { min, max }), defined))
+    }
+
+    pub fn import_memory(&mut self, module: &str, name: &str, min: u32, max: Option<u32>) -> MemoryIndex {
+        let defined = self.memories.len();
+        MemoryIndex(self.import(module, name, ImportDesc::Memory(Limits { min, max }), defined))
+    }
+
+    pub fn import_global(&mut self, module: &str, name: &str, ty: ValType, mutable: bool) -> GlobalIndex {
+        let desc = ImportDesc::Global {
+            value_type: encode_val_type(ty),
+            mutable,
+        };
+        let defined = self.globals.len();
+        let global = GlobalIndex(self.import(module, name, desc, defined));
+        self.name_global(global, &format!("{}.{}", module, name));
+        global
+    }
+
+    /// Declares a function of the module, which `define` gives a body.
+    pub fn function(&mut self, signature: &ir::Signature) -> FuncIndex {
+        let type_index = self.signature(signature);
+        let imported = self.imports.count(webassembly::DESC_FUNCTION);
+        self.functions.push((type_index, None));
+        FuncIndex(imported + self.functions.len() as u32 - 1)
+    }
+
+    /// Gives a declared function its body, `locals` are the types of the locals
+    /// after the parameters.
+    pub fn define(&mut self, function: FuncIndex, locals: &[ValType], body: Vec<Instruction>) {
+        let imported = self.imports.count(webassembly::DESC_FUNCTION);
+        let entry = function
+            .0
+            .checked_sub(imported)
+            .and_then(|index| self.functions.get_mut(index as usize));
+
+        match entry {
+            Some((_, code @ None)) => {
+                *code = Some(CodeBody {
+                    locals: CodeLocal::declare(locals),
+                    instructions: body,
+                });
+            }
+            _ => {
+                if self.error.is_none() {
+                    self.error = Some(EmitError::Redefined(function.0));
+                }
+            }
+        }
+    }
+
+    /// A table of function references, the MVP allows only one.
+    pub fn table(&mut self, min: u32, max: Option<u32>) -> TableIndex {
+        let imported = self.imports.count(webassembly::DESC_TABLE);
+        self.tables.push(Limits { min, max });
+        TableIndex(imported + self.tables.len() as u32 - 1)
+    }
+
+    /// Fills a table with functions from `offset` on when the module is instantiated.
+    pub fn elements(&mut self, table: TableIndex, offset: u32, functions: &[FuncIndex]) {
+        self.elements.push(ElementSegment {
+            table: table.0,
+            offset,
+            functions: functions.iter().map(|function| function.0).collect(),
+        });
+    }
+
+    /// A memory of `min` to `max` pages of 64 KiB, the MVP allows only one.
+    pub fn memory(&mut self, min: u32, max: Option<u32>) -> MemoryIndex {
+        let imported = self.imports.count(webassembly::DESC_MEMORY);
+        self.memories.push(Limits { min, max });
+        MemoryIndex(imported + self.memories.len() as u32 - 1)
+    }
+
+    /// Copies `bytes` into memory 0 at `offset` when the module is instantiated.
+    pub fn data(&mut self, offset: u32, bytes: Vec<u8>) {
+        self.data.push(DataSegment { offset, bytes });
+    }
+
+    /// A global holding `init` to begin with, which must be a constant instruction.
+    pub fn global(&mut self, ty: ValType, mutable: bool, init: Instruction) -> GlobalIndex {
+        let imported = self.imports.count(webassembly::DESC_GLOBAL);
+        self.globals.push(GlobalEntry {
+            value_type: encode_val_type(ty),
+            mutable,
+            init,
+        });
+        GlobalIndex(imported + self.globals.len() as u32 - 1)
+    }
+
+    fn export(&mut self, name: &str, sig_type: u8, index: u32) {
+        self.exports.push(ExportSignature {
+            name: EncodedString { val: name.to_string() },
+            sig_type,
+            index,
+        });
+    }
+
+    pub fn export_function(&mut self, name: &str, function: FuncIndex) {
+        self.export(name, webassembly::DESC_FUNCTION, function.0);
+    }
+
+    pub fn export_table(&mut self, name: &str, table: TableIndex) {
+        self.export(name, webassembly::DESC_TABLE, table.0);
+    }
+
+    pub fn export_memory(&mut self, name: &str, memory: MemoryIndex) {
+        self.export(name, webassembly::DESC_MEMORY, memory.0);
+    }
+
+    pub fn export_global(&mut self, name: &str, global: GlobalIndex) {
+        self.export(name, webassembly::DESC_GLOBAL, global.0);
+    }
+
+    pub fn is_exported(&self, name: &str) -> bool {
+        self.exports.iter().any(|export| export.name.val == name)
+    }
+
+    /// Runs `function` when the module is instantiated.
+    pub fn start(&mut self, function: FuncIndex) {
+        self.start = Some(function);
+    }
+
+    pub fn custom(&mut self, section: CustomSection) {
+        self.custom_sections.push(section);
+    }
+
+    /// Names a function in the text format, imported functions are named after
+    /// their module and name to begin with.
+    pub fn name_function(&mut self, function: FuncIndex, name: &str) {
+        self.names.functions.insert(function.0, name.to_string());
+    }
+
+    /// Names a local of a function in the text format, parameters first.
+    pub fn name_local(&mut self, function: FuncIndex, local: u32, name: &str) {
+        self.names.locals.entry(function.0).or_default().insert(local, name.to_string());
+    }
+
+    pub fn name_global(&mut self, global: GlobalIndex, name: &str) {
+        self.names.globals.insert(global.0, name.to_string());
+    }
+
+    /// Encodes the module, failing if the builder was misused or a declared
+    /// function was never defined.
+    pub fn finish(self) -> Result<Vec<u8>, EmitError> {
+        let (module, _) = self.assemble()?;
+        Ok(module.encode())
+    }
+
+    /// Renders the module in the text format, failing like `finish`.
+    pub fn wat(self, style: wat::Style) -> Result<String, EmitError> {
+        let (module, names) = self.assemble()?;
+        Ok(module.wat(&names, style))
+    }
+
+    fn assemble(self) -> Result<(EncodedModule, Names), EmitError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        let imported = self.imports.count(webassembly::DESC_FUNCTION);
+        let mut signature_ids = Vec::new();
+        let mut blocks = Vec::new();
+        for (index, (type_index, body)) in self.functions.into_iter().enumerate() {
+            match body {
+                Some(body) => blocks.push(body),
+                None => return Err(EmitError::Undefined(imported + index as u32)),
+            }
+            signature_ids.push(type_index.0);
+        }
+
+        let mut encoded = EncodedModule {
+            type_section: Some(TypeSection {
+                signatures: self.types.iter().map(TypeSignature::signature).collect(),
+            }),
+            function_section: Some(FuncSection { signature_ids }),
+            code_section: Some(CodeSection { blocks }),
+            custom_sections: self.custom_sections,
+            ..EncodedModule::default()
+        };
+
+        if !self.imports.imports.is_empty() {
+            encoded.import_section = Some(self.imports);
+        }
+        if !self.tables.is_empty() {
+            encoded.table_section = Some(TableSection { tables: self.tables });
+        }
+        if !self.memories.is_empty() {
+            encoded.memory_section = Some(MemorySection { memories: self.memories });
+        }
+        if !self.globals.is_empty() {
+            encoded.global_section = Some(GlobalSection { globals: self.globals });
+        }
+        if !self.exports.is_empty() {
+            encoded.export_section = Some(ExportSection { exports: self.exports });
+        }
+        if let Some(function) = self.start {
+            encoded.start_section = Some(StartSection { function: function.0 });
+        }
+        if !self.elements.is_empty() {
+            encoded.element_section = Some(ElementSection { segments: self.elements });
+        }
+        if !self.data.is_empty() {
+            let data = DataSection { segments: self.data };
+            encoded.data_count_section = Some(data.count());
+            encoded.data_section = Some(data);
+        }
+
+        encoded.check()?;
+        Ok((encoded, self.names))
+    }
+}
@@ -0,0 +1,782 @@
+//! Reads a binary module back into the sections it is encoded from, for checking
+//! what the emitter wrote and for `lsc objdump`.
+//!
+//! Only what the structures can hold is understood: the MVP instructions, and
+//! active element and data segments at constant offsets. Integers are decoded
+//! here rather than with the `webassembly` crate, whose extractors ignore the
+//! position they are given.
+
+use super::instruction::NumType;
+use super::*;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DecodeErrorKind {
+    UnexpectedEnd,
+    /// The file doesn't start with `\0asm`.
+    NotWasm,
+    UnknownVersion(u32),
+    /// An integer encoded in more bytes than its type allows, or too large for it.
+    BadInteger,
+    InvalidUtf8,
+    UnknownSection(u8),
+    /// A section after one the spec orders after it, or a second one of a kind.
+    MisplacedSection(u8),
+    /// A section or function body whose contents don't end where its size says.
+    SizeMismatch,
+    UnknownOpcode(u8),
+    UnknownType(u8),
+    Unsupported(&'static str),
+}
+
+/// Where a module stops making sense, as an offset into the file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodeError {
+    pub kind: DecodeErrorKind,
+    pub offset: usize,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at offset {:#x}: ", self.offset)?;
+        match &self.kind {
+            DecodeErrorKind::UnexpectedEnd => write!(f, "unexpected end of the module"),
+            DecodeErrorKind::NotWasm => write!(f, "not a wasm module"),
+            DecodeErrorKind::UnknownVersion(version) => write!(f, "unknown binary version {}", version),
+            DecodeErrorKind::BadInteger => write!(f, "integer too long or too large"),
+            DecodeErrorKind::InvalidUtf8 => write!(f, "name is not valid UTF-8"),
+            DecodeErrorKind::UnknownSection(id) => write!(f, "unknown section {}", id),
+            DecodeErrorKind::MisplacedSection(id) => write!(f, "section {} is out of order or repeated", id),
+            DecodeErrorKind::SizeMismatch => write!(f, "contents don't match the size"),
+            DecodeErrorKind::UnknownOpcode(opcode) => write!(f, "unknown opcode {:#04x}", opcode),
+            DecodeErrorKind::UnknownType(code) => write!(f, "unknown type {:#04x}", code),
+            DecodeErrorKind::Unsupported(what) => write!(f, "{} are not supported", what),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+/// A section as it was found in the file, `start` and `end` enclosing its contents.
+struct SectionHeader {
+    id: u8,
+    start: usize,
+    end: usize,
+    /// The name of a custom section.
+    name: Option<String>,
+}
+
+/// Where the parts of a function body were found in the file.
+struct BodyLayout {
+    /// The size of the body.
+    start: usize,
+    /// Each declaration of locals, the first one taking in their count.
+    locals: Vec<usize>,
+    instructions: Vec<usize>,
+    /// The `end` of the body.
+    end: usize,
+}
+
+/// Reads the bytes of a module up to `end`, reporting errors at the position it
+/// reached.
+struct Reader<'b> {
+    bytes: &'b [u8],
+    position: usize,
+    end: usize,
+}
+
+impl<'b> Reader<'b> {
+    fn error<T>(&self, kind: DecodeErrorKind, offset: usize) -> Result<T, DecodeError> {
+        Err(DecodeError { kind, offset })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.end
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        if self.is_empty() {
+            return self.error(DecodeErrorKind::UnexpectedEnd, self.position);
+        }
+        self.position += 1;
+        Ok(self.bytes[self.position - 1])
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'b [u8], DecodeError> {
+        if self.end - self.position < count {
+            return self.error(DecodeErrorKind::UnexpectedEnd, self.end);
+        }
+        self.position += count;
+        Ok(&self.bytes[self.position - count..self.position])
+    }
+
+    /// A reader for the next `size` bytes, which this one skips.
+    fn sub(&mut self, size: usize) -> Result<Reader<'b>, DecodeError> {
+        let start = self.position;
+        self.bytes(size)?;
+        Ok(Reader {
+            bytes: self.bytes,
+            position: start,
+            end: start + size,
+        })
+    }
+
+    /// Checks that everything was read.
+    fn finish(&self) -> Result<(), DecodeError> {
+        if self.position != self.end {
+            return self.error(DecodeErrorKind::SizeMismatch, self.position);
+        }
+        Ok(())
+    }
+
+    /// An unsigned LEB128 integer.
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        let start = self.position;
+        let mut value: u64 = 0;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                if value > u64::from(u32::MAX) {
+                    return self.error(DecodeErrorKind::BadInteger, start);
+                }
+                return Ok(value as u32);
+            }
+        }
+        self.error(DecodeErrorKind::BadInteger, start)
+    }
+
+    /// A signed LEB128 integer of `bits` bits.
+    fn signed(&mut self, bits: u32) -> Result<i64, DecodeError> {
+        let start = self.position;
+        let mut value: i64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift < 64 {
+                value |= i64::from(byte & 0x7f) << shift;
+            }
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                // The sign is the top bit of the last byte.
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                break;
+            }
+            // At most as many bytes as it takes to hold `bits` bits.
+            if shift >= bits.div_ceil(7) * 7 {
+                return self.error(DecodeErrorKind::BadInteger, start);
+            }
+        }
+
+        let unused = 64 - bits;
+        if (value << unused) >> unused != value {
+            return self.error(DecodeErrorKind::BadInteger, start);
+        }
+        Ok(value)
+    }
+
+    fn name(&mut self) -> Result<String, DecodeError> {
+        let length = self.u32()? as usize;
+        let start = self.position;
+        let bytes = self.bytes(length)?;
+        match std::str::from_utf8(bytes) {
+            Ok(name) => Ok(name.to_string()),
+            Err(_) => self.error(DecodeErrorKind::InvalidUtf8, start),
+        }
+    }
+
+    fn vec<T, F>(&mut self, mut element: F) -> Result<Vec<T>, DecodeError>
+    where
+        F: FnMut(&mut Self) -> Result<T, DecodeError>,
+    {
+        let count = self.u32()?;
+        (0..count).map(|_| element(self)).collect()
+    }
+
+    fn value_type(&mut self) -> Result<u8, DecodeError> {
+        let code = self.byte()?;
+        match code {
+            webassembly::I32 | webassembly::I64 | webassembly::F32 | webassembly::F64 => Ok(code),
+            _ => self.error(DecodeErrorKind::UnknownType(code), self.position - 1),
+        }
+    }
+
+    fn block_type(&mut self) -> Result<BlockType, DecodeError> {
+        let code = self.byte()?;
+        Ok(match code {
+            webassembly::EMPTY => BlockType::Empty,
+            webassembly::I32 => BlockType::Value(NumType::I32),
+            webassembly::I64 => BlockType::Value(NumType::I64),
+            webassembly::F32 => BlockType::Value(NumType::F32),
+            webassembly::F64 => BlockType::Value(NumType::F64),
+            _ => return self.error(DecodeErrorKind::UnknownType(code), self.position - 1),
+        })
+    }
+
+    fn limits(&mut self) -> Result<Limits, DecodeError> {
+        let start = self.position;
+        match self.byte()? {
+            webassembly::LIMIT_MIN => Ok(Limits {
+                min: self.u32()?,
+                max: None,
+            }),
+            webassembly::LIMIT_MIN_MAX => Ok(Limits {
+                min: self.u32()?,
+                max: Some(self.u32()?),
+            }),
+            _ => self.error(DecodeErrorKind::Unsupported("shared or 64-bit limits"), start),
+        }
+    }
+
+    /// The limits of a table, whose elements must be `funcref`.
+    fn table(&mut self) -> Result<Limits, DecodeError> {
+        let code = self.byte()?;
+        if code != webassembly::ANYFUNC {
+            return self.error(DecodeErrorKind::UnknownType(code), self.position - 1);
+        }
+        self.limits()
+    }
+
+    fn signature(&mut self) -> Result<TypeSignature, DecodeError> {
+        let code = self.byte()?;
+        if code != webassembly::FUNC {
+            return self.error(DecodeErrorKind::UnknownType(code), self.position - 1);
+        }
+
+        let inputs = self.vec(Reader::value_type)?;
+        let outputs = self.vec(Reader::value_type)?;
+        Ok(TypeSignature {
+            type_sig: code,
+            inputs: Some(inputs.into_iter().map(TypeSignature::value).collect()),
+            outputs: Some(outputs.into_iter().map(TypeSignature::value).collect()),
+        })
+    }
+
+    fn import(&mut self) -> Result<ImportEntry, DecodeError> {
+        let module = EncodedString { val: self.name()? };
+        let name = EncodedString { val: self.name()? };
+
+        let start = self.position;
+        let desc = match self.byte()? {
+            webassembly::DESC_FUNCTION => ImportDesc::Function(self.u32()?),
+            webassembly::DESC_TABLE => ImportDesc::Table(self.table()?),
+            webassembly::DESC_MEMORY => ImportDesc::Memory(self.limits()?),
+            webassembly::DESC_GLOBAL => ImportDesc::Global {
+                value_type: self.value_type()?,
+                mutable: self.mutability()?,
+            },
+            _ => return self.error(DecodeErrorKind::Unsupported("imports of that kind"), start),
+        };
+
+        Ok(ImportEntry { module, name, desc })
+    }
+
+    fn mutability(&mut self) -> Result<bool, DecodeError> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => self.error(DecodeErrorKind::Unsupported("mutabilities other than 0 and 1"), self.position - 1),
+        }
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, DecodeError> {
+        let start = self.position;
+        let opcode = self.byte()?;
+        if let Some(instruction) = Instruction::from_opcode(opcode) {
+            return Ok(instruction);
+        }
+
+        Ok(match opcode {
+            webassembly::BLOCK => Instruction::Block(self.block_type()?),
+            webassembly::LOOP => Instruction::Loop(self.block_type()?),
+            webassembly::IF => Instruction::If(self.block_type()?),
+            webassembly::BR => Instruction::Br(self.u32()?),
+            webassembly::BR_IF => Instruction::BrIf(self.u32()?),
+            webassembly::BR_TABLE => {
+                let labels = self.vec(Reader::u32)?;
+                Instruction::BrTable(labels, self.u32()?)
+            }
+            webassembly::CALL => Instruction::Call(self.u32()?),
+            webassembly::CALL_INDIRECT => Instruction::CallIndirect {
+                type_index: self.u32()?,
+                table: self.u32()?,
+            },
+            webassembly::LOCAL_GET => Instruction::LocalGet(self.u32()?),
+            webassembly::LOCAL_SET => Instruction::LocalSet(self.u32()?),
+            webassembly::LOCAL_TEE => Instruction::LocalTee(self.u32()?),
+            webassembly::GLOBAL_GET => Instruction::GlobalGet(self.u32()?),
+            webassembly::GLOBAL_SET => Instruction::GlobalSet(self.u32()?),
+            webassembly::MEMORY_SIZE | webassembly::MEMORY_GROW => {
+                if self.byte()? != 0x00 {
+                    return self.error(DecodeErrorKind::Unsupported("memories other than 0"), self.position - 1);
+                }
+                if opcode == webassembly::MEMORY_SIZE {
+                    Instruction::MemorySize
+                } else {
+                    Instruction::MemoryGrow
+                }
+            }
+            webassembly::I32_CONST => Instruction::I32Const(self.signed(32)? as i32),
+            webassembly::I64_CONST => Instruction::I64Const(self.signed(64)?),
+            webassembly::F32_CONST => {
+                let bytes = self.bytes(4)?;
+                Instruction::F32Const(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            }
+            webassembly::F64_CONST => {
+                let mut bits = [0; 8];
+                bits.copy_from_slice(self.bytes(8)?);
+                Instruction::F64Const(f64::from_le_bytes(bits))
+            }
+            _ => {
+                let memarg = MemArg {
+                    align: self.u32()?,
+                    offset: self.u32()?,
+                };
+                match Instruction::from_memory_opcode(opcode, memarg) {
+                    Some(instruction) => instruction,
+                    None => return self.error(DecodeErrorKind::UnknownOpcode(opcode), start),
+                }
+            }
+        })
+    }
+
+    /// A constant expression, which the structures hold as a single instruction.
+    fn constant(&mut self) -> Result<Instruction, DecodeError> {
+        let instruction = self.instruction()?;
+        if self.byte()? != webassembly::END {
+            let what = "constant expressions of more than one instruction";
+            return self.error(DecodeErrorKind::Unsupported(what), self.position - 1);
+        }
+        Ok(instruction)
+    }
+
+    /// The offset of an active segment.
+    fn offset(&mut self) -> Result<u32, DecodeError> {
+        let start = self.position;
+        match self.constant()? {
+            Instruction::I32Const(offset) => Ok(offset as u32),
+            _ => self.error(DecodeErrorKind::Unsupported("segment offsets other than constants"), start),
+        }
+    }
+
+    fn global(&mut self) -> Result<GlobalEntry, DecodeError> {
+        Ok(GlobalEntry {
+            value_type: self.value_type()?,
+            mutable: self.mutability()?,
+            init: self.constant()?,
+        })
+    }
+
+    fn export(&mut self) -> Result<ExportSignature, DecodeError> {
+        let name = EncodedString { val: self.name()? };
+        let start = self.position;
+        let sig_type = self.byte()?;
+        if sig_type > webassembly::DESC_GLOBAL {
+            return self.error(DecodeErrorKind::Unsupported("exports of that kind"), start);
+        }
+
+        Ok(ExportSignature {
+            name,
+            sig_type,
+            index: self.u32()?,
+        })
+    }
+
+    fn element(&mut self) -> Result<ElementSegment, DecodeError> {
+        let start = self.position;
+        let flags = self.u32()?;
+        let table = match flags {
+            0 => 0,
+            2 => self.u32()?,
+            _ => return self.error(DecodeErrorKind::Unsupported("passive and declarative element segments"), start),
+        };
+
+        // A segment naming its table names the kind of its elements, even table 0.
+        let offset = self.offset()?;
+        if flags == 2 && self.byte()? != 0x00 {
+            return self.error(DecodeErrorKind::Unsupported("elements other than functions"), self.position - 1);
+        }
+
+        Ok(ElementSegment {
+            table,
+            offset,
+            functions: self.vec(Reader::u32)?,
+        })
+    }
+
+    fn data(&mut self) -> Result<DataSegment, DecodeError> {
+        let start = self.position;
+        if self.u32()? != 0 {
+            return self.error(DecodeErrorKind::Unsupported("passive data segments and memories other than 0"), start);
+        }
+
+        let offset = self.offset()?;
+        let length = self.u32()? as usize;
+        Ok(DataSegment {
+            offset,
+            bytes: self.bytes(length)?.to_vec(),
+        })
+    }
+
+    fn body(&mut self) -> Result<(CodeBody, BodyLayout), DecodeError> {
+        let start = self.position;
+        let size = self.u32()? as usize;
+        let mut body = self.sub(size)?;
+
+        let mut locals = Vec::new();
+        let mut layout = BodyLayout {
+            start,
+            locals: Vec::new(),
+            instructions: Vec::new(),
+            end: 0,
+        };
+
+        let count_start = body.position;
+        let count = body.u32()?;
+        for i in 0..count {
+            layout.locals.push(if i == 0 { count_start } else { body.position });
+            locals.push(CodeLocal {
+                count: body.u32()? as usize,
+                local_type: body.value_type()?,
+            });
+        }
+
+        let mut instructions = Vec::new();
+        let mut depth = 0usize;
+        loop {
+            let offset = body.position;
+            let instruction = body.instruction()?;
+            match instruction {
+                Instruction::Block(_) | Instruction::Loop(_) | Instruction::If(_) => depth += 1,
+                Instruction::End if depth == 0 => {
+                    layout.end = offset;
+                    break;
+                }
+                Instruction::End => depth -= 1,
+                _ => {}
+            }
+            layout.instructions.push(offset);
+            instructions.push(instruction);
+        }
+        body.finish()?;
+
+        Ok((CodeBody { locals, instructions }, layout))
+    }
+}
+
+/// A module read back from its binary encoding, with where its sections and
+/// instructions were found.
+pub struct Decoded {
+    module: EncodedModule,
+    sections: Vec<SectionHeader>,
+    bodies: Vec<BodyLayout>,
+    bytes: Vec<u8>,
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Decoded, DecodeError> {
+    let mut reader = Reader {
+        bytes,
+        position: 0,
+        end: bytes.len(),
+    };
+
+    if reader.bytes(4).ok() != Some(webassembly::MAGIC_NUMBER) {
+        return reader.error(DecodeErrorKind::NotWasm, 0);
+    }
+    let version = reader.bytes(4)?;
+    if version != webassembly::VERSION_1 {
+        let version = u32::from_le_bytes([version[0], version[1], version[2], version[3]]);
+        return reader.error(DecodeErrorKind::UnknownVersion(version), 4);
+    }
+
+    let mut decoded = Decoded {
+        module: EncodedModule::default(),
+        sections: Vec::new(),
+        bodies: Vec::new(),
+        bytes: bytes.to_vec(),
+    };
+
+    // The ids of the known sections in the order they must come in.
+    let order: Vec<u8> = decoded.module.sections().iter().map(|(id, _)| *id).collect();
+    let mut last: Option<(usize, u8)> = None;
+
+    while !reader.is_empty() {
+        let offset = reader.position;
+        let id = reader.byte()?;
+        let size = reader.u32()? as usize;
+        let mut section = reader.sub(size)?;
+        let start = section.position;
+
+        let mut name = None;
+        if id == webassembly::SECTION_CUSTOM {
+            let section_name = section.name()?;
+            let content = section.bytes(section.end - section.position)?.to_vec();
+            decoded.module.custom_sections.push(CustomSection {
+                name: section_name.clone(),
+                content,
+                placement: last.map_or(Placement::First, |(_, id)| Placement::After(id)),
+            });
+            name = Some(section_name);
+        } else {
+            let rank = match order.iter().position(|known| *known == id) {
+                Some(rank) => rank,
+                None => return reader.error(DecodeErrorKind::UnknownSection(id), offset),
+            };
+            if last.is_some_and(|(previous, _)| previous >= rank) {
+                return reader.error(DecodeErrorKind::MisplacedSection(id), offset);
+            }
+            decoded.section(id, &mut section)?;
+            last = Some((rank, id));
+        }
+        section.finish()?;
+
+        decoded.sections.push(SectionHeader {
+            id,
+            start,
+            end: start + size,
+            name,
+        });
+    }
+
+    Ok(decoded)
+}
+
+impl Decoded {
+    fn section(&mut self, id: u8, reader: &mut Reader) -> Result<(), DecodeError> {
+        let module = &mut self.module;
+        match id {
+            webassembly::SECTION_TYPE => {
+                let signatures = reader.vec(Reader::signature)?;
+                module.type_section = Some(TypeSection { signatures });
+            }
+            webassembly::SECTION_IMPORT => {
+                let imports = reader.vec(Reader::import)?;
+                module.import_section = Some(ImportSection { imports });
+            }
+            webassembly::SECTION_FUNCTION => {
+                let signature_ids = reader.vec(Reader::u32)?;
+                module.function_section = Some(FuncSection { signature_ids });
+            }
+            webassembly::SECTION_TABLE => {
+                let tables = reader.vec(Reader::table)?;
+                module.table_section = Some(TableSection { tables });
+            }
+            webassembly::SECTION_MEMORY => {
+                let memories = reader.vec(Reader::limits)?;
+                module.memory_section = Some(MemorySection { memories });
+            }
+            webassembly::SECTION_GLOBAL => {
+                let globals = reader.vec(Reader::global)?;
+                module.global_section = Some(GlobalSection { globals });
+            }
+            webassembly::SECTION_EXPORT => {
+                let exports = reader.vec(Reader::export)?;
+                module.export_section = Some(ExportSection { exports });
+            }
+            webassembly::SECTION_START => {
+                let function = reader.u32()?;
+                module.start_section = Some(StartSection { function });
+            }
+            webassembly::SECTION_ELEMENT => {
+                let segments = reader.vec(Reader::element)?;
+                module.element_section = Some(ElementSection { segments });
+            }
+            SECTION_DATA_COUNT => {
+                let count = reader.u32()?;
+                module.data_count_section = Some(DataCountSection { count });
+            }
+            webassembly::SECTION_CODE => {
+                let bodies = reader.vec(Reader::body)?;
+                let (blocks, layouts): (Vec<CodeBody>, Vec<BodyLayout>) = bodies.into_iter().unzip();
+                module.code_section = Some(CodeSection { blocks });
+                self.bodies = layouts;
+            }
+            webassembly::SECTION_DATA => {
+                let segments = reader.vec(Reader::data)?;
+                module.data_section = Some(DataSection { segments });
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Encodes the module again, which gives back the bytes it was decoded from
+    /// if they were encoded the way `ModuleEmitter` does.
+    pub fn encode(&self) -> Vec<u8> {
+        self.module.encode()
+    }
+
+    pub fn wat(&self, style: wat::Style) -> String {
+        self.module.wat(&self.names(), style)
+    }
+
+    /// Imports are named after their module and name, and the functions and
+    /// globals the module defines after their first export.
+    fn names(&self) -> Names {
+        let mut names = Names::default();
+
+        let (mut functions, mut globals) = (0, 0);
+        for import in self.module.import_section.iter().flat_map(|section| section.imports.iter()) {
+            let name = format!("{}.{}", import.module.val, import.name.val);
+            match import.desc {
+                ImportDesc::Function(_) => {
+                    names.functions.insert(functions, name);
+                    functions += 1;
+                }
+                ImportDesc::Global { .. } => {
+                    names.globals.insert(globals, name);
+                    globals += 1;
+                }
+                _ => {}
+            }
+        }
+
+        for export in self.module.export_section.iter().flat_map(|section| section.exports.iter()) {
+            let name = export.name.val.clone();
+            match export.sig_type {
+                webassembly::DESC_FUNCTION => names.functions.entry(export.index).or_insert(name),
+                webassembly::DESC_GLOBAL => names.globals.entry(export.index).or_insert(name),
+                _ => continue,
+            };
+        }
+
+        names
+    }
+
+    /// The number of entries of a section, or what it holds if it isn't a vector.
+    fn describe(&self, header: &SectionHeader, names: &Names) -> String {
+        let module = &self.module;
+        let count = match header.id {
+            webassembly::SECTION_TYPE => module.type_section.as_ref().map(|s| s.signatures.len()),
+            webassembly::SECTION_IMPORT => module.import_section.as_ref().map(|s| s.imports.len()),
+            webassembly::SECTION_FUNCTION => module.function_section.as_ref().map(|s| s.signature_ids.len()),
+            webassembly::SECTION_TABLE => module.table_section.as_ref().map(|s| s.tables.len()),
+            webassembly::SECTION_MEMORY => module.memory_section.as_ref().map(|s| s.memories.len()),
+            webassembly::SECTION_GLOBAL => module.global_section.as_ref().map(|s| s.globals.len()),
+            webassembly::SECTION_EXPORT => module.export_section.as_ref().map(|s| s.exports.len()),
+            webassembly::SECTION_ELEMENT => module.element_section.as_ref().map(|s| s.segments.len()),
+            SECTION_DATA_COUNT => module.data_count_section.as_ref().map(|s| s.count as usize),
+            webassembly::SECTION_CODE => module.code_section.as_ref().map(|s| s.blocks.len()),
+            webassembly::SECTION_DATA => module.data_section.as_ref().map(|s| s.segments.len()),
+            webassembly::SECTION_START => {
+                let function = module.start_section.as_ref().map_or(0, |s| s.function);
+                return format!("start: {}{}", function, annotation(names.functions.get(&function)));
+            }
+            _ => return format!("\"{}\"", header.name.as_deref().unwrap_or("")),
+        };
+        format!("count: {}", count.unwrap_or(0))
+    }
+
+    /// Lists the sections with where they are and how large they are, and
+    /// disassembles the function bodies.
+    pub fn objdump(&self) -> String {
+        let names = self.names();
+        let mut out = String::from("Sections:\n\n");
+
+        for header in self.sections.iter() {
+            out.push_str(&format!(
+                "{:>9} start={:#010x} end={:#010x} (size={:#010x}) {}\n",
+                section_name(header.id),
+                header.start,
+                header.end,
+                header.end - header.start,
+                self.describe(header, &names)
+            ));
+        }
+
+        out.push_str("\nCode Disassembly:\n");
+
+        let imported = self.module.import_section.as_ref().map_or(0, |s| s.count(webassembly::DESC_FUNCTION));
+        let signature_ids = self.module.function_section.iter().flat_map(|s| s.signature_ids.iter());
+        let blocks = self.module.code_section.iter().flat_map(|s| s.blocks.iter());
+        for (i, ((body, layout), type_index)) in blocks.zip(self.bodies.iter()).zip(signature_ids).enumerate() {
+            let index = imported + i as u32;
+            out.push_str(&format!(
+                "\n{:06x} func[{}]{}:\n",
+                layout.start,
+                index,
+                annotation(names.functions.get(&index))
+            ));
+
+            let parameters = self
+                .module
+                .type_section
+                .as_ref()
+                .and_then(|s| s.signatures.get(*type_index as usize))
+                .and_then(|s| s.inputs.as_ref())
+                .map_or(0, |inputs| inputs.len());
+            let first_instruction = layout.instructions.first().copied().unwrap_or(layout.end);
+
+            let mut local = parameters;
+            for (j, declaration) in body.locals.iter().enumerate() {
+                let start = layout.locals[j];
+                let end = layout.locals.get(j + 1).copied().unwrap_or(first_instruction);
+                let text = format!(
+                    "local[{}..{}] type={}",
+                    local,
+                    local + declaration.count,
+                    value_type(declaration.local_type)
+                );
+                out.push_str(&self.line(start, end, 0, &text));
+                local += declaration.count;
+            }
+
+            let mut depth = 0;
+            for (j, instruction) in body.instructions.iter().enumerate() {
+                let start = layout.instructions[j];
+                let end = layout.instructions.get(j + 1).copied().unwrap_or(layout.end);
+                if let Instruction::Else | Instruction::End = instruction {
+                    depth = usize::saturating_sub(depth, 1);
+                }
+
+                let name = match instruction {
+                    Instruction::Call(index) => names.functions.get(index),
+                    Instruction::GlobalGet(index) | Instruction::GlobalSet(index) => names.globals.get(index),
+                    _ => None,
+                };
+                let text = format!("{}{}", instruction, annotation(name));
+                out.push_str(&self.line(start, end, depth, &text));
+
+                if let Instruction::Block(_) | Instruction::Loop(_) | Instruction::If(_) | Instruction::Else = instruction {
+                    depth += 1;
+                }
+            }
+            out.push_str(&self.line(layout.end, layout.end + 1, 0, "end"));
+        }
+
+        out
+    }
+
+    /// A line of the disassembly, the bytes from `start` to `end` and their text.
+    fn line(&self, start: usize, end: usize, depth: usize, text: &str) -> String {
+        let bytes: Vec<String> = self.bytes[start..end].iter().map(|byte| format!("{:02x}", byte)).collect();
+        format!(" {:06x}: {:<26} | {}{}\n", start, bytes.join(" "), "  ".repeat(depth), text)
+    }
+}
+
+fn annotation(name: Option<&String>) -> String {
+    match name {
+        Some(name) => format!(" <{}>", name),
+        None => String::new(),
+    }
+}
+
+fn section_name(id: u8) -> &'static str {
+    match id {
+        webassembly::SECTION_TYPE => "Type",
+        webassembly::SECTION_IMPORT => "Import",
+        webassembly::SECTION_FUNCTION => "Function",
+        webassembly::SECTION_TABLE => "Table",
+        webassembly::SECTION_MEMORY => "Memory",
+        webassembly::SECTION_GLOBAL => "Global",
+        webassembly::SECTION_EXPORT => "Export",
+        webassembly::SECTION_START => "Start",
+        webassembly::SECTION_ELEMENT => "Elem",
+        SECTION_DATA_COUNT => "DataCount",
+        webassembly::SECTION_CODE => "Code",
+        webassembly::SECTION_DATA => "Data",
+        _ => "Custom",
+    }
+}
@@ -0,0 +1,987 @@
+//! The instructions of the wasm MVP with typed immediates, and their binary
+//! encoding.
+
+use std::fmt;
+
+use webassembly::TypeWasmExt;
+
+use super::Encoded;
+
+/// The value types of the MVP.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NumType {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl NumType {
+    pub fn code(self) -> u8 {
+        match self {
+            NumType::I32 => webassembly::I32,
+            NumType::I64 => webassembly::I64,
+            NumType::F32 => webassembly::F32,
+            NumType::F64 => webassembly::F64,
+        }
+    }
+}
+
+/// The result of a `block`, `loop` or `if`, the MVP allows at most one value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockType {
+    Empty,
+    Value(NumType),
+}
+
+impl BlockType {
+    pub fn code(self) -> u8 {
+        match self {
+            BlockType::Empty => webassembly::EMPTY,
+            BlockType::Value(ty) => ty.code(),
+        }
+    }
+}
+
+/// The immediates of a load or store, the alignment as a power of two and an
+/// offset added to the address operand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemArg {
+    pub align: u32,
+    pub offset: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instruction {
+    Unreachable,
+    Nop,
+    Block(BlockType),
+    Loop(BlockType),
+    If(BlockType),
+    Else,
+    End,
+    /// Branches to the label of the enclosing block this many blocks out.
+    Br(u32),
+    BrIf(u32),
+    /// Branches to the label at the index on the stack, or to the default one
+    /// if the index is out of range.
+    BrTable(Vec<u32>, u32),
+    Return,
+    Call(u32),
+    /// Calls the function at the index on the stack of a table, trapping unless
+    /// it has the signature at `type_index`.
+    CallIndirect { type_index: u32, table: u32 },
+    Drop,
+    Select,
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    I32Load(MemArg),
+    I64Load(MemArg),
+    F32Load(MemArg),
+    F64Load(MemArg),
+    I32Load8S(MemArg),
+    I32Load8U(MemArg),
+    I32Load16S(MemArg),
+    I32Load16U(MemArg),
+    I64Load8S(MemArg),
+    I64Load8U(MemArg),
+    I64Load16S(MemArg),
+    I64Load16U(MemArg),
+    I64Load32S(MemArg),
+    I64Load32U(MemArg),
+    I32Store(MemArg),
+    I64Store(MemArg),
+    F32Store(MemArg),
+    F64Store(MemArg),
+    I32Store8(MemArg),
+    I32Store16(MemArg),
+    I64Store8(MemArg),
+    I64Store16(MemArg),
+    I64Store32(MemArg),
+    /// The size of memory 0 in pages.
+    MemorySize,
+    /// Grows memory 0 by a number of pages, returning its old size or -1.
+    MemoryGrow,
+    I32Const(i32),
+    I64Const(i64),
+    F32Const(f32),
+    F64Const(f64),
+    I32Eqz,
+    I32Eq,
+    I32Ne,
+    I32LtS,
+    I32LtU,
+    I32GtS,
+    I32GtU,
+    I32LeS,
+    I32LeU,
+    I32GeS,
+    I32GeU,
+    I64Eqz,
+    I64Eq,
+    I64Ne,
+    I64LtS,
+    I64LtU,
+    I64GtS,
+    I64GtU,
+    I64LeS,
+    I64LeU,
+    I64GeS,
+    I64GeU,
+    F32Eq,
+    F32Ne,
+    F32Lt,
+    F32Gt,
+    F32Le,
+    F32Ge,
+    F64Eq,
+    F64Ne,
+    F64Lt,
+    F64Gt,
+    F64Le,
+    F64Ge,
+    I32Clz,
+    I32Ctz,
+    I32Popcnt,
+    I32Add,
+    I32Sub,
+    I32Mul,
+    I32DivS,
+    I32DivU,
+    I32RemS,
+    I32RemU,
+    I32And,
+    I32Or,
+    I32Xor,
+    I32Shl,
+    I32ShrS,
+    I32ShrU,
+    I32Rotl,
+    I32Rotr,
+    I64Clz,
+    I64Ctz,
+    I64Popcnt,
+    I64Add,
+    I64Sub,
+    I64Mul,
+    I64DivS,
+    I64DivU,
+    I64RemS,
+    I64RemU,
+    I64And,
+    I64Or,
+    I64Xor,
+    I64Shl,
+    I64ShrS,
+    I64ShrU,
+    I64Rotl,
+    I64Rotr,
+    F32Abs,
+    F32Neg,
+    F32Ceil,
+    F32Floor,
+    F32Trunc,
+    F32Nearest,
+    F32Sqrt,
+    F32Add,
+    F32Sub,
+    F32Mul,
+    F32Div,
+    F32Min,
+    F32Max,
+    F32Copysign,
+    F64Abs,
+    F64Neg,
+    F64Ceil,
+    F64Floor,
+    F64Trunc,
+    F64Nearest,
+    F64Sqrt,
+    F64Add,
+    F64Sub,
+    F64Mul,
+    F64Div,
+    F64Min,
+    F64Max,
+    F64Copysign,
+    I32WrapI64,
+    I32TruncF32S,
+    I32TruncF32U,
+    I32TruncF64S,
+    I32TruncF64U,
+    I64ExtendI32S,
+    I64ExtendI32U,
+    I64TruncF32S,
+    I64TruncF32U,
+    I64TruncF64S,
+    I64TruncF64U,
+    F32ConvertI32S,
+    F32ConvertI32U,
+    F32ConvertI64S,
+    F32ConvertI64U,
+    F32DemoteF64,
+    F64ConvertI32S,
+    F64ConvertI32U,
+    F64ConvertI64S,
+    F64ConvertI64U,
+    F64PromoteF32,
+    I32ReinterpretF32,
+    I64ReinterpretF64,
+    F32ReinterpretI32,
+    F64ReinterpretI64,
+}
+
+impl Instruction {
+    pub fn opcode(&self) -> u8 {
+        match self {
+            Instruction::Unreachable => webassembly::UNREACHABLE,
+            Instruction::Nop => webassembly::NOP,
+            Instruction::Block(_) => webassembly::BLOCK,
+            Instruction::Loop(_) => webassembly::LOOP,
+            Instruction::If(_) => webassembly::IF,
+            Instruction::Else => webassembly::ELSE,
+            Instruction::End => webassembly::END,
+            Instruction::Br(_) => webassembly::BR,
+            Instruction::BrIf(_) => webassembly::BR_IF,
+            Instruction::BrTable(..) => webassembly::BR_TABLE,
+            Instruction::Return => webassembly::RETURN,
+            Instruction::Call(_) => webassembly::CALL,
+            Instruction::CallIndirect { .. } => webassembly::CALL_INDIRECT,
+            Instruction::Drop => webassembly::DROP,
+            Instruction::Select => webassembly::SELECT,
+            Instruction::LocalGet(_) => webassembly::LOCAL_GET,
+            Instruction::LocalSet(_) => webassembly::LOCAL_SET,
+            Instruction::LocalTee(_) => webassembly::LOCAL_TEE,
+            Instruction::GlobalGet(_) => webassembly::GLOBAL_GET,
+            Instruction::GlobalSet(_) => webassembly::GLOBAL_SET,
+            Instruction::I32Load(_) => webassembly::I32_LOAD,
+            Instruction::I64Load(_) => webassembly::I64_LOAD,
+            Instruction::F32Load(_) => webassembly::F32_LOAD,
+            Instruction::F64Load(_) => webassembly::F64_LOAD,
+            Instruction::I32Load8S(_) => webassembly::I32_LOAD8_S,
+            Instruction::I32Load8U(_) => webassembly::I32_LOAD8_U,
+            Instruction::I32Load16S(_) => webassembly::I32_LOAD16_S,
+            Instruction::I32Load16U(_) => webassembly::I32_LOAD16_U,
+            Instruction::I64Load8S(_) => webassembly::I64_LOAD8_S,
+            Instruction::I64Load8U(_) => webassembly::I64_LOAD8_U,
+            Instruction::I64Load16S(_) => webassembly::I64_LOAD16_S,
+            Instruction::I64Load16U(_) => webassembly::I64_LOAD16_U,
+            Instruction::I64Load32S(_) => webassembly::I64_LOAD32_S,
+            Instruction::I64Load32U(_) => webassembly::I64_LOAD32_U,
+            Instruction::I32Store(_) => webassembly::I32_STORE,
+            Instruction::I64Store(_) => webassembly::I64_STORE,
+            Instruction::F32Store(_) => webassembly::F32_STORE,
+            Instruction::F64Store(_) => webassembly::F64_STORE,
+            Instruction::I32Store8(_) => webassembly::I32_STORE8,
+            Instruction::I32Store16(_) => webassembly::I32_STORE16,
+            Instruction::I64Store8(_) => webassembly::I64_STORE8,
+            Instruction::I64Store16(_) => webassembly::I64_STORE16,
+            Instruction::I64Store32(_) => webassembly::I64_STORE32,
+            Instruction::MemorySize => webassembly::MEMORY_SIZE,
+            Instruction::MemoryGrow => webassembly::MEMORY_GROW,
+            Instruction::I32Const(_) => webassembly::I32_CONST,
+            Instruction::I64Const(_) => webassembly::I64_CONST,
+            Instruction::F32Const(_) => webassembly::F32_CONST,
+            Instruction::F64Const(_) => webassembly::F64_CONST,
+            Instruction::I32Eqz => webassembly::I32_EQZ,
+            Instruction::I32Eq => webassembly::I32_EQ,
+            Instruction::I32Ne => webassembly::I32_NE,
+            Instruction::I32LtS => webassembly::I32_LT_S,
+            Instruction::I32LtU => webassembly::I32_LT_U,
+            Instruction::I32GtS => webassembly::I32_GT_S,
+            Instruction::I32GtU => webassembly::I32_GT_U,
+            Instruction::I32LeS => webassembly::I32_LE_S,
+            Instruction::I32LeU => webassembly::I32_LE_U,
+            Instruction::I32GeS => webassembly::I32_GE_S,
+            Instruction::I32GeU => webassembly::I32_GE_U,
+            Instruction::I64Eqz => webassembly::I64_EQZ,
+            Instruction::I64Eq => webassembly::I64_EQ,
+            Instruction::I64Ne => webassembly::I64_NE,
+            Instruction::I64LtS => webassembly::I64_LT_S,
+            Instruction::I64LtU => webassembly::I64_LT_U,
+            Instruction::I64GtS => webassembly::I64_GT_S,
+            Instruction::I64GtU => webassembly::I64_GT_U,
+            Instruction::I64LeS => webassembly::I64_LE_S,
+            Instruction::I64LeU => webassembly::I64_LE_U,
+            Instruction::I64GeS => webassembly::I64_GE_S,
+            Instruction::I64GeU => webassembly::I64_GE_U,
+            Instruction::F32Eq => webassembly::F32_EQ,
+            Instruction::F32Ne => webassembly::F32_NE,
+            Instruction::F32Lt => webassembly::F32_LT,
+            Instruction::F32Gt => webassembly::F32_GT,
+            Instruction::F32Le => webassembly::F32_LE,
+            Instruction::F32Ge => webassembly::F32_GE,
+            Instruction::F64Eq => webassembly::F64_EQ,
+            Instruction::F64Ne => webassembly::F64_NE,
+            Instruction::F64Lt => webassembly::F64_LT,
+            Instruction::F64Gt => webassembly::F64_GT,
+            Instruction::F64Le => webassembly::F64_LE,
+            Instruction::F64Ge => webassembly::F64_GE,
+            Instruction::I32Clz => webassembly::I32_CLZ,
+            Instruction::I32Ctz => webassembly::I32_CTZ,
+            Instruction::I32Popcnt => webassembly::I32_POPCNT,
+            Instruction::I32Add => webassembly::I32_ADD,
+            Instruction::I32Sub => webassembly::I32_SUB,
+            Instruction::I32Mul => webassembly::I32_MUL,
+            Instruction::I32DivS => webassembly::I32_DIV_S,
+            Instruction::I32DivU => webassembly::I32_DIV_U,
+            Instruction::I32RemS => webassembly::I32_REM_S,
+            Instruction::I32RemU => webassembly::I32_REM_U,
+            Instruction::I32And => webassembly::I32_AND,
+            Instruction::I32Or => webassembly::I32_OR,
+            Instruction::I32Xor => webassembly::I32_XOR,
+            Instruction::I32Shl => webassembly::I32_SHL,
+            Instruction::I32ShrS => webassembly::I32_SHR_S,
+            Instruction::I32ShrU => webassembly::I32_SHR_U,
+            Instruction::I32Rotl => webassembly::I32_ROTL,
+            Instruction::I32Rotr => webassembly::I32_ROTR,
+            Instruction::I64Clz => webassembly::I64_CLZ,
+            Instruction::I64Ctz => webassembly::I64_CTZ,
+            Instruction::I64Popcnt => webassembly::I64_POPCNT,
+            Instruction::I64Add => webassembly::I64_ADD,
+            Instruction::I64Sub => webassembly::I64_SUB,
+            Instruction::I64Mul => webassembly::I64_MUL,
+            Instruction::I64DivS => webassembly::I64_DIV_S,
+            Instruction::I64DivU => webassembly::I64_DIV_U,
+            Instruction::I64RemS => webassembly::I64_REM_S,
+            Instruction::I64RemU => webassembly::I64_REM_U,
+            Instruction::I64And => webassembly::I64_AND,
+            Instruction::I64Or => webassembly::I64_OR,
+            Instruction::I64Xor => webassembly::I64_XOR,
+            Instruction::I64Shl => webassembly::I64_SHL,
+            Instruction::I64ShrS => webassembly::I64_SHR_S,
+            Instruction::I64ShrU => webassembly::I64_SHR_U,
+            Instruction::I64Rotl => webassembly::I64_ROTL,
+            Instruction::I64Rotr => webassembly::I64_ROTR,
+            Instruction::F32Abs => webassembly::F32_ABS,
+            Instruction::F32Neg => webassembly::F32_NEG,
+            Instruction::F32Ceil => webassembly::F32_CEIL,
+            Instruction::F32Floor => webassembly::F32_FLOOR,
+            Instruction::F32Trunc => webassembly::F32_TRUNC,
+            Instruction::F32Nearest => webassembly::F32_NEAREST,
+            Instruction::F32Sqrt => webassembly::F32_SQRT,
+            Instruction::F32Add => webassembly::F32_ADD,
+            Instruction::F32Sub => webassembly::F32_SUB,
+            Instruction::F32Mul => webassembly::F32_MUL,
+            Instruction::F32Div => webassembly::F32_DIV,
+            Instruction::F32Min => webassembly::F32_MIN,
+            Instruction::F32Max => webassembly::F32_MAX,
+            Instruction::F32Copysign => webassembly::F32_COPYSIGN,
+            Instruction::F64Abs => webassembly::F64_ABS,
+            Instruction::F64Neg => webassembly::F64_NEG,
+            Instruction::F64Ceil => webassembly::F64_CEIL,
+            Instruction::F64Floor => webassembly::F64_FLOOR,
+            Instruction::F64Trunc => webassembly::F64_TRUNC,
+            Instruction::F64Nearest => webassembly::F64_NEAREST,
+            Instruction::F64Sqrt => webassembly::F64_SQRT,
+            Instruction::F64Add => webassembly::F64_ADD,
+            Instruction::F64Sub => webassembly::F64_SUB,
+            Instruction::F64Mul => webassembly::F64_MUL,
+            Instruction::F64Div => webassembly::F64_DIV,
+            Instruction::F64Min => webassembly::F64_MIN,
+            Instruction::F64Max => webassembly::F64_MAX,
+            Instruction::F64Copysign => webassembly::F64_COPYSIGN,
+            Instruction::I32WrapI64 => webassembly::I32_WRAP_F64, // Misnamed in `webassembly`, this is i32.wrap_i64.
+            Instruction::I32TruncF32S => webassembly::I32_TRUNC_S_F32,
+            Instruction::I32TruncF32U => webassembly::I32_TRUNC_U_F32,
+            Instruction::I32TruncF64S => webassembly::I32_TRUNC_S_F64,
+            Instruction::I32TruncF64U => webassembly::I32_TRUNC_U_F64,
+            Instruction::I64ExtendI32S => webassembly::I64_EXTEND_S_I32,
+            Instruction::I64ExtendI32U => webassembly::I64_EXTEND_U_I32,
+            Instruction::I64TruncF32S => webassembly::I64_TRUNC_S_F32,
+            Instruction::I64TruncF32U => webassembly::I64_TRUNC_U_F32,
+            Instruction::I64TruncF64S => webassembly::I64_TRUNC_S_F64,
+            Instruction::I64TruncF64U => webassembly::I64_TRUNC_U_F64,
+            Instruction::F32ConvertI32S => webassembly::F32_CONVERT_S_I32,
+            Instruction::F32ConvertI32U => webassembly::F32_CONVERT_U_I32,
+            Instruction::F32ConvertI64S => webassembly::F32_CONVERT_S_I64,
+            Instruction::F32ConvertI64U => webassembly::F32_CONVERT_U_I64,
+            Instruction::F32DemoteF64 => webassembly::F32_DEMOTE_F64,
+            Instruction::F64ConvertI32S => webassembly::F64_CONVERT_S_I32,
+            Instruction::F64ConvertI32U => webassembly::F64_CONVERT_U_I32,
+            Instruction::F64ConvertI64S => webassembly::F64_CONVERT_S_I64,
+            Instruction::F64ConvertI64U => webassembly::F64_CONVERT_U_I64,
+            Instruction::F64PromoteF32 => webassembly::F64_PROMOTE_F32,
+            Instruction::I32ReinterpretF32 => webassembly::I32_REINTERPRET_F32,
+            Instruction::I64ReinterpretF64 => webassembly::I64_REINTERPRET_F64,
+            Instruction::F32ReinterpretI32 => webassembly::F32_REINTERPRET_I32,
+            Instruction::F64ReinterpretI64 => webassembly::F64_REINTERPRET_I64,
+        }
+    }
+
+    /// The instruction with an opcode, if it has no immediates.
+    pub fn from_opcode(opcode: u8) -> Option<Instruction> {
+        Some(match opcode {
+            webassembly::UNREACHABLE => Instruction::Unreachable,
+            webassembly::NOP => Instruction::Nop,
+            webassembly::ELSE => Instruction::Else,
+            webassembly::END => Instruction::End,
+            webassembly::RETURN => Instruction::Return,
+            webassembly::DROP => Instruction::Drop,
+            webassembly::SELECT => Instruction::Select,
+            webassembly::I32_EQZ => Instruction::I32Eqz,
+            webassembly::I32_EQ => Instruction::I32Eq,
+            webassembly::I32_NE => Instruction::I32Ne,
+            webassembly::I32_LT_S => Instruction::I32LtS,
+            webassembly::I32_LT_U => Instruction::I32LtU,
+            webassembly::I32_GT_S => Instruction::I32GtS,
+            webassembly::I32_GT_U => Instruction::I32GtU,
+            webassembly::I32_LE_S => Instruction::I32LeS,
+            webassembly::I32_LE_U => Instruction::I32LeU,
+            webassembly::I32_GE_S => Instruction::I32GeS,
+            webassembly::I32_GE_U => Instruction::I32GeU,
+            webassembly::I64_EQZ => Instruction::I64Eqz,
+            webassembly::I64_EQ => Instruction::I64Eq,
+            webassembly::I64_NE => Instruction::I64Ne,
+            webassembly::I64_LT_S => Instruction::I64LtS,
+            webassembly::I64_LT_U => Instruction::I64LtU,
+            webassembly::I64_GT_S => Instruction::I64GtS,
+            webassembly::I64_GT_U => Instruction::I64GtU,
+            webassembly::I64_LE_S => Instruction::I64LeS,
+            webassembly::I64_LE_U => Instruction::I64LeU,
+            webassembly::I64_GE_S => Instruction::I64GeS,
+            webassembly::I64_GE_U => Instruction::I64GeU,
+            webassembly::F32_EQ => Instruction::F32Eq,
+            webassembly::F32_NE => Instruction::F32Ne,
+            webassembly::F32_LT => Instruction::F32Lt,
+            webassembly::F32_GT => Instruction::F32Gt,
+            webassembly::F32_LE => Instruction::F32Le,
+            webassembly::F32_GE => Instruction::F32Ge,
+            webassembly::F64_EQ => Instruction::F64Eq,
+            webassembly::F64_NE => Instruction::F64Ne,
+            webassembly::F64_LT => Instruction::F64Lt,
+            webassembly::F64_GT => Instruction::F64Gt,
+            webassembly::F64_LE => Instruction::F64Le,
+            webassembly::F64_GE => Instruction::F64Ge,
+            webassembly::I32_CLZ => Instruction::I32Clz,
+            webassembly::I32_CTZ => Instruction::I32Ctz,
+            webassembly::I32_POPCNT => Instruction::I32Popcnt,
+            webassembly::I32_ADD => Instruction::I32Add,
+            webassembly::I32_SUB => Instruction::I32Sub,
+            webassembly::I32_MUL => Instruction::I32Mul,
+            webassembly::I32_DIV_S => Instruction::I32DivS,
+            webassembly::I32_DIV_U => Instruction::I32DivU,
+            webassembly::I32_REM_S => Instruction::I32RemS,
+            webassembly::I32_REM_U => Instruction::I32RemU,
+            webassembly::I32_AND => Instruction::I32And,
+            webassembly::I32_OR => Instruction::I32Or,
+            webassembly::I32_XOR => Instruction::I32Xor,
+            webassembly::I32_SHL => Instruction::I32Shl,
+            webassembly::I32_SHR_S => Instruction::I32ShrS,
+            webassembly::I32_SHR_U => Instruction::I32ShrU,
+            webassembly::I32_ROTL => Instruction::I32Rotl,
+            webassembly::I32_ROTR => Instruction::I32Rotr,
+            webassembly::I64_CLZ => Instruction::I64Clz,
+            webassembly::I64_CTZ => Instruction::I64Ctz,
+            webassembly::I64_POPCNT => Instruction::I64Popcnt,
+            webassembly::I64_ADD => Instruction::I64Add,
+            webassembly::I64_SUB => Instruction::I64Sub,
+            webassembly::I64_MUL => Instruction::I64Mul,
+            webassembly::I64_DIV_S => Instruction::I64DivS,
+            webassembly::I64_DIV_U => Instruction::I64DivU,
+            webassembly::I64_REM_S => Instruction::I64RemS,
+            webassembly::I64_REM_U => Instruction::I64RemU,
+            webassembly::I64_AND => Instruction::I64And,
+            webassembly::I64_OR => Instruction::I64Or,
+            webassembly::I64_XOR => Instruction::I64Xor,
+            webassembly::I64_SHL => Instruction::I64Shl,
+            webassembly::I64_SHR_S => Instruction::I64ShrS,
+            webassembly::I64_SHR_U => Instruction::I64ShrU,
+            webassembly::I64_ROTL => Instruction::I64Rotl,
+            webassembly::I64_ROTR => Instruction::I64Rotr,
+            webassembly::F32_ABS => Instruction::F32Abs,
+            webassembly::F32_NEG => Instruction::F32Neg,
+            webassembly::F32_CEIL => Instruction::F32Ceil,
+            webassembly::F32_FLOOR => Instruction::F32Floor,
+            webassembly::F32_TRUNC => Instruction::F32Trunc,
+            webassembly::F32_NEAREST => Instruction::F32Nearest,
+            webassembly::F32_SQRT => Instruction::F32Sqrt,
+            webassembly::F32_ADD => Instruction::F32Add,
+            webassembly::F32_SUB => Instruction::F32Sub,
+            webassembly::F32_MUL => Instruction::F32Mul,
+            webassembly::F32_DIV => Instruction::F32Div,
+            webassembly::F32_MIN => Instruction::F32Min,
+            webassembly::F32_MAX => Instruction::F32Max,
+            webassembly::F32_COPYSIGN => Instruction::F32Copysign,
+            webassembly::F64_ABS => Instruction::F64Abs,
+            webassembly::F64_NEG => Instruction::F64Neg,
+            webassembly::F64_CEIL => Instruction::F64Ceil,
+            webassembly::F64_FLOOR => Instruction::F64Floor,
+            webassembly::F64_TRUNC => Instruction::F64Trunc,
+            webassembly::F64_NEAREST => Instruction::F64Nearest,
+            webassembly::F64_SQRT => Instruction::F64Sqrt,
+            webassembly::F64_ADD => Instruction::F64Add,
+            webassembly::F64_SUB => Instruction::F64Sub,
+            webassembly::F64_MUL => Instruction::F64Mul,
+            webassembly::F64_DIV => Instruction::F64Div,
+            webassembly::F64_MIN => Instruction::F64Min,
+            webassembly::F64_MAX => Instruction::F64Max,
+            webassembly::F64_COPYSIGN => Instruction::F64Copysign,
+            webassembly::I32_WRAP_F64 => Instruction::I32WrapI64, // Misnamed in `webassembly`, this is i32.wrap_i64.
+            webassembly::I32_TRUNC_S_F32 => Instruction::I32TruncF32S,
+            webassembly::I32_TRUNC_U_F32 => Instruction::I32TruncF32U,
+            webassembly::I32_TRUNC_S_F64 => Instruction::I32TruncF64S,
+            webassembly::I32_TRUNC_U_F64 => Instruction::I32TruncF64U,
+            webassembly::I64_EXTEND_S_I32 => Instruction::I64ExtendI32S,
+            webassembly::I64_EXTEND_U_I32 => Instruction::I64ExtendI32U,
+            webassembly::I64_TRUNC_S_F32 => Instruction::I64TruncF32S,
+            webassembly::I64_TRUNC_U_F32 => Instruction::I64TruncF32U,
+            webassembly::I64_TRUNC_S_F64 => Instruction::I64TruncF64S,
+            webassembly::I64_TRUNC_U_F64 => Instruction::I64TruncF64U,
+            webassembly::F32_CONVERT_S_I32 => Instruction::F32ConvertI32S,
+            webassembly::F32_CONVERT_U_I32 => Instruction::F32ConvertI32U,
+            webassembly::F32_CONVERT_S_I64 => Instruction::F32ConvertI64S,
+            webassembly::F32_CONVERT_U_I64 => Instruction::F32ConvertI64U,
+            webassembly::F32_DEMOTE_F64 => Instruction::F32DemoteF64,
+            webassembly::F64_CONVERT_S_I32 => Instruction::F64ConvertI32S,
+            webassembly::F64_CONVERT_U_I32 => Instruction::F64ConvertI32U,
+            webassembly::F64_CONVERT_S_I64 => Instruction::F64ConvertI64S,
+            webassembly::F64_CONVERT_U_I64 => Instruction::F64ConvertI64U,
+            webassembly::F64_PROMOTE_F32 => Instruction::F64PromoteF32,
+            webassembly::I32_REINTERPRET_F32 => Instruction::I32ReinterpretF32,
+            webassembly::I64_REINTERPRET_F64 => Instruction::I64ReinterpretF64,
+            webassembly::F32_REINTERPRET_I32 => Instruction::F32ReinterpretI32,
+            webassembly::F64_REINTERPRET_I64 => Instruction::F64ReinterpretI64,
+            _ => return None,
+        })
+    }
+
+    /// The load or store with an opcode.
+    pub fn from_memory_opcode(opcode: u8, memarg: MemArg) -> Option<Instruction> {
+        Some(match opcode {
+            webassembly::I32_LOAD => Instruction::I32Load(memarg),
+            webassembly::I64_LOAD => Instruction::I64Load(memarg),
+            webassembly::F32_LOAD => Instruction::F32Load(memarg),
+            webassembly::F64_LOAD => Instruction::F64Load(memarg),
+            webassembly::I32_LOAD8_S => Instruction::I32Load8S(memarg),
+            webassembly::I32_LOAD8_U => Instruction::I32Load8U(memarg),
+            webassembly::I32_LOAD16_S => Instruction::I32Load16S(memarg),
+            webassembly::I32_LOAD16_U => Instruction::I32Load16U(memarg),
+            webassembly::I64_LOAD8_S => Instruction::I64Load8S(memarg),
+            webassembly::I64_LOAD8_U => Instruction::I64Load8U(memarg),
+            webassembly::I64_LOAD16_S => Instruction::I64Load16S(memarg),
+            webassembly::I64_LOAD16_U => Instruction::I64Load16U(memarg),
+            webassembly::I64_LOAD32_S => Instruction::I64Load32S(memarg),
+            webassembly::I64_LOAD32_U => Instruction::I64Load32U(memarg),
+            webassembly::I32_STORE => Instruction::I32Store(memarg),
+            webassembly::I64_STORE => Instruction::I64Store(memarg),
+            webassembly::F32_STORE => Instruction::F32Store(memarg),
+            webassembly::F64_STORE => Instruction::F64Store(memarg),
+            webassembly::I32_STORE8 => Instruction::I32Store8(memarg),
+            webassembly::I32_STORE16 => Instruction::I32Store16(memarg),
+            webassembly::I64_STORE8 => Instruction::I64Store8(memarg),
+            webassembly::I64_STORE16 => Instruction::I64Store16(memarg),
+            webassembly::I64_STORE32 => Instruction::I64Store32(memarg),
+            _ => return None,
+        })
+    }
+
+    /// The mnemonic of the instruction in the text format.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Instruction::Unreachable => "unreachable",
+            Instruction::Nop => "nop",
+            Instruction::Block(_) => "block",
+            Instruction::Loop(_) => "loop",
+            Instruction::If(_) => "if",
+            Instruction::Else => "else",
+            Instruction::End => "end",
+            Instruction::Br(_) => "br",
+            Instruction::BrIf(_) => "br_if",
+            Instruction::BrTable(..) => "br_table",
+            Instruction::Return => "return",
+            Instruction::Call(_) => "call",
+            Instruction::CallIndirect { .. } => "call_indirect",
+            Instruction::Drop => "drop",
+            Instruction::Select => "select",
+            Instruction::LocalGet(_) => "local.get",
+            Instruction::LocalSet(_) => "local.set",
+            Instruction::LocalTee(_) => "local.tee",
+            Instruction::GlobalGet(_) => "global.get",
+            Instruction::GlobalSet(_) => "global.set",
+            Instruction::I32Load(_) => "i32.load",
+            Instruction::I64Load(_) => "i64.load",
+            Instruction::F32Load(_) => "f32.load",
+            Instruction::F64Load(_) => "f64.load",
+            Instruction::I32Load8S(_) => "i32.load8_s",
+            Instruction::I32Load8U(_) => "i32.load8_u",
+            Instruction::I32Load16S(_) => "i32.load16_s",
+            Instruction::I32Load16U(_) => "i32.load16_u",
+            Instruction::I64Load8S(_) => "i64.load8_s",
+            Instruction::I64Load8U(_) => "i64.load8_u",
+            Instruction::I64Load16S(_) => "i64.load16_s",
+            Instruction::I64Load16U(_) => "i64.load16_u",
+            Instruction::I64Load32S(_) => "i64.load32_s",
+            Instruction::I64Load32U(_) => "i64.load32_u",
+            Instruction::I32Store(_) => "i32.store",
+            Instruction::I64Store(_) => "i64.store",
+            Instruction::F32Store(_) => "f32.store",
+            Instruction::F64Store(_) => "f64.store",
+            Instruction::I32Store8(_) => "i32.store8",
+            Instruction::I32Store16(_) => "i32.store16",
+            Instruction::I64Store8(_) => "i64.store8",
+            Instruction::I64Store16(_) => "i64.store16",
+            Instruction::I64Store32(_) => "i64.store32",
+            Instruction::MemorySize => "memory.size",
+            Instruction::MemoryGrow => "memory.grow",
+            Instruction::I32Const(_) => "i32.const",
+            Instruction::I64Const(_) => "i64.const",
+            Instruction::F32Const(_) => "f32.const",
+            Instruction::F64Const(_) => "f64.const",
+            Instruction::I32Eqz => "i32.eqz",
+            Instruction::I32Eq => "i32.eq",
+            Instruction::I32Ne => "i32.ne",
+            Instruction::I32LtS => "i32.lt_s",
+            Instruction::I32LtU => "i32.lt_u",
+            Instruction::I32GtS => "i32.gt_s",
+            Instruction::I32GtU => "i32.gt_u",
+            Instruction::I32LeS => "i32.le_s",
+            Instruction::I32LeU => "i32.le_u",
+            Instruction::I32GeS => "i32.ge_s",
+            Instruction::I32GeU => "i32.ge_u",
+            Instruction::I64Eqz => "i64.eqz",
+            Instruction::I64Eq => "i64.eq",
+            Instruction::I64Ne => "i64.ne",
+            Instruction::I64LtS => "i64.lt_s",
+            Instruction::I64LtU => "i64.lt_u",
+            Instruction::I64GtS => "i64.gt_s",
+            Instruction::I64GtU => "i64.gt_u",
+            Instruction::I64LeS => "i64.le_s",
+            Instruction::I64LeU => "i64.le_u",
+            Instruction::I64GeS => "i64.ge_s",
+            Instruction::I64GeU => "i64.ge_u",
+            Instruction::F32Eq => "f32.eq",
+            Instruction::F32Ne => "f32.ne",
+            Instruction::F32Lt => "f32.lt",
+            Instruction::F32Gt => "f32.gt",
+            Instruction::F32Le => "f32.le",
+            Instruction::F32Ge => "f32.ge",
+            Instruction::F64Eq => "f64.eq",
+            Instruction::F64Ne => "f64.ne",
+            Instruction::F64Lt => "f64.lt",
+            Instruction::F64Gt => "f64.gt",
+            Instruction::F64Le => "f64.le",
+            Instruction::F64Ge => "f64.ge",
+            Instruction::I32Clz => "i32.clz",
+            Instruction::I32Ctz => "i32.ctz",
+            Instruction::I32Popcnt => "i32.popcnt",
+            Instruction::I32Add => "i32.add",
+            Instruction::I32Sub => "i32.sub",
+            Instruction::I32Mul => "i32.mul",
+            Instruction::I32DivS => "i32.div_s",
+            Instruction::I32DivU => "i32.div_u",
+            Instruction::I32RemS => "i32.rem_s",
+            Instruction::I32RemU => "i32.rem_u",
+            Instruction::I32And => "i32.and",
+            Instruction::I32Or => "i32.or",
+            Instruction::I32Xor => "i32.xor",
+            Instruction::I32Shl => "i32.shl",
+            Instruction::I32ShrS => "i32.shr_s",
+            Instruction::I32ShrU => "i32.shr_u",
+            Instruction::I32Rotl => "i32.rotl",
+            Instruction::I32Rotr => "i32.rotr",
+            Instruction::I64Clz => "i64.clz",
+            Instruction::I64Ctz => "i64.ctz",
+            Instruction::I64Popcnt => "i64.popcnt",
+            Instruction::I64Add => "i64.add",
+            Instruction::I64Sub => "i64.sub",
+            Instruction::I64Mul => "i64.mul",
+            Instruction::I64DivS => "i64.div_s",
+            Instruction::I64DivU => "i64.div_u",
+            Instruction::I64RemS => "i64.rem_s",
+            Instruction::I64RemU => "i64.rem_u",
+            Instruction::I64And => "i64.and",
+            Instruction::I64Or => "i64.or",
+            Instruction::I64Xor => "i64.xor",
+            Instruction::I64Shl => "i64.shl",
+            Instruction::I64ShrS => "i64.shr_s",
+            Instruction::I64ShrU => "i64.shr_u",
+            Instruction::I64Rotl => "i64.rotl",
+            Instruction::I64Rotr => "i64.rotr",
+            Instruction::F32Abs => "f32.abs",
+            Instruction::F32Neg => "f32.neg",
+            Instruction::F32Ceil => "f32.ceil",
+            Instruction::F32Floor => "f32.floor",
+            Instruction::F32Trunc => "f32.trunc",
+            Instruction::F32Nearest => "f32.nearest",
+            Instruction::F32Sqrt => "f32.sqrt",
+            Instruction::F32Add => "f32.add",
+            Instruction::F32Sub => "f32.sub",
+            Instruction::F32Mul => "f32.mul",
+            Instruction::F32Div => "f32.div",
+            Instruction::F32Min => "f32.min",
+            Instruction::F32Max => "f32.max",
+            Instruction::F32Copysign => "f32.copysign",
+            Instruction::F64Abs => "f64.abs",
+            Instruction::F64Neg => "f64.neg",
+            Instruction::F64Ceil => "f64.ceil",
+            Instruction::F64Floor => "f64.floor",
+            Instruction::F64Trunc => "f64.trunc",
+            Instruction::F64Nearest => "f64.nearest",
+            Instruction::F64Sqrt => "f64.sqrt",
+            Instruction::F64Add => "f64.add",
+            Instruction::F64Sub => "f64.sub",
+            Instruction::F64Mul => "f64.mul",
+            Instruction::F64Div => "f64.div",
+            Instruction::F64Min => "f64.min",
+            Instruction::F64Max => "f64.max",
+            Instruction::F64Copysign => "f64.copysign",
+            Instruction::I32WrapI64 => "i32.wrap_i64",
+            Instruction::I32TruncF32S => "i32.trunc_f32_s",
+            Instruction::I32TruncF32U => "i32.trunc_f32_u",
+            Instruction::I32TruncF64S => "i32.trunc_f64_s",
+            Instruction::I32TruncF64U => "i32.trunc_f64_u",
+            Instruction::I64ExtendI32S => "i64.extend_i32_s",
+            Instruction::I64ExtendI32U => "i64.extend_i32_u",
+            Instruction::I64TruncF32S => "i64.trunc_f32_s",
+            Instruction::I64TruncF32U => "i64.trunc_f32_u",
+            Instruction::I64TruncF64S => "i64.trunc_f64_s",
+            Instruction::I64TruncF64U => "i64.trunc_f64_u",
+            Instruction::F32ConvertI32S => "f32.convert_i32_s",
+            Instruction::F32ConvertI32U => "f32.convert_i32_u",
+            Instruction::F32ConvertI64S => "f32.convert_i64_s",
+            Instruction::F32ConvertI64U => "f32.convert_i64_u",
+            Instruction::F32DemoteF64 => "f32.demote_f64",
+            Instruction::F64ConvertI32S => "f64.convert_i32_s",
+            Instruction::F64ConvertI32U => "f64.convert_i32_u",
+            Instruction::F64ConvertI64S => "f64.convert_i64_s",
+            Instruction::F64ConvertI64U => "f64.convert_i64_u",
+            Instruction::F64PromoteF32 => "f64.promote_f32",
+            Instruction::I32ReinterpretF32 => "i32.reinterpret_f32",
+            Instruction::I64ReinterpretF64 => "i64.reinterpret_f64",
+            Instruction::F32ReinterpretI32 => "f32.reinterpret_i32",
+            Instruction::F64ReinterpretI64 => "f64.reinterpret_i64",
+        }
+    }
+
+    /// The immediates of a load or store.
+    pub fn memarg(&self) -> Option<MemArg> {
+        match self {
+            Instruction::I32Load(memarg)
+            | Instruction::I64Load(memarg)
+            | Instruction::F32Load(memarg)
+            | Instruction::F64Load(memarg)
+            | Instruction::I32Load8S(memarg)
+            | Instruction::I32Load8U(memarg)
+            | Instruction::I32Load16S(memarg)
+            | Instruction::I32Load16U(memarg)
+            | Instruction::I64Load8S(memarg)
+            | Instruction::I64Load8U(memarg)
+            | Instruction::I64Load16S(memarg)
+            | Instruction::I64Load16U(memarg)
+            | Instruction::I64Load32S(memarg)
+            | Instruction::I64Load32U(memarg)
+            | Instruction::I32Store(memarg)
+            | Instruction::I64Store(memarg)
+            | Instruction::F32Store(memarg)
+            | Instruction::F64Store(memarg)
+            | Instruction::I32Store8(memarg)
+            | Instruction::I32Store16(memarg)
+            | Instruction::I64Store8(memarg)
+            | Instruction::I64Store16(memarg)
+            | Instruction::I64Store32(memarg) => Some(*memarg),
+            _ => None,
+        }
+    }
+
+    /// The alignment of a load or store that matches the size of the access, as a
+    /// power of two.
+    pub fn natural_align(&self) -> Option<u32> {
+        match self {
+            Instruction::I32Load8S(_)
+            | Instruction::I32Load8U(_)
+            | Instruction::I64Load8S(_)
+            | Instruction::I64Load8U(_)
+            | Instruction::I32Store8(_)
+            | Instruction::I64Store8(_) => Some(0),
+            Instruction::I32Load16S(_)
+            | Instruction::I32Load16U(_)
+            | Instruction::I64Load16S(_)
+            | Instruction::I64Load16U(_)
+            | Instruction::I32Store16(_)
+            | Instruction::I64Store16(_) => Some(1),
+            Instruction::I32Load(_)
+            | Instruction::F32Load(_)
+            | Instruction::I64Load32S(_)
+            | Instruction::I64Load32U(_)
+            | Instruction::I32Store(_)
+            | Instruction::F32Store(_)
+            | Instruction::I64Store32(_) => Some(2),
+            Instruction::I64Load(_) | Instruction::F64Load(_) | Instruction::I64Store(_) | Instruction::F64Store(_) => {
+                Some(3)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for NumType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NumType::I32 => write!(f, "i32"),
+            NumType::I64 => write!(f, "i64"),
+            NumType::F32 => write!(f, "f32"),
+            NumType::F64 => write!(f, "f64"),
+        }
+    }
+}
+
+fn nan_text(negative: bool, payload: u64, canonical: u64) -> String {
+    let sign = if negative { "-" } else { "" };
+    if payload == canonical {
+        format!("{}nan", sign)
+    } else {
+        format!("{}nan:0x{:x}", sign, payload)
+    }
+}
+
+/// A float as the text format writes it, the shortest decimal that reads back as
+/// the same value. NaNs keep their payload unless it's the canonical one.
+pub fn f32_text(x: f32) -> String {
+    if x.is_nan() {
+        nan_text(x.is_sign_negative(), u64::from(x.to_bits() & 0x7f_ffff), 0x40_0000)
+    } else if x.is_infinite() {
+        if x < 0.0 { "-inf" } else { "inf" }.to_string()
+    } else {
+        format!("{:?}", x)
+    }
+}
+
+pub fn f64_text(x: f64) -> String {
+    if x.is_nan() {
+        nan_text(x.is_sign_negative(), x.to_bits() & 0xf_ffff_ffff_ffff, 0x8_0000_0000_0000)
+    } else if x.is_infinite() {
+        if x < 0.0 { "-inf" } else { "inf" }.to_string()
+    } else {
+        format!("{:?}", x)
+    }
+}
+
+/// The instruction in the text format, referring to everything by index.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())?;
+
+        match self {
+            Instruction::Block(block_type) | Instruction::Loop(block_type) | Instruction::If(block_type) => {
+                match block_type {
+                    BlockType::Value(ty) => write!(f, " (result {})", ty),
+                    BlockType::Empty => Ok(()),
+                }
+            }
+            Instruction::Br(index)
+            | Instruction::BrIf(index)
+            | Instruction::Call(index)
+            | Instruction::LocalGet(index)
+            | Instruction::LocalSet(index)
+            | Instruction::LocalTee(index)
+            | Instruction::GlobalGet(index)
+            | Instruction::GlobalSet(index) => write!(f, " {}", index),
+            Instruction::BrTable(labels, default) => {
+                for label in labels.iter() {
+                    write!(f, " {}", label)?;
+                }
+                write!(f, " {}", default)
+            }
+            Instruction::CallIndirect { type_index, table } => {
+                if *table != 0 {
+                    write!(f, " {}", table)?;
+                }
+                write!(f, " (type {})", type_index)
+            }
+            Instruction::I32Const(n) => write!(f, " {}", n),
+            Instruction::I64Const(n) => write!(f, " {}", n),
+            Instruction::F32Const(x) => write!(f, " {}", f32_text(*x)),
+            Instruction::F64Const(x) => write!(f, " {}", f64_text(*x)),
+            instruction => {
+                if let Some(memarg) = instruction.memarg() {
+                    if memarg.offset != 0 {
+                        write!(f, " offset={}", memarg.offset)?;
+                    }
+                    if instruction.natural_align() != Some(memarg.align) {
+                        write!(f, " align={}", 1u64 << memarg.align.min(63))?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Encoded for Instruction {
+    fn encode(&self) -> Vec<u8> {
+        let mut encoded: Vec<u8> = vec![self.opcode()];
+
+        match self {
+            Instruction::Block(block_type) | Instruction::Loop(block_type) | Instruction::If(block_type) => {
+                encoded.push(block_type.code());
+            }
+            Instruction::Br(index)
+            | Instruction::BrIf(index)
+            | Instruction::Call(index)
+            | Instruction::LocalGet(index)
+            | Instruction::LocalSet(index)
+            | Instruction::LocalTee(index)
+            | Instruction::GlobalGet(index)
+            | Instruction::GlobalSet(index) => encoded.extend_from_slice(&index.to_wasm_bytes()),
+            Instruction::BrTable(labels, default) => {
+                encoded.extend_from_slice(&labels.len().to_wasm_bytes());
+                for label in labels.iter() {
+                    encoded.extend_from_slice(&label.to_wasm_bytes());
+                }
+                encoded.extend_from_slice(&default.to_wasm_bytes());
+            }
+            Instruction::CallIndirect { type_index, table } => {
+                encoded.extend_from_slice(&type_index.to_wasm_bytes());
+                encoded.extend_from_slice(&table.to_wasm_bytes());
+            }
+            // The memory index, which is always 0 in the MVP.
+            Instruction::MemorySize | Instruction::MemoryGrow => encoded.push(0x00),
+            // Integers are signed LEB128, floats their little endian bits.
+            Instruction::I32Const(n) => encoded.extend_from_slice(&n.to_wasm_bytes()),
+            Instruction::I64Const(n) => encoded.extend_from_slice(&n.to_wasm_bytes()),
+            Instruction::F32Const(x) => encoded.extend_from_slice(&x.to_le_bytes()),
+            Instruction::F64Const(x) => encoded.extend_from_slice(&x.to_le_bytes()),
+            instruction => {
+                if let Some(memarg) = instruction.memarg() {
+                    encoded.extend_from_slice(&memarg.align.to_wasm_bytes());
+                    encoded.extend_from_slice(&memarg.offset.to_wasm_bytes());
+                }
+            }
+        }
+
+        encoded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indices_of_128_and_more_take_several_bytes() {
+        assert_eq!(Instruction::LocalGet(127).encode(), [0x20, 0x7f]);
+        assert_eq!(Instruction::LocalGet(128).encode(), [0x20, 0x80, 0x01]);
+        assert_eq!(Instruction::Call(300).encode(), [0x10, 0xac, 0x02]);
+        assert_eq!(Instruction::GlobalSet(16384).encode(), [0x24, 0x80, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn i32_constants_are_signed() {
+        assert_eq!(Instruction::I32Const(-1).encode(), [0x41, 0x7f]);
+        assert_eq!(Instruction::I32Const(-64).encode(), [0x41, 0x40]);
+        assert_eq!(Instruction::I32Const(-65).encode(), [0x41, 0xbf, 0x7f]);
+        assert_eq!(Instruction::I32Const(i32::MIN).encode(), [0x41, 0x80, 0x80, 0x80, 0x80, 0x78]);
+    }
+
+    #[test]
+    fn i32_constants_from_64_need_a_byte_for_the_sign() {
+        assert_eq!(Instruction::I32Const(63).encode(), [0x41, 0x3f]);
+        assert_eq!(Instruction::I32Const(64).encode(), [0x41, 0xc0, 0x00]);
+        assert_eq!(Instruction::I32Const(155).encode(), [0x41, 0x9b, 0x01]);
+        assert_eq!(Instruction::I32Const(i32::MAX).encode(), [0x41, 0xff, 0xff, 0xff, 0xff, 0x07]);
+    }
+}
@@ -0,0 +1,678 @@
+//! Renders an encoded module in the WebAssembly text format, for reading the
+//! code the compiler generates.
+//!
+//! Functions, locals and globals are referred to by their names where the
+//! builder was given one and by index otherwise. Names are turned into valid
+//! identifiers, so the output reads back into the same module with any wat
+//! tooling, except for custom sections, which have no text form and are only
+//! listed in comments.
+
+use std::collections::HashSet;
+
+use super::*;
+
+/// How function bodies are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Style {
+    /// One instruction per line, in the order they are encoded.
+    Flat,
+    /// Instructions as s-expressions holding the instructions computing their
+    /// operands, and blocks holding their bodies.
+    Folded,
+}
+
+/// The characters an identifier may have besides ASCII letters and digits.
+const ID_CHARS: &str = "!#$%&'*+-./:<=>?@\\^_`|~";
+
+/// The identifiers of the first `count` entries of an index space, `None` for
+/// the unnamed ones. Characters identifiers can't have become `_`, and names
+/// made the same that way are told apart by a suffix.
+fn identifiers<'n, F>(count: u32, name: F) -> Vec<Option<String>>
+where
+    F: Fn(u32) -> Option<&'n String>,
+{
+    let mut used = HashSet::new();
+
+    (0..count)
+        .map(|index| {
+            let name = name(index)?;
+            let mut base: String = name
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() || ID_CHARS.contains(c) { c } else { '_' })
+                .collect();
+            if base.is_empty() {
+                base.push('_');
+            }
+
+            let mut id = format!("${}", base);
+            let mut suffix = 1;
+            while !used.insert(id.clone()) {
+                id = format!("${}.{}", base, suffix);
+                suffix += 1;
+            }
+            Some(id)
+        })
+        .collect()
+}
+
+/// A string literal, with the bytes that aren't printable ASCII escaped.
+fn string(bytes: &[u8]) -> String {
+    let mut text = String::from("\"");
+    for byte in bytes.iter().copied() {
+        match byte {
+            b'"' | b'\\' => {
+                text.push('\\');
+                text.push(byte as char);
+            }
+            0x20..=0x7e => text.push(byte as char),
+            _ => text.push_str(&format!("\\{:02x}", byte)),
+        }
+    }
+    text.push('"');
+    text
+}
+
+fn limits(limits: &Limits) -> String {
+    match limits.max {
+        Some(max) => format!("{} {}", limits.min, max),
+        None => limits.min.to_string(),
+    }
+}
+
+/// Whether a numeric instruction takes a single operand: a test, a unary
+/// operator or a conversion, whose mnemonics name the type converted from.
+fn is_unary(instruction: &Instruction) -> bool {
+    let operator = instruction.name().split('.').nth(1).unwrap_or("");
+    let mut parts = operator.split('_');
+
+    let unary = ["eqz", "clz", "ctz", "popcnt", "abs", "neg", "ceil", "floor", "trunc", "nearest", "sqrt"];
+    let converts = |from: &str| from.starts_with('i') || from.starts_with('f');
+    parts.next().is_some_and(|operator| unary.contains(&operator)) || parts.next().is_some_and(converts)
+}
+
+/// An instruction of a folded body.
+enum Node<'i> {
+    /// An instruction after the nodes computing its operands.
+    Plain(&'i Instruction, Vec<Node<'i>>),
+    /// A `block` or `loop`.
+    Block(&'i Instruction, Vec<Node<'i>>),
+    If {
+        opener: &'i Instruction,
+        condition: Vec<Node<'i>>,
+        then: Vec<Node<'i>>,
+        otherwise: Option<Vec<Node<'i>>>,
+    },
+}
+
+/// A block being folded, or the function body itself.
+struct Open<'i> {
+    /// The `block`, `loop` or `if` starting the block, `None` for the body.
+    opener: Option<&'i Instruction>,
+    /// The number of values the block results in.
+    results: usize,
+    condition: Vec<Node<'i>>,
+    /// The nodes before the `else` of an `if`.
+    then: Option<Vec<Node<'i>>>,
+    /// The nodes so far, with the number of values each leaves on the stack.
+    nodes: Vec<(Node<'i>, usize)>,
+}
+
+impl<'i> Open<'i> {
+    fn new(opener: Option<&'i Instruction>, results: usize, condition: Vec<Node<'i>>) -> Self {
+        Open {
+            opener,
+            results,
+            condition,
+            then: None,
+            nodes: Vec::new(),
+        }
+    }
+
+    /// The number of values a branch to the block's label takes, a `loop` is
+    /// branched to at its start.
+    fn label_arity(&self) -> usize {
+        match self.opener {
+            Some(Instruction::Loop(_)) => 0,
+            _ => self.results,
+        }
+    }
+
+    /// The nodes computing the top `count` values of the stack, if each of the
+    /// last `count` nodes computes exactly one. Otherwise nothing is folded and
+    /// the nodes stay where they are, which runs them in the same order.
+    fn operands(&mut self, count: usize) -> Vec<Node<'i>> {
+        let start = match self.nodes.len().checked_sub(count) {
+            Some(start) if self.nodes[start..].iter().all(|(_, values)| *values == 1) => start,
+            _ => return Vec::new(),
+        };
+        self.nodes.drain(start..).map(|(node, _)| node).collect()
+    }
+
+    /// The node of a block once it's ended, `None` for the body.
+    fn close(self) -> Option<Node<'i>> {
+        let nodes = self.nodes.into_iter().map(|(node, _)| node).collect();
+        Some(match self.opener? {
+            opener @ Instruction::If(_) => {
+                let (then, otherwise) = match self.then {
+                    Some(then) => (then, Some(nodes)),
+                    None => (nodes, None),
+                };
+                Node::If {
+                    opener,
+                    condition: self.condition,
+                    then,
+                    otherwise,
+                }
+            }
+            opener => Node::Block(opener, nodes),
+        })
+    }
+}
+
+/// Ends the innermost block, adding its node to the enclosing one.
+fn end(open: &mut Vec<Open>) {
+    if let Some(block) = open.pop() {
+        let results = block.results;
+        if let (Some(node), Some(parent)) = (block.close(), open.last_mut()) {
+            parent.nodes.push((node, results));
+        }
+    }
+}
+
+fn block_results(instruction: &Instruction) -> usize {
+    match instruction {
+        Instruction::Block(BlockType::Value(_))
+        | Instruction::Loop(BlockType::Value(_))
+        | Instruction::If(BlockType::Value(_)) => 1,
+        _ => 0,
+    }
+}
+
+/// Declares parameters or locals in order, each named one on its own and runs
+/// of unnamed ones together, every declaration with a leading space.
+fn declarations(keyword: &str, types: &[u8], ids: &[Option<String>]) -> Vec<String> {
+    let mut declarations = Vec::new();
+    let mut unnamed: Vec<&str> = Vec::new();
+
+    for (i, ty) in types.iter().enumerate() {
+        match ids.get(i) {
+            Some(Some(id)) => {
+                if !unnamed.is_empty() {
+                    declarations.push(format!(" ({} {})", keyword, unnamed.join(" ")));
+                    unnamed.clear();
+                }
+                declarations.push(format!(" ({} {} {})", keyword, id, value_type(*ty)));
+            }
+            _ => unnamed.push(value_type(*ty)),
+        }
+    }
+    if !unnamed.is_empty() {
+        declarations.push(format!(" ({} {})", keyword, unnamed.join(" ")));
+    }
+
+    declarations
+}
+
+/// Longer nodes are split over several lines.
+const LINE_WIDTH: usize = 100;
+
+struct Printer<'m> {
+    module: &'m EncodedModule,
+    style: Style,
+    /// The type index of every function, the imported ones first.
+    function_types: Vec<u32>,
+    functions: Vec<Option<String>>,
+    globals: Vec<Option<String>>,
+    /// The identifiers of the locals of the function being written.
+    locals: Vec<Option<String>>,
+    indent: usize,
+    lines: Vec<String>,
+}
+
+impl<'m> Printer<'m> {
+    fn line(&mut self, text: String) {
+        self.lines.push(format!("{}{}", "  ".repeat(self.indent), text));
+    }
+
+    /// Closes the s-expression on the last line.
+    fn close(&mut self) {
+        if let Some(line) = self.lines.last_mut() {
+            line.push(')');
+        }
+    }
+
+    fn reference(ids: &[Option<String>], index: u32) -> String {
+        match ids.get(index as usize) {
+            Some(Some(id)) => id.clone(),
+            _ => index.to_string(),
+        }
+    }
+
+    /// The identifier of an entity in its own definition, or its index in a comment.
+    fn definition(ids: &[Option<String>], index: usize) -> String {
+        match ids.get(index) {
+            Some(Some(id)) => id.clone(),
+            _ => format!("(;{};)", index),
+        }
+    }
+
+    fn signature(&self, type_index: u32) -> Option<&'m TypeSignature> {
+        self.module.type_section.as_ref()?.signatures.get(type_index as usize)
+    }
+
+    fn parameters(&self, type_index: u32) -> &'m [TypeSignature] {
+        self.signature(type_index).and_then(|s| s.inputs.as_deref()).unwrap_or(&[])
+    }
+
+    fn results(&self, type_index: u32) -> &'m [TypeSignature] {
+        self.signature(type_index).and_then(|s| s.outputs.as_deref()).unwrap_or(&[])
+    }
+
+    /// The `param` and `result` declarations of a signature, naming the
+    /// parameters that have identifiers.
+    fn function_type(&self, type_index: u32, ids: &[Option<String>]) -> String {
+        let parameters: Vec<u8> = self.parameters(type_index).iter().map(|p| p.type_sig).collect();
+        let mut text = declarations("param", &parameters, ids).join("");
+
+        let results: Vec<&str> = self.results(type_index).iter().map(|r| value_type(r.type_sig)).collect();
+        if !results.is_empty() {
+            text.push_str(&format!(" (result {})", results.join(" ")));
+        }
+
+        text
+    }
+
+    fn global_type(value_type_code: u8, mutable: bool) -> String {
+        if mutable {
+            format!("(mut {})", value_type(value_type_code))
+        } else {
+            value_type(value_type_code).to_string()
+        }
+    }
+
+    /// An instruction in flat form, referring to functions, locals and globals by name.
+    fn instruction(&self, instruction: &Instruction) -> String {
+        let name = instruction.name();
+        match instruction {
+            Instruction::Call(index) => format!("{} {}", name, Self::reference(&self.functions, *index)),
+            Instruction::LocalGet(index) | Instruction::LocalSet(index) | Instruction::LocalTee(index) => {
+                format!("{} {}", name, Self::reference(&self.locals, *index))
+            }
+            Instruction::GlobalGet(index) | Instruction::GlobalSet(index) => {
+                format!("{} {}", name, Self::reference(&self.globals, *index))
+            }
+            instruction => instruction.to_string(),
+        }
+    }
+
+    /// The number of values an instruction takes from the stack and leaves on it.
+    fn effect(&self, instruction: &Instruction, open: &[Open]) -> (usize, usize) {
+        let label = |depth: u32| {
+            let index = open.len().checked_sub(1 + depth as usize);
+            index.map_or(0, |index| open[index].label_arity())
+        };
+        let call = |type_index: u32| (self.parameters(type_index).len(), self.results(type_index).len());
+
+        match instruction {
+            Instruction::Br(depth) => (label(*depth), 0),
+            Instruction::BrIf(depth) => (label(*depth) + 1, label(*depth)),
+            Instruction::BrTable(_, default) => (label(*default) + 1, 0),
+            Instruction::Return => (open.first().map_or(0, |body| body.results), 0),
+            Instruction::Call(index) => self.function_types.get(*index as usize).map_or((0, 0), |t| call(*t)),
+            Instruction::CallIndirect { type_index, .. } => {
+                let (parameters, results) = call(*type_index);
+                (parameters + 1, results)
+            }
+            Instruction::Unreachable
+            | Instruction::Nop
+            | Instruction::Block(_)
+            | Instruction::Loop(_)
+            | Instruction::If(_)
+            | Instruction::Else
+            | Instruction::End => (0, 0),
+            Instruction::Drop | Instruction::LocalSet(_) | Instruction::GlobalSet(_) => (1, 0),
+            Instruction::Select => (3, 1),
+            Instruction::LocalGet(_)
+            | Instruction::GlobalGet(_)
+            | Instruction::MemorySize
+            | Instruction::I32Const(_)
+            | Instruction::I64Const(_)
+            | Instruction::F32Const(_)
+            | Instruction::F64Const(_) => (0, 1),
+            Instruction::LocalTee(_) | Instruction::MemoryGrow => (1, 1),
+            instruction if instruction.memarg().is_some() => {
+                if instruction.name().contains(".store") {
+                    (2, 0)
+                } else {
+                    (1, 1)
+                }
+            }
+            instruction if is_unary(instruction) => (1, 1),
+            _ => (2, 1),
+        }
+    }
+
+    fn fold<'i>(&self, instructions: &'i [Instruction], results: usize) -> Vec<Node<'i>> {
+        let mut open = vec![Open::new(None, results, Vec::new())];
+
+        for instruction in instructions.iter() {
+            match instruction {
+                Instruction::Block(_) | Instruction::Loop(_) => {
+                    open.push(Open::new(Some(instruction), block_results(instruction), Vec::new()));
+                }
+                Instruction::If(_) => {
+                    let condition = open.last_mut().map(|block| block.operands(1)).unwrap_or_default();
+                    open.push(Open::new(Some(instruction), block_results(instruction), condition));
+                }
+                Instruction::Else if open.len() > 1 => {
+                    if let Some(block) = open.last_mut() {
+                        let then = block.nodes.drain(..).map(|(node, _)| node).collect();
+                        block.then = Some(then);
+                    }
+                }
+                Instruction::End if open.len() > 1 => end(&mut open),
+                instruction => {
+                    let (operands, values) = self.effect(instruction, &open);
+                    if let Some(block) = open.last_mut() {
+                        let operands = block.operands(operands);
+                        block.nodes.push((Node::Plain(instruction, operands), values));
+                    }
+                }
+            }
+        }
+
+        // Blocks are always ended in a valid body.
+        while open.len() > 1 {
+            end(&mut open);
+        }
+
+        open.pop().map_or_else(Vec::new, |body| body.nodes.into_iter().map(|(node, _)| node).collect())
+    }
+
+    /// A node on a single line, if it has no blocks in it.
+    fn inline(&self, node: &Node) -> Option<String> {
+        match node {
+            Node::Plain(instruction, operands) => {
+                let mut text = format!("({}", self.instruction(instruction));
+                for operand in operands.iter() {
+                    text.push(' ');
+                    text.push_str(&self.inline(operand)?);
+                }
+                text.push(')');
+                Some(text)
+            }
+            _ => None,
+        }
+    }
+
+    fn nodes(&mut self, nodes: &[Node]) {
+        for node in nodes.iter() {
+            self.node(node);
+        }
+    }
+
+    fn node(&mut self, node: &Node) {
+        if let Some(text) = self.inline(node) {
+            if self.indent * 2 + text.len() <= LINE_WIDTH {
+                self.line(text);
+                return;
+            }
+        }
+
+        match node {
+            Node::Plain(instruction, operands) => {
+                self.line(format!("({}", self.instruction(instruction)));
+                self.indent += 1;
+                self.nodes(operands);
+                self.indent -= 1;
+                self.close();
+            }
+            Node::Block(opener, body) => {
+                self.line(format!("({}", self.instruction(opener)));
+                self.indent += 1;
+                self.nodes(body);
+                self.indent -= 1;
+                self.close();
+            }
+            Node::If {
+                opener,
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.line(format!("({}", self.instruction(opener)));
+                self.indent += 1;
+                self.nodes(condition);
+                self.line("(then".to_string());
+                self.indent += 1;
+                self.nodes(then);
+                self.indent -= 1;
+                self.close();
+                if let Some(otherwise) = otherwise {
+                    self.line("(else".to_string());
+                    self.indent += 1;
+                    self.nodes(otherwise);
+                    self.indent -= 1;
+                    self.close();
+                }
+                self.indent -= 1;
+                self.close();
+            }
+        }
+    }
+
+    fn flat(&mut self, instructions: &[Instruction]) {
+        for instruction in instructions.iter() {
+            match instruction {
+                Instruction::Else | Instruction::End => self.indent = self.indent.saturating_sub(1),
+                _ => {}
+            }
+            self.line(self.instruction(instruction));
+            match instruction {
+                Instruction::Block(_) | Instruction::Loop(_) | Instruction::If(_) | Instruction::Else => {
+                    self.indent += 1
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn function(&mut self, index: u32, type_index: u32, body: &CodeBody, names: &Names) {
+        let parameters = self.parameters(type_index).len();
+        let declared: Vec<u8> = body
+            .locals
+            .iter()
+            .flat_map(|local| std::iter::repeat_n(local.local_type, local.count))
+            .collect();
+
+        let count = (parameters + declared.len()) as u32;
+        let local_names = names.locals.get(&index);
+        self.locals = identifiers(count, |local| local_names.and_then(|names| names.get(&local)));
+
+        let id = Self::definition(&self.functions, index as usize);
+        let signature = self.function_type(type_index, &self.locals[..parameters]);
+        self.line(format!("(func {} (type {}){}", id, type_index, signature));
+        self.indent += 1;
+
+        for declaration in declarations("local", &declared, &self.locals[parameters..]) {
+            self.line(declaration.trim_start().to_string());
+        }
+
+        match self.style {
+            Style::Flat => self.flat(&body.instructions),
+            Style::Folded => {
+                let results = self.results(type_index).len();
+                let nodes = self.fold(&body.instructions, results);
+                self.nodes(&nodes);
+            }
+        }
+
+        self.indent -= 1;
+        self.close();
+    }
+
+    fn module(&mut self, names: &Names) {
+        let module = self.module;
+        let imports = module.import_section.as_ref().map_or(&[][..], |section| &section.imports[..]);
+
+        self.line("(module".to_string());
+        self.indent += 1;
+
+        if let Some(section) = &module.type_section {
+            for (i, signature) in section.signatures.iter().enumerate() {
+                let parameters: Vec<&str> = signature.inputs.iter().flatten().map(|p| value_type(p.type_sig)).collect();
+                let results: Vec<&str> = signature.outputs.iter().flatten().map(|r| value_type(r.type_sig)).collect();
+
+                let mut text = format!("(type (;{};) (func", i);
+                if !parameters.is_empty() {
+                    text.push_str(&format!(" (param {})", parameters.join(" ")));
+                }
+                if !results.is_empty() {
+                    text.push_str(&format!(" (result {})", results.join(" ")));
+                }
+                text.push_str("))");
+                self.line(text);
+            }
+        }
+
+        let (mut functions, mut tables, mut memories, mut globals) = (0, 0, 0, 0);
+        for import in imports.iter() {
+            let desc = match &import.desc {
+                ImportDesc::Function(type_index) => {
+                    let id = Self::definition(&self.functions, functions);
+                    functions += 1;
+                    format!("(func {} (type {}){})", id, type_index, self.function_type(*type_index, &[]))
+                }
+                ImportDesc::Table(table) => {
+                    tables += 1;
+                    format!("(table (;{};) {} funcref)", tables - 1, limits(table))
+                }
+                ImportDesc::Memory(memory) => {
+                    memories += 1;
+                    format!("(memory (;{};) {})", memories - 1, limits(memory))
+                }
+                ImportDesc::Global { value_type, mutable } => {
+                    let id = Self::definition(&self.globals, globals);
+                    globals += 1;
+                    format!("(global {} {})", id, Self::global_type(*value_type, *mutable))
+                }
+            };
+            let (module_name, name) = (string(import.module.val.as_bytes()), string(import.name.val.as_bytes()));
+            self.line(format!("(import {} {} {})", module_name, name, desc));
+        }
+
+        let signature_ids = module.function_section.iter().flat_map(|section| section.signature_ids.iter());
+        let bodies = module.code_section.iter().flat_map(|section| section.blocks.iter());
+        for (i, (type_index, body)) in signature_ids.zip(bodies).enumerate() {
+            self.function((functions + i) as u32, *type_index, body, names);
+        }
+
+        if let Some(section) = &module.table_section {
+            for (i, table) in section.tables.iter().enumerate() {
+                self.line(format!("(table (;{};) {} funcref)", tables + i, limits(table)));
+            }
+        }
+
+        if let Some(section) = &module.memory_section {
+            for (i, memory) in section.memories.iter().enumerate() {
+                self.line(format!("(memory (;{};) {})", memories + i, limits(memory)));
+            }
+        }
+
+        if let Some(section) = &module.global_section {
+            for (i, global) in section.globals.iter().enumerate() {
+                let id = Self::definition(&self.globals, globals + i);
+                let ty = Self::global_type(global.value_type, global.mutable);
+                self.line(format!("(global {} {} ({}))", id, ty, self.instruction(&global.init)));
+            }
+        }
+
+        if let Some(section) = &module.export_section {
+            for export in section.exports.iter() {
+                let desc = match export.sig_type {
+                    webassembly::DESC_FUNCTION => format!("func {}", Self::reference(&self.functions, export.index)),
+                    webassembly::DESC_TABLE => format!("table {}", export.index),
+                    webassembly::DESC_MEMORY => format!("memory {}", export.index),
+                    _ => format!("global {}", Self::reference(&self.globals, export.index)),
+                };
+                self.line(format!("(export {} ({}))", string(export.name.val.as_bytes()), desc));
+            }
+        }
+
+        if let Some(section) = &module.start_section {
+            self.line(format!("(start {})", Self::reference(&self.functions, section.function)));
+        }
+
+        if let Some(section) = &module.element_section {
+            for (i, segment) in section.segments.iter().enumerate() {
+                let functions: Vec<String> = segment
+                    .functions
+                    .iter()
+                    .map(|function| Self::reference(&self.functions, *function))
+                    .collect();
+                // Only segments for table 0 can leave out the table and the `func` keyword.
+                let table = if segment.table == 0 {
+                    String::new()
+                } else {
+                    format!(" (table {})", segment.table)
+                };
+                let kind = if segment.table == 0 { "" } else { " func" };
+                self.line(format!(
+                    "(elem (;{};){} (i32.const {}){} {})",
+                    i,
+                    table,
+                    segment.offset as i32,
+                    kind,
+                    functions.join(" ")
+                ));
+            }
+        }
+
+        if let Some(section) = &module.data_section {
+            for (i, segment) in section.segments.iter().enumerate() {
+                let offset = segment.offset as i32;
+                self.line(format!("(data (;{};) (i32.const {}) {})", i, offset, string(&segment.bytes)));
+            }
+        }
+
+        for section in module.custom_sections.iter() {
+            let (name, size) = (string(section.name.as_bytes()), section.content.len());
+            self.line(format!(";; custom section {}, {} bytes", name, size));
+        }
+
+        self.indent -= 1;
+        self.close();
+    }
+}
+
+impl EncodedModule {
+    pub fn wat(&self, names: &Names, style: Style) -> String {
+        let imports = self.import_section.iter().flat_map(|section| section.imports.iter());
+        let mut function_types: Vec<u32> = imports
+            .filter_map(|import| match import.desc {
+                ImportDesc::Function(type_index) => Some(type_index),
+                _ => None,
+            })
+            .collect();
+        function_types.extend(self.function_section.iter().flat_map(|section| section.signature_ids.iter()));
+
+        let globals = self.import_section.as_ref().map_or(0, |section| section.count(webassembly::DESC_GLOBAL))
+            + self.global_section.as_ref().map_or(0, |section| section.globals.len() as u32);
+
+        let mut printer = Printer {
+            module: self,
+            style,
+            functions: identifiers(function_types.len() as u32, |index| names.functions.get(&index)),
+            globals: identifiers(globals, |index| names.globals.get(&index)),
+            function_types,
+            locals: Vec::new(),
+            indent: 0,
+            lines: Vec::new(),
+        };
+        printer.module(names);
+
+        let mut text = printer.lines.join("\n");
+        text.push('\n');
+        text
+    }
+}
@@ -0,0 +1,520 @@
+//! Control flow analysis.
+//!
+//! Builds a control flow graph of every function, method and lambda body, and of
+//! the module level statements, and uses it to find functions that can reach the
+//! end of their body without returning the value they return elsewhere, statements
+//! that can never run, and variables read before every path to the read has
+//! assigned them.
+//!
+//! Conditions folded to a constant only have the edge they take, so `while true`
+//! without a `break` never exits. Assignments in the right hand side of `&&` and
+//! `||`, and inside lambdas, don't count as definite.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::ast::visit::{self, Visitor};
+use crate::ast::*;
+use crate::lint::{Warning, WarningKind};
+use crate::resolve::Resolution;
+
+#[derive(Clone, Debug)]
+pub enum FlowErrorKind {
+    /// A function returning a value can reach the end of its body, the name is
+    /// `None` for lambdas.
+    MissingReturn(Option<String>),
+    /// A variable read before it is definitely assigned, declared at `declaration`.
+    UseBeforeAssignment { name: String, declaration: Span },
+}
+
+#[derive(Clone, Debug)]
+pub struct FlowError {
+    pub kind: FlowErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for FlowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            FlowErrorKind::MissingReturn(Some(name)) => {
+                write!(f, "not all paths through `{}` return a value", name)
+            }
+            FlowErrorKind::MissingReturn(None) => {
+                write!(f, "not all paths through this function return a value")
+            }
+            FlowErrorKind::UseBeforeAssignment { name, .. } => {
+                write!(f, "`{}` is used before it is definitely assigned", name)
+            }
+        }
+    }
+}
+
+/// Analyzes a resolved program, returning the errors and the warnings about
+/// unreachable code, each sorted by position.
+pub fn analyze(program: &Program, resolution: &Resolution) -> (Vec<FlowError>, Vec<Warning>) {
+    let mut bodies = Bodies {
+        resolution,
+        errors: Vec::new(),
+        warnings: Vec::new(),
+    };
+
+    bodies.body(&program.statements, None);
+    bodies.visit_program(program);
+
+    bodies.errors.sort_by_key(|e| e.span.start);
+    bodies.warnings.sort_by_key(|w| w.span.start);
+    (bodies.errors, bodies.warnings)
+}
+
+/// What a body that returns a value is called in errors.
+struct Returning {
+    name: Option<String>,
+    span: Span,
+}
+
+/// Finds every body and analyzes it.
+struct Bodies<'r> {
+    resolution: &'r Resolution,
+    errors: Vec<FlowError>,
+    warnings: Vec<Warning>,
+}
+
+impl<'r> Bodies<'r> {
+    fn body(&mut self, statements: &[Statement], returning: Option<Returning>) {
+        let mut lets = HashMap::new();
+        collect_lets(statements, &mut lets);
+
+        let mut builder = Builder {
+            resolution: self.resolution,
+            lets: &lets,
+            cfg: Cfg::default(),
+            current: BlockId(0),
+            loops: Vec::new(),
+            lists: Vec::new(),
+            conditional: false,
+        };
+        builder.cfg.blocks.push(BasicBlock::default());
+        builder.statements(statements);
+
+        let end = builder.current;
+        let lists = builder.lists;
+        let cfg = builder.cfg;
+        let reachable = cfg.reachable();
+
+        if let Some(returning) = returning {
+            if reachable[end.0] {
+                self.errors.push(FlowError {
+                    kind: FlowErrorKind::MissingReturn(returning.name),
+                    span: returning.span,
+                });
+            }
+        }
+
+        // Only the first statement of a list that can't run is reported, the
+        // ones after it, and those nested inside it, follow from it.
+        for list in lists.iter() {
+            let starts_reachable = list.first().is_some_and(|(block, _)| reachable[block.0]);
+            if let Some((_, span)) = list.iter().find(|(block, _)| !reachable[block.0]) {
+                if starts_reachable {
+                    self.warnings.push(Warning {
+                        kind: WarningKind::Unreachable,
+                        span: *span,
+                        suggestion: None,
+                    });
+                }
+            }
+        }
+
+        self.errors.extend(cfg.unassigned_uses(&reachable, &lets));
+    }
+
+    fn function(&mut self, name: Option<&Ident>, span: Span, return_type: &Option<TypeAnnotation>, body: &Block) {
+        let returning = (return_type.is_some() || returns_value(&body.statements)).then(|| Returning {
+            name: name.map(|name| name.name.clone()),
+            span: name.map_or(span, |name| name.span),
+        });
+
+        self.body(&body.statements, returning);
+    }
+}
+
+impl<'r> Visitor for Bodies<'r> {
+    fn visit_function(&mut self, function: &FunctionDeclaration) {
+        self.function(Some(&function.name), function.span, &function.return_type, &function.body);
+        visit::walk_function(self, function);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        if let ExpressionKind::Lambda(lambda) = &expression.kind {
+            self.function(None, expression.span, &lambda.return_type, &lambda.body);
+        }
+        visit::walk_expression(self, expression);
+    }
+}
+
+/// Whether a body has a `return` with a value, leaving out nested functions.
+fn returns_value(statements: &[Statement]) -> bool {
+    statements.iter().any(|statement| match &statement.kind {
+        StatementKind::Return(value) => value.is_some(),
+        StatementKind::If(_, body, otherwise) => {
+            returns_value(&body.statements) || otherwise.iter().any(|s| returns_value(std::slice::from_ref(s)))
+        }
+        StatementKind::While(_, body) | StatementKind::Block(body) => returns_value(&body.statements),
+        _ => false,
+    })
+}
+
+/// Collects the variables a body declares, leaving out nested functions, keyed by
+/// the declaring `Ident` with its name and span.
+fn collect_lets(statements: &[Statement], lets: &mut HashMap<NodeId, (String, Span)>) {
+    for statement in statements.iter() {
+        match &statement.kind {
+            StatementKind::Let(variable) => {
+                lets.insert(variable.name.id, (variable.name.name.clone(), variable.name.span));
+            }
+            StatementKind::If(_, body, otherwise) => {
+                collect_lets(&body.statements, lets);
+                if let Some(otherwise) = otherwise {
+                    collect_lets(std::slice::from_ref(otherwise), lets);
+                }
+            }
+            StatementKind::While(_, body) | StatementKind::Block(body) => collect_lets(&body.statements, lets),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BlockId(usize);
+
+#[derive(Debug, Clone, Copy)]
+enum Event {
+    /// A read of the variable declared by the `NodeId`.
+    Use(NodeId, Span),
+    Assign(NodeId),
+}
+
+/// A straight line sequence of reads and assignments, followed by a jump to any
+/// of its successors.
+#[derive(Debug, Default)]
+struct BasicBlock {
+    events: Vec<Event>,
+    successors: Vec<BlockId>,
+}
+
+/// The control flow graph of one body, which is entered at block 0.
+#[derive(Debug, Default)]
+struct Cfg {
+    blocks: Vec<BasicBlock>,
+}
+
+impl Cfg {
+    fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut pending = vec![BlockId(0)];
+
+        while let Some(block) = pending.pop() {
+            if reachable[block.0] {
+                continue;
+            }
+            reachable[block.0] = true;
+            pending.extend(self.blocks[block.0].successors.iter().copied());
+        }
+
+        reachable
+    }
+
+    /// The first read of every variable that some path reaches before an assignment.
+    fn unassigned_uses(&self, reachable: &[bool], lets: &HashMap<NodeId, (String, Span)>) -> Vec<FlowError> {
+        let mut predecessors = vec![Vec::new(); self.blocks.len()];
+        for (index, block) in self.blocks.iter().enumerate() {
+            for successor in block.successors.iter() {
+                predecessors[successor.0].push(index);
+            }
+        }
+
+        // The variables definitely assigned on entry to each block, `None` until
+        // a path to the block has been seen.
+        let mut entry: Vec<Option<HashSet<NodeId>>> = vec![None; self.blocks.len()];
+        entry[0] = Some(HashSet::new());
+
+        let exit = |block: &BasicBlock, assigned: &HashSet<NodeId>| {
+            let mut assigned = assigned.clone();
+            for event in block.events.iter() {
+                if let Event::Assign(id) = event {
+                    assigned.insert(*id);
+                }
+            }
+            assigned
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for index in 1..self.blocks.len() {
+                if !reachable[index] {
+                    continue;
+                }
+
+                let mut assigned: Option<HashSet<NodeId>> = None;
+                for predecessor in predecessors[index].iter() {
+                    if let Some(before) = &entry[*predecessor] {
+                        let after = exit(&self.blocks[*predecessor], before);
+                        assigned = Some(match assigned {
+                            Some(assigned) => assigned.intersection(&after).copied().collect(),
+                            None => after,
+                        });
+                    }
+                }
+
+                if assigned.is_some() && assigned != entry[index] {
+                    entry[index] = assigned;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut reported = HashSet::new();
+        let mut errors = Vec::new();
+
+        for (block, assigned) in self.blocks.iter().zip(entry.iter()) {
+            let mut assigned = match assigned {
+                Some(assigned) => assigned.clone(),
+                None => continue,
+            };
+
+            for event in block.events.iter() {
+                match event {
+                    Event::Assign(id) => {
+                        assigned.insert(*id);
+                    }
+                    Event::Use(id, span) if !assigned.contains(id) => {
+                        if let Some((name, declaration)) = lets.get(id) {
+                            errors.push(FlowError {
+                                kind: FlowErrorKind::UseBeforeAssignment {
+                                    name: name.clone(),
+                                    declaration: *declaration,
+                                },
+                                span: *span,
+                            });
+                        }
+                    }
+                    Event::Use(..) => {}
+                }
+            }
+        }
+
+        // One error per variable, at its first use.
+        errors.sort_by_key(|e| e.span.start);
+        errors.retain(|e| match &e.kind {
+            FlowErrorKind::UseBeforeAssignment { declaration, .. } => reported.insert(declaration.start),
+            _ => true,
+        });
+        errors
+    }
+}
+
+/// The blocks `break` and `continue` jump to.
+struct Loop {
+    header: BlockId,
+    exit: BlockId,
+}
+
+/// Builds the graph of one body.
+struct Builder<'a> {
+    resolution: &'a Resolution,
+    /// The variables the body declares, the only ones whose assignments are tracked.
+    lets: &'a HashMap<NodeId, (String, Span)>,
+    cfg: Cfg,
+    current: BlockId,
+    loops: Vec<Loop>,
+    /// Every statement list with the block each statement starts in.
+    lists: Vec<Vec<(BlockId, Span)>>,
+    /// Inside an expression that may not be evaluated, assignments aren't definite.
+    conditional: bool,
+}
+
+impl<'a> Builder<'a> {
+    fn block(&mut self) -> BlockId {
+        self.cfg.blocks.push(BasicBlock::default());
+        BlockId(self.cfg.blocks.len() - 1)
+    }
+
+    fn edge(&mut self, from: BlockId, to: BlockId) {
+        self.cfg.blocks[from.0].successors.push(to);
+    }
+
+    fn event(&mut self, event: Event) {
+        self.cfg.blocks[self.current.0].events.push(event);
+    }
+
+    /// Ends the current block with a jump to `to`, anything after it starts a
+    /// block nothing jumps to.
+    fn jump(&mut self, to: BlockId) {
+        self.edge(self.current, to);
+        self.current = self.block();
+    }
+
+    /// The variable an identifier reads, if it is one whose assignments are tracked.
+    fn tracked(&self, ident: &Ident) -> Option<NodeId> {
+        self.resolution
+            .references
+            .get(&ident.id)
+            .filter(|declaration| self.lets.contains_key(declaration))
+            .copied()
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        self.lists.push(Vec::new());
+        let list = self.lists.len() - 1;
+
+        for statement in statements.iter() {
+            match &statement.kind {
+                // Declarations don't run, they can't be unreachable.
+                StatementKind::Import(_) | StatementKind::Function(_) => continue,
+                _ => self.lists[list].push((self.current, statement.span)),
+            }
+            self.statement(statement);
+        }
+    }
+
+    /// The value a condition was folded to, if it was.
+    fn constant(condition: &Expression) -> Option<bool> {
+        match &condition.kind {
+            ExpressionKind::Singleton(ValueType::Boolean(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match &statement.kind {
+            StatementKind::Import(_) | StatementKind::Function(_) => {}
+            StatementKind::Let(variable) => {
+                if let Some(value) = &variable.value {
+                    self.visit_expression(value);
+                    self.event(Event::Assign(variable.name.id));
+                }
+            }
+            StatementKind::Class(class) => self.visit_class(class),
+            StatementKind::If(condition, body, otherwise) => {
+                self.visit_expression(condition);
+                let constant = Self::constant(condition);
+
+                let then = self.block();
+                let other = self.block();
+                let join = self.block();
+                if constant != Some(false) {
+                    self.edge(self.current, then);
+                }
+                if constant != Some(true) {
+                    self.edge(self.current, other);
+                }
+
+                self.current = then;
+                self.statements(&body.statements);
+                self.edge(self.current, join);
+
+                self.current = other;
+                if let Some(otherwise) = otherwise {
+                    self.statements(std::slice::from_ref(otherwise));
+                }
+                self.edge(self.current, join);
+
+                self.current = join;
+            }
+            StatementKind::While(condition, body) => {
+                let header = self.block();
+                let entered = self.block();
+                let exit = self.block();
+                self.edge(self.current, header);
+
+                self.current = header;
+                self.visit_expression(condition);
+                let constant = Self::constant(condition);
+                if constant != Some(false) {
+                    self.edge(self.current, entered);
+                }
+                if constant != Some(true) {
+                    self.edge(self.current, exit);
+                }
+
+                self.loops.push(Loop { header, exit });
+                self.current = entered;
+                self.statements(&body.statements);
+                self.edge(self.current, header);
+                self.loops.pop();
+
+                self.current = exit;
+            }
+            StatementKind::Return(value) => {
+                if let Some(value) = value {
+                    self.visit_expression(value);
+                }
+                // Nothing follows a return within the body.
+                self.current = self.block();
+            }
+            StatementKind::Break => {
+                if let Some(exit) = self.loops.last().map(|l| l.exit) {
+                    self.jump(exit);
+                }
+            }
+            StatementKind::Continue => {
+                if let Some(header) = self.loops.last().map(|l| l.header) {
+                    self.jump(header);
+                }
+            }
+            StatementKind::Block(block) => self.statements(&block.statements),
+            StatementKind::Expression(expression) => self.visit_expression(expression),
+        }
+    }
+}
+
+/// Records the reads and assignments of expressions and nested declarations in
+/// evaluation order.
+impl<'a> Visitor for Builder<'a> {
+    // Named functions run when they are called, not where they are declared.
+    fn visit_function(&mut self, _function: &FunctionDeclaration) {}
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        match &expression.kind {
+            ExpressionKind::Identifier(ident) => {
+                if let Some(declaration) = self.tracked(ident) {
+                    self.event(Event::Use(declaration, ident.span));
+                }
+            }
+            ExpressionKind::Assignment(target, op, value) => {
+                let assigned = match &target.kind {
+                    ExpressionKind::Identifier(ident) => self.tracked(ident),
+                    _ => None,
+                };
+
+                match assigned {
+                    Some(declaration) => {
+                        if !matches!(op, Operator::Assign(_)) {
+                            self.visit_expression(target);
+                        }
+                        self.visit_expression(value);
+                        if !self.conditional {
+                            self.event(Event::Assign(declaration));
+                        }
+                    }
+                    None => visit::walk_expression(self, expression),
+                }
+            }
+            ExpressionKind::Operation(lhs, Operator::And(_), rhs) | ExpressionKind::Operation(lhs, Operator::Or(_), rhs) => {
+                self.visit_expression(lhs);
+                let conditional = std::mem::replace(&mut self.conditional, true);
+                self.visit_expression(rhs);
+                self.conditional = conditional;
+            }
+            // A closure copies the variables it captures when it is created.
+            ExpressionKind::Lambda(_) => {
+                let conditional = std::mem::replace(&mut self.conditional, true);
+                visit::walk_expression(self, expression);
+                self.conditional = conditional;
+            }
+            _ => visit::walk_expression(self, expression),
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Constant folding.
+//!
+//! Replaces operations on literals, and uses of `const` bindings, with the value
+//! they compute. Integer arithmetic is checked, so an overflow or a division by
+//! zero that is certain to happen is a compile error rather than a trap or a
+//! wrapped result at run time. Operands of mismatched types are left alone for
+//! the checker to report.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::ast::visit::{self, Folder, Visitor};
+use crate::ast::*;
+use crate::resolve::Resolution;
+
+#[derive(Clone, Debug)]
+pub enum FoldErrorKind {
+    DivideByZero,
+    Overflow(Operator),
+    /// The value of the named `const` can't be computed at compile time.
+    NotConstant(String),
+    AssignToConstant(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct FoldError {
+    pub kind: FoldErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for FoldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            FoldErrorKind::DivideByZero => write!(f, "division by zero in constant expression"),
+            FoldErrorKind::Overflow(op) => {
+                write!(f, "`{}` overflows in constant expression", op)
+            }
+            FoldErrorKind::NotConstant(name) => {
+                write!(f, "the value of constant `{}` is not known at compile time", name)
+            }
+            FoldErrorKind::AssignToConstant(name) => {
+                write!(f, "cannot assign to constant `{}`", name)
+            }
+        }
+    }
+}
+
+/// Folds a resolved program, returning the folded tree and every error sorted by position.
+pub fn fold(program: Program, resolution: &Resolution) -> (Program, Vec<FoldError>) {
+    let mut constants = Constants(HashSet::new());
+    constants.visit_program(&program);
+
+    let mut folder = ConstantFolder {
+        resolution,
+        constants: constants.0,
+        values: HashMap::new(),
+        errors: Vec::new(),
+    };
+
+    let program = folder.fold_program(program);
+
+    folder.errors.sort_by_key(|e| e.span.start);
+    (program, folder.errors)
+}
+
+/// Collects the declaring `Ident` of every `const`, so assignments to one are
+/// caught wherever they appear.
+struct Constants(HashSet<NodeId>);
+
+impl Visitor for Constants {
+    fn visit_variable(&mut self, variable: &VariableDeclaration) {
+        if variable.constant {
+            self.0.insert(variable.name.id);
+        }
+        visit::walk_variable(self, variable);
+    }
+}
+
+struct ConstantFolder<'r> {
+    resolution: &'r Resolution,
+    constants: HashSet<NodeId>,
+    /// The folded value of every `const` seen so far.
+    values: HashMap<NodeId, ValueType>,
+    errors: Vec<FoldError>,
+}
+
+impl<'r> Folder for ConstantFolder<'r> {
+    fn fold_variable(&mut self, variable: VariableDeclaration) -> VariableDeclaration {
+        let reported = self.errors.len();
+        let variable = visit::fold_variable(self, variable);
+
+        // An initializer that failed to fold has already been reported.
+        if variable.constant && self.errors.len() == reported {
+            match &variable.value {
+                Some(Expression {
+                    kind: ExpressionKind::Singleton(value),
+                    ..
+                }) => {
+                    self.values.insert(variable.name.id, value.clone());
+                }
+                value => {
+                    let kind = FoldErrorKind::NotConstant(variable.name.name.clone());
+                    let span = value.as_ref().map_or(variable.span, |value| value.span);
+                    self.errors.push(FoldError { kind, span });
+                }
+            }
+        }
+
+        variable
+    }
+
+    fn fold_expression(&mut self, expression: Expression) -> Expression {
+        // Checked before folding, which would replace the target with its value.
+        if let ExpressionKind::Assignment(target, ..) = &expression.kind {
+            if let ExpressionKind::Identifier(ident) = &target.kind {
+                let declaration = self.resolution.references.get(&ident.id);
+                if declaration.is_some_and(|id| self.constants.contains(id)) {
+                    let kind = FoldErrorKind::AssignToConstant(ident.name.clone());
+                    self.errors.push(FoldError {
+                        kind,
+                        span: expression.span,
+                    });
+                    return expression;
+                }
+            }
+        }
+
+        let expression = visit::fold_expression(self, expression);
+
+        let folded = match &expression.kind {
+            ExpressionKind::Identifier(ident) => Ok(self
+                .resolution
+                .references
+                .get(&ident.id)
+                .and_then(|declaration| self.values.get(declaration))
+                .cloned()),
+            ExpressionKind::Unary(op, operand) => match &operand.kind {
+                ExpressionKind::Singleton(value) => fold_unary(op, value),
+                _ => Ok(None),
+            },
+            ExpressionKind::Operation(lhs, op, rhs) => match (&lhs.kind, &rhs.kind) {
+                (ExpressionKind::Singleton(lhs), ExpressionKind::Singleton(rhs)) => {
+                    fold_operation(lhs, op, rhs)
+                }
+                _ => Ok(None),
+            },
+            _ => Ok(None),
+        };
+
+        match folded {
+            Ok(Some(value)) => Expression {
+                kind: ExpressionKind::Singleton(value),
+                ..expression
+            },
+            Ok(None) => expression,
+            Err(kind) => {
+                self.errors.push(FoldError {
+                    kind,
+                    span: expression.span,
+                });
+                expression
+            }
+        }
+    }
+}
+
+fn fold_unary(op: &Operator, operand: &ValueType) -> Result<Option<ValueType>, FoldErrorKind> {
+    Ok(Some(match (op, operand) {
+        (Operator::Subtract(_), ValueType::Number(n)) => {
+            ValueType::Number(n.checked_neg().ok_or_else(|| FoldErrorKind::Overflow(op.clone()))?)
+        }
+        (Operator::Subtract(_), ValueType::Float(x)) => ValueType::Float(-x),
+        (Operator::Not(_), ValueType::Boolean(b)) => ValueType::Boolean(!b),
+        _ => return Ok(None),
+    }))
+}
+
+/// Folds an operation on two literals, `None` if the operands don't have types it applies to.
+fn fold_operation(lhs: &ValueType, op: &Operator, rhs: &ValueType) -> Result<Option<ValueType>, FoldErrorKind> {
+    use std::cmp::Ordering;
+
+    let overflow = || FoldErrorKind::Overflow(op.clone());
+
+    let ordering = match (lhs, rhs) {
+        (ValueType::Number(a), ValueType::Number(b)) => Some(a.cmp(b)),
+        (ValueType::Float(a), ValueType::Float(b)) => a.partial_cmp(b),
+        (ValueType::String(a), ValueType::String(b)) => Some(a.cmp(b)),
+        (ValueType::Boolean(a), ValueType::Boolean(b)) => Some(a.cmp(b)),
+        _ => None,
+    };
+
+    let value = match (op, lhs, rhs) {
+        (Operator::Add(_), ValueType::Number(a), ValueType::Number(b)) => {
+            ValueType::Number(a.checked_add(*b).ok_or_else(overflow)?)
+        }
+        (Operator::Subtract(_), ValueType::Number(a), ValueType::Number(b)) => {
+            ValueType::Number(a.checked_sub(*b).ok_or_else(overflow)?)
+        }
+        (Operator::Multiply(_), ValueType::Number(a), ValueType::Number(b)) => {
+            ValueType::Number(a.checked_mul(*b).ok_or_else(overflow)?)
+        }
+        (Operator::Divide(_), ValueType::Number(_), ValueType::Number(0)) => {
+            return Err(FoldErrorKind::DivideByZero)
+        }
+        // Only `i32::MIN / -1` overflows.
+        (Operator::Divide(_), ValueType::Number(a), ValueType::Number(b)) => {
+            ValueType::Number(a.checked_div(*b).ok_or_else(overflow)?)
+        }
+        (Operator::Divide(_), ValueType::Float(_), ValueType::Float(b)) if *b == 0.0 => {
+            return Err(FoldErrorKind::DivideByZero)
+        }
+        (Operator::Add(_), ValueType::Float(a), ValueType::Float(b)) => float(a + b, overflow)?,
+        (Operator::Subtract(_), ValueType::Float(a), ValueType::Float(b)) => float(a - b, overflow)?,
+        (Operator::Multiply(_), ValueType::Float(a), ValueType::Float(b)) => float(a * b, overflow)?,
+        (Operator::Divide(_), ValueType::Float(a), ValueType::Float(b)) => float(a / b, overflow)?,
+        (Operator::Add(_), ValueType::String(a), ValueType::String(b)) => {
+            ValueType::String(format!("{}{}", a, b))
+        }
+        (Operator::And(_), ValueType::Boolean(a), ValueType::Boolean(b)) => ValueType::Boolean(*a && *b),
+        (Operator::Or(_), ValueType::Boolean(a), ValueType::Boolean(b)) => ValueType::Boolean(*a || *b),
+        (Operator::Equal(_), ..) if ordering.is_some() => ValueType::Boolean(ordering == Some(Ordering::Equal)),
+        (Operator::NotEqual(_), ..) if ordering.is_some() => ValueType::Boolean(ordering != Some(Ordering::Equal)),
+        // Booleans can only be compared for equality.
+        (_, ValueType::Boolean(_), _) => return Ok(None),
+        (Operator::Less(_), ..) if ordering.is_some() => ValueType::Boolean(ordering == Some(Ordering::Less)),
+        (Operator::LessEqual(_), ..) if ordering.is_some() => ValueType::Boolean(ordering != Some(Ordering::Greater)),
+        (Operator::Greater(_), ..) if ordering.is_some() => ValueType::Boolean(ordering == Some(Ordering::Greater)),
+        (Operator::GreaterEqual(_), ..) if ordering.is_some() => ValueType::Boolean(ordering != Some(Ordering::Less)),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(value))
+}
+
+/// A float result, which overflows when finite operands give an infinite result.
+fn float<F>(result: f32, overflow: F) -> Result<ValueType, FoldErrorKind>
+where
+    F: Fn() -> FoldErrorKind,
+{
+    if result.is_finite() {
+        Ok(ValueType::Float(result))
+    } else {
+        Err(overflow())
+    }
+}
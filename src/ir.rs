@@ -0,0 +1,448 @@
+//! The mid-level IR between the syntax tree and wasm.
+//!
+//! A module is a list of functions over typed virtual locals. Every instruction
+//! reads constants or locals and writes its result to a local, control flow is
+//! structured (`if`, `loop`, `break`, `continue`) so it maps directly onto wasm
+//! blocks, and memory is only touched by explicit loads and stores at the offsets
+//! the resolver laid instances and closure environments out with.
+//!
+//! `lower` builds a module from a checked program, `pass` transforms it, and the
+//! `Display` impl prints the textual form shown by `--emit=ir`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::types::Type;
+
+pub mod lower;
+pub mod pass;
+
+/// The type of a local, global or operand, as wasm represents it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValType {
+    I32,
+    F32,
+}
+
+impl ValType {
+    /// How values of a checked type are represented, `None` for `Void`. Everything
+    /// but `f32` is an i32, either the value itself or a pointer into memory.
+    pub fn of(ty: &Type) -> Option<ValType> {
+        match ty {
+            Type::F32 => Some(ValType::F32),
+            Type::Void => None,
+            _ => Some(ValType::I32),
+        }
+    }
+}
+
+/// A virtual local of the function, parameters come first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Local(pub u32);
+
+/// A function of the module, an index into `Module::functions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FuncId(pub u32);
+
+/// An index into `Module::imports`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ImportId(pub u32);
+
+/// A global, the index the resolver assigned the variable or static.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GlobalId(pub u32);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Signature {
+    pub parameters: Vec<ValType>,
+    pub result: Option<ValType>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    Local(Local),
+    I32(i32),
+    F32(f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    /// Whether the operand is zero, `!` on a boolean.
+    Eqz,
+}
+
+/// A binary operation, comparisons produce an i32 0 or 1 and are signed for i32.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Callee {
+    Function(FuncId),
+    Import(ImportId),
+    /// The function at an index of the module's table, see `Module::table`.
+    Indirect { table_index: Operand, signature: Signature },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    Copy { dest: Local, value: Operand },
+    Unary { dest: Local, op: UnaryOp, ty: ValType, operand: Operand },
+    Binary { dest: Local, op: BinaryOp, ty: ValType, lhs: Operand, rhs: Operand },
+    /// Reads the value at `address + offset`.
+    Load { dest: Local, ty: ValType, address: Operand, offset: u32 },
+    Store { ty: ValType, address: Operand, offset: u32, value: Operand },
+    GlobalGet { dest: Local, global: GlobalId },
+    GlobalSet { global: GlobalId, value: Operand },
+    /// Evaluates an operand for nothing, the value of an expression statement.
+    Drop(Operand),
+    /// Reserves `size` bytes of memory and sets `dest` to their address.
+    Alloc { dest: Local, size: u32 },
+    /// `dest` is set exactly when the callee returns a value.
+    Call { dest: Option<Local>, callee: Callee, arguments: Vec<Operand> },
+    If { condition: Operand, then: Vec<Instruction>, otherwise: Vec<Instruction> },
+    /// Runs its body over and over, until a `Break` leaves it.
+    Loop(Vec<Instruction>),
+    /// Leaves the innermost loop.
+    Break,
+    /// Starts the next iteration of the innermost loop.
+    Continue,
+    Return(Option<Operand>),
+}
+
+impl Instruction {
+    /// Whether control never continues past this instruction.
+    pub fn is_terminator(&self) -> bool {
+        matches!(self, Instruction::Break | Instruction::Continue | Instruction::Return(_))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    /// Exported from the module under its name.
+    pub export: bool,
+    pub signature: Signature,
+    /// The type of every local, the parameters first.
+    pub locals: Vec<ValType>,
+    /// The source names of the locals declared as parameters or variables.
+    pub local_names: BTreeMap<Local, String>,
+    pub body: Vec<Instruction>,
+}
+
+impl Function {
+    /// The locals that aren't parameters.
+    pub fn declared_locals(&self) -> &[ValType] {
+        &self.locals[self.signature.parameters.len()..]
+    }
+}
+
+/// A function the host provides, see `resolve::HOST_MODULES`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+    pub signature: Signature,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub name: String,
+    pub ty: ValType,
+    /// Whether the program assigns the global, `const` bindings are only initialized.
+    pub mutable: bool,
+    /// The constant the global holds before the init function runs.
+    pub init: Operand,
+}
+
+/// A string literal placed in linear memory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub address: u32,
+    pub value: String,
+}
+
+/// The lowest address of the literals, and so of the heap after them. The bytes
+/// below it stay unused so that no string or allocation is the null pointer.
+pub const DATA_START: u32 = 8;
+
+/// The string literals of a module, each stored once. A string is a pointer to
+/// its length in bytes, a little endian u32 followed by the UTF-8 bytes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Strings {
+    pub segments: Vec<Segment>,
+}
+
+impl Strings {
+    /// The address of a literal, laid out after the others the first time it is seen.
+    pub fn intern(&mut self, value: &str) -> u32 {
+        if let Some(segment) = self.segments.iter().find(|segment| segment.value == value) {
+            return segment.address;
+        }
+
+        let address = self.end();
+        self.segments.push(Segment {
+            address,
+            value: value.to_string(),
+        });
+        address
+    }
+
+    /// The first address after the literals, aligned so the next length prefix
+    /// or heap allocation is too.
+    pub fn end(&self) -> u32 {
+        self.segments.last().map_or(DATA_START, |segment| {
+            let end = segment.address + 4 + segment.value.len() as u32;
+            (end + 3) & !3
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Module {
+    pub imports: Vec<Import>,
+    pub globals: Vec<Global>,
+    pub functions: Vec<Function>,
+    /// The functions called indirectly, lambdas, by their index in the table.
+    pub table: Vec<FuncId>,
+    /// The function running the module level statements, if there are any to run.
+    pub init: Option<FuncId>,
+    pub strings: Strings,
+}
+
+impl fmt::Display for ValType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValType::I32 => write!(f, "i32"),
+            ValType::F32 => write!(f, "f32"),
+        }
+    }
+}
+
+impl fmt::Display for Local {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for GlobalId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@{}", self.0)
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Local(local) => write!(f, "{}", local),
+            Operand::I32(n) => write!(f, "{}", n),
+            Operand::F32(x) => write!(f, "{:?}", x),
+        }
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(")?;
+        for (i, parameter) in self.parameters.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", parameter)?;
+        }
+        write!(f, ")")?;
+        match self.result {
+            Some(result) => write!(f, " -> {}", result),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnaryOp::Neg => write!(f, "neg"),
+            UnaryOp::Eqz => write!(f, "eqz"),
+        }
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BinaryOp::Add => "add",
+            BinaryOp::Sub => "sub",
+            BinaryOp::Mul => "mul",
+            BinaryOp::Div => "div",
+            BinaryOp::Eq => "eq",
+            BinaryOp::Ne => "ne",
+            BinaryOp::Lt => "lt",
+            BinaryOp::Le => "le",
+            BinaryOp::Gt => "gt",
+            BinaryOp::Ge => "ge",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Writes comma separated operands.
+fn write_operands(f: &mut fmt::Formatter<'_>, operands: &[Operand]) -> fmt::Result {
+    for (i, operand) in operands.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", operand)?;
+    }
+    Ok(())
+}
+
+impl Module {
+    fn write_instructions(&self, f: &mut fmt::Formatter<'_>, instructions: &[Instruction], depth: usize) -> fmt::Result {
+        let indent = "  ".repeat(depth);
+
+        for instruction in instructions.iter() {
+            write!(f, "{}", indent)?;
+
+            match instruction {
+                Instruction::Copy { dest, value } => writeln!(f, "{} = {}", dest, value)?,
+                Instruction::Unary { dest, op, ty, operand } => {
+                    writeln!(f, "{} = {}.{} {}", dest, ty, op, operand)?
+                }
+                Instruction::Binary { dest, op, ty, lhs, rhs } => {
+                    writeln!(f, "{} = {}.{} {}, {}", dest, ty, op, lhs, rhs)?
+                }
+                Instruction::Load { dest, ty, address, offset } => {
+                    writeln!(f, "{} = {}.load [{} + {}]", dest, ty, address, offset)?
+                }
+                Instruction::Store { ty, address, offset, value } => {
+                    writeln!(f, "{}.store [{} + {}], {}", ty, address, offset, value)?
+                }
+                Instruction::GlobalGet { dest, global } => writeln!(f, "{} = global.get {}", dest, global)?,
+                Instruction::GlobalSet { global, value } => writeln!(f, "global.set {}, {}", global, value)?,
+                Instruction::Drop(value) => writeln!(f, "drop {}", value)?,
+                Instruction::Alloc { dest, size } => writeln!(f, "{} = alloc {}", dest, size)?,
+                Instruction::Call { dest, callee, arguments } => {
+                    if let Some(dest) = dest {
+                        write!(f, "{} = ", dest)?;
+                    }
+                    match callee {
+                        Callee::Function(id) => {
+                            let name = self.functions.get(id.0 as usize).map_or("?", |function| &function.name);
+                            write!(f, "call ${}(", name)?;
+                        }
+                        Callee::Import(id) => match self.imports.get(id.0 as usize) {
+                            Some(import) => write!(f, "call ${}.{}(", import.module, import.name)?,
+                            None => write!(f, "call $?(")?,
+                        },
+                        Callee::Indirect { table_index, signature } => {
+                            write!(f, "call_indirect {} {} (", table_index, signature)?
+                        }
+                    }
+                    write_operands(f, arguments)?;
+                    writeln!(f, ")")?;
+                }
+                Instruction::If { condition, then, otherwise } => {
+                    writeln!(f, "if {} {{", condition)?;
+                    self.write_instructions(f, then, depth + 1)?;
+                    if !otherwise.is_empty() {
+                        writeln!(f, "{}}} else {{", indent)?;
+                        self.write_instructions(f, otherwise, depth + 1)?;
+                    }
+                    writeln!(f, "{}}}", indent)?;
+                }
+                Instruction::Loop(body) => {
+                    writeln!(f, "loop {{")?;
+                    self.write_instructions(f, body, depth + 1)?;
+                    writeln!(f, "{}}}", indent)?;
+                }
+                Instruction::Break => writeln!(f, "break")?,
+                Instruction::Continue => writeln!(f, "continue")?,
+                Instruction::Return(Some(value)) => writeln!(f, "return {}", value)?,
+                Instruction::Return(None) => writeln!(f, "return")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for import in self.imports.iter() {
+            writeln!(f, "import ${}.{} {}", import.module, import.name, import.signature)?;
+        }
+
+        for (index, global) in self.globals.iter().enumerate() {
+            let qualifier = if global.mutable { "mut " } else { "" };
+            writeln!(
+                f,
+                "global {}{} ${}: {} = {}",
+                qualifier,
+                GlobalId(index as u32),
+                global.name,
+                global.ty,
+                global.init
+            )?;
+        }
+
+        for segment in self.strings.segments.iter() {
+            writeln!(f, "data {} {:?}", segment.address, segment.value)?;
+        }
+
+        if !self.table.is_empty() {
+            write!(f, "table [")?;
+            for (i, id) in self.table.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                let name = self.functions.get(id.0 as usize).map_or("?", |function| &function.name);
+                write!(f, "${}", name)?;
+            }
+            writeln!(f, "]")?;
+        }
+
+        for (index, function) in self.functions.iter().enumerate() {
+            writeln!(f)?;
+
+            write!(f, "func ${}(", function.name)?;
+            for (i, ty) in function.signature.parameters.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}: {}", Local(i as u32), ty)?;
+            }
+            write!(f, ")")?;
+            if let Some(result) = function.signature.result {
+                write!(f, " -> {}", result)?;
+            }
+            if function.export {
+                write!(f, " export")?;
+            }
+            if self.init == Some(FuncId(index as u32)) {
+                write!(f, " init")?;
+            }
+            writeln!(f, " {{")?;
+
+            let parameters = function.signature.parameters.len();
+            for (i, ty) in function.declared_locals().iter().enumerate() {
+                writeln!(f, "  local {}: {}", Local((parameters + i) as u32), ty)?;
+            }
+
+            self.write_instructions(f, &function.body, 1)?;
+            writeln!(f, "}}")?;
+        }
+
+        Ok(())
+    }
+}
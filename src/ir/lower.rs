@@ -0,0 +1,1291 @@
+//! Lowering of a checked program to the IR.
+//!
+//! Every function declaration, method and lambda becomes a function of the module,
+//! as does the constructor of every class, which allocates an instance and runs
+//! the property initializers. The module level statements become the `init`
+//! function. Named variables keep the local or global index the resolver gave
+//! them, temporaries are allocated after them.
+//!
+//! A method receives its instance in local 0 and a lambda its closure environment,
+//! laid out as the table index of its body followed by one slot per captured value
+//! in the order `capture::analyze` lists them. An array is laid out like a string,
+//! its length followed by its elements, four bytes each, and indexing it is unchecked.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use super::*;
+use crate::ast::visit::{self, Visitor};
+use crate::ast::*;
+use crate::capture::Captures;
+use crate::check::TypeTable;
+use crate::resolve::{DeclarationKind, Resolution};
+
+#[derive(Clone, Debug)]
+pub enum LowerErrorKind {
+    /// A construct the backend has no representation for yet.
+    Unsupported(&'static str),
+    /// A lambda assigning a variable it captured, which would only change its copy.
+    AssignedCapture(String),
+    /// A value used where one of the other representation is expected, which
+    /// happens when values of unknown type meet `f32`s.
+    Representation { expected: ValType, found: ValType },
+    /// A call to an untyped import that doesn't match the signature its first call gave it.
+    ImportSignature { name: String, expected: Signature, found: Signature },
+}
+
+#[derive(Clone, Debug)]
+pub struct LowerError {
+    pub kind: LowerErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for LowerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            LowerErrorKind::Unsupported(what) => write!(f, "{} cannot be compiled yet", what),
+            LowerErrorKind::AssignedCapture(name) => {
+                write!(f, "cannot assign to `{}`, lambdas capture variables by value", name)
+            }
+            LowerErrorKind::Representation { expected, found } => write!(
+                f,
+                "expected an `{}`, found an `{}`, values of unknown type are `i32`s and need an annotation",
+                expected, found
+            ),
+            LowerErrorKind::ImportSignature { name, expected, found } => write!(
+                f,
+                "`{}` was first called as `{}`, this call needs `{}`, an import keeps the signature of its first call",
+                name, expected, found
+            ),
+        }
+    }
+}
+
+/// Lowers a program that resolved and checked without errors, returning every
+/// error sorted by position.
+pub fn lower(
+    program: &Program,
+    resolution: &Resolution,
+    types: &TypeTable,
+    captures: &Captures,
+) -> (Module, Vec<LowerError>) {
+    let mut declared = Declared {
+        types,
+        functions: Vec::new(),
+        ids: HashMap::new(),
+        table: Vec::new(),
+        classes: HashMap::new(),
+        depth: 0,
+    };
+    declared.visit_program(program);
+
+    let mut globals = vec![
+        Global {
+            name: String::new(),
+            ty: ValType::I32,
+            mutable: true,
+            init: Operand::I32(0),
+        };
+        resolution.global_count as usize
+    ];
+    for (id, declaration) in resolution.declarations.iter() {
+        if let DeclarationKind::Global(index) | DeclarationKind::Static(index) = declaration.kind {
+            let ty = binding_type(types, *id);
+            globals[index as usize] = Global {
+                name: declaration.name.clone(),
+                ty,
+                mutable: true,
+                init: match ty {
+                    ValType::I32 => Operand::I32(0),
+                    ValType::F32 => Operand::F32(0.0),
+                },
+            };
+        }
+    }
+
+    let init = FuncId(declared.functions.len() as u32);
+
+    let mut lowering = Lowering {
+        resolution,
+        types,
+        captures,
+        ids: declared.ids,
+        classes: declared.classes,
+        module: Module {
+            globals,
+            functions: declared.functions,
+            table: declared.table,
+            ..Module::default()
+        },
+        imports: HashMap::new(),
+        capture_types: HashMap::new(),
+        queue: Vec::new(),
+        current: Frame::new(Vec::new()),
+        in_place: in_place(program, resolution),
+        errors: Vec::new(),
+    };
+
+    lowering.statements(&program.statements);
+    let body = std::mem::take(&mut lowering.current.body);
+    if !body.is_empty() {
+        lowering.module.functions.push(Function {
+            name: "<init>".to_string(),
+            export: false,
+            signature: Signature {
+                parameters: Vec::new(),
+                result: None,
+            },
+            locals: std::mem::take(&mut lowering.current.locals),
+            local_names: std::mem::take(&mut lowering.current.names),
+            body,
+        });
+        lowering.module.init = Some(init);
+    }
+
+    // Bodies queue the functions declared inside them in turn.
+    while let Some(body) = lowering.queue.pop() {
+        lowering.body(body);
+    }
+
+    lowering.errors.sort_by_key(|e| e.span.start);
+    (lowering.module, lowering.errors)
+}
+
+/// The module level variables whose folded value can be the initial value of their
+/// global: constants, and the `let`s of the module's own statement list, which run
+/// once, when nothing reads or assigns them before their declaration does.
+fn in_place(program: &Program, resolution: &Resolution) -> HashSet<NodeId> {
+    let mut uses = EarlyUses {
+        resolution,
+        depth: 0,
+        early: HashSet::new(),
+    };
+    uses.visit_program(program);
+
+    let mut in_place = HashSet::new();
+    for statement in program.statements.iter() {
+        if let StatementKind::Let(variable) = &statement.kind {
+            if !uses.early.contains(&variable.name.id) {
+                in_place.insert(variable.name.id);
+            }
+        }
+    }
+    in_place
+}
+
+/// Finds the variables used where their declaration may not have run yet: ahead of
+/// it, or in a function, method or lambda, which can be called before it.
+struct EarlyUses<'r> {
+    resolution: &'r Resolution,
+    /// How many function bodies or classes the visitor is inside.
+    depth: usize,
+    early: HashSet<NodeId>,
+}
+
+impl<'r> Visitor for EarlyUses<'r> {
+    fn visit_function(&mut self, function: &FunctionDeclaration) {
+        self.depth += 1;
+        visit::walk_function(self, function);
+        self.depth -= 1;
+    }
+
+    fn visit_class(&mut self, class: &ClassDeclaration) {
+        self.depth += 1;
+        visit::walk_class(self, class);
+        self.depth -= 1;
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        match &expression.kind {
+            ExpressionKind::Identifier(ident) => {
+                let declaration = self.resolution.references.get(&ident.id);
+                if let Some(declaration) = declaration {
+                    let declared = self.resolution.declarations.get(declaration).map(|d| d.span.start);
+                    if self.depth > 0 || declared.is_none_or(|start| ident.span.start < start) {
+                        self.early.insert(*declaration);
+                    }
+                }
+            }
+            ExpressionKind::Lambda(_) => {
+                self.depth += 1;
+                visit::walk_expression(self, expression);
+                self.depth -= 1;
+            }
+            _ => visit::walk_expression(self, expression),
+        }
+    }
+}
+
+/// The IR type of a declared name, `i32` when the checker found nothing better.
+fn binding_type(types: &TypeTable, id: NodeId) -> ValType {
+    types.bindings.get(&id).and_then(ValType::of).unwrap_or(ValType::I32)
+}
+
+/// The signature of a function of checked type `ty`, receiving an i32 ahead of its
+/// parameters when `receiver` is set.
+fn signature(ty: Option<&Type>, parameters: usize, receiver: bool) -> Signature {
+    let (parameter_types, result) = match ty {
+        Some(Type::Function(parameters, result)) => (
+            parameters.iter().map(|ty| ValType::of(ty).unwrap_or(ValType::I32)).collect(),
+            ValType::of(result),
+        ),
+        _ => (vec![ValType::I32; parameters], Some(ValType::I32)),
+    };
+
+    let mut signature = Signature {
+        parameters: Vec::new(),
+        result,
+    };
+    if receiver {
+        signature.parameters.push(ValType::I32);
+    }
+    signature.parameters.extend(parameter_types);
+    signature
+}
+
+/// Assigns every function, method, lambda and constructor its `FuncId` up front,
+/// so calls can be lowered before the callee.
+struct Declared<'t> {
+    types: &'t TypeTable,
+    /// Every function with its signature and an empty body.
+    functions: Vec<Function>,
+    /// The function declared by a function or method name, a class name (its
+    /// constructor) or a lambda expression.
+    ids: HashMap<NodeId, FuncId>,
+    table: Vec<FuncId>,
+    /// The members of every class, by name, keyed by the class name.
+    classes: HashMap<String, HashMap<String, NodeId>>,
+    /// How many function bodies the visitor is inside, module level functions are exported.
+    depth: usize,
+}
+
+impl<'t> Declared<'t> {
+    fn declare(&mut self, id: NodeId, name: String, export: bool, signature: Signature) -> FuncId {
+        let func = FuncId(self.functions.len() as u32);
+        self.functions.push(Function {
+            name,
+            export,
+            locals: signature.parameters.clone(),
+            local_names: BTreeMap::new(),
+            signature,
+            body: Vec::new(),
+        });
+        self.ids.insert(id, func);
+        func
+    }
+}
+
+impl<'t> Visitor for Declared<'t> {
+    fn visit_function(&mut self, function: &FunctionDeclaration) {
+        let ty = self.types.functions.get(&function.id);
+        let signature = signature(ty, function.parameters.len(), false);
+        self.declare(function.name.id, function.name.name.clone(), self.depth == 0, signature);
+
+        self.depth += 1;
+        visit::walk_function(self, function);
+        self.depth -= 1;
+    }
+
+    fn visit_class(&mut self, class: &ClassDeclaration) {
+        let constructor = Signature {
+            parameters: Vec::new(),
+            result: Some(ValType::I32),
+        };
+        self.declare(class.name.id, format!("{}.new", class.name.name), false, constructor);
+
+        let mut members = HashMap::new();
+        for member in class.members.iter() {
+            match member {
+                ClassMember::Property(variable) | ClassMember::Static(variable) => {
+                    members.insert(variable.name.name.clone(), variable.name.id);
+                    visit::walk_variable(self, variable);
+                }
+                ClassMember::Method(method) => {
+                    members.insert(method.name.name.clone(), method.name.id);
+
+                    let ty = self.types.functions.get(&method.id);
+                    let signature = signature(ty, method.parameters.len(), true);
+                    let name = format!("{}.{}", class.name.name, method.name.name);
+                    self.declare(method.name.id, name, false, signature);
+
+                    self.depth += 1;
+                    visit::walk_function(self, method);
+                    self.depth -= 1;
+                }
+            }
+        }
+
+        self.classes.insert(class.name.name.clone(), members);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        if let ExpressionKind::Lambda(lambda) = &expression.kind {
+            let ty = self.types.functions.get(&expression.id);
+            let signature = signature(ty, lambda.parameters.len(), true);
+            let name = format!("lambda{}", self.table.len());
+            let func = self.declare(expression.id, name, false, signature);
+            self.table.push(func);
+
+            self.depth += 1;
+            visit::walk_expression(self, expression);
+            self.depth -= 1;
+            return;
+        }
+
+        visit::walk_expression(self, expression);
+    }
+}
+
+/// A function body waiting to be lowered.
+enum Body<'a> {
+    Function(&'a FunctionDeclaration),
+    Method(&'a FunctionDeclaration),
+    Constructor(&'a ClassDeclaration),
+    Lambda(&'a Expression, &'a Lambda),
+}
+
+/// The function being lowered.
+struct Frame {
+    locals: Vec<ValType>,
+    /// The names of the locals bound to a declaration, the first one if a local
+    /// is reused by a later scope.
+    names: BTreeMap<Local, String>,
+    /// The declarations whose locals belong to this function, rather than to an
+    /// enclosing one.
+    owned: HashSet<NodeId>,
+    /// The variables in scope by name, for handing them on to closures.
+    scopes: Vec<HashMap<String, Local>>,
+    /// The lambda being lowered, whose environment is in local 0.
+    lambda: Option<NodeId>,
+    /// The type the function returns.
+    result: Option<ValType>,
+    /// The instructions of the innermost block being lowered.
+    body: Vec<Instruction>,
+}
+
+impl Frame {
+    fn new(locals: Vec<ValType>) -> Self {
+        Frame {
+            locals,
+            names: BTreeMap::new(),
+            owned: HashSet::new(),
+            scopes: vec![HashMap::new()],
+            lambda: None,
+            result: None,
+            body: Vec::new(),
+        }
+    }
+}
+
+/// Storage that can be read and assigned.
+#[derive(Clone, Copy)]
+enum Place {
+    Local(Local),
+    Global(GlobalId),
+    Memory { ty: ValType, address: Operand, offset: u32 },
+}
+
+struct Lowering<'a> {
+    resolution: &'a Resolution,
+    types: &'a TypeTable,
+    captures: &'a Captures,
+    ids: HashMap<NodeId, FuncId>,
+    classes: HashMap<String, HashMap<String, NodeId>>,
+    module: Module,
+    /// The imports called so far, keyed by the `Ident` importing them.
+    imports: HashMap<NodeId, ImportId>,
+    /// The type of every slot of a lambda's environment, fixed where the closure
+    /// is created, which is always lowered before the lambda's body.
+    capture_types: HashMap<NodeId, Vec<Option<ValType>>>,
+    queue: Vec<Body<'a>>,
+    current: Frame,
+    /// The module level variables initialized in place, see `in_place`.
+    in_place: HashSet<NodeId>,
+    errors: Vec<LowerError>,
+}
+
+impl<'a> Lowering<'a> {
+    fn unsupported(&mut self, what: &'static str, span: Span) {
+        self.errors.push(LowerError {
+            kind: LowerErrorKind::Unsupported(what),
+            span,
+        });
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        self.current.body.push(instruction);
+    }
+
+    fn temp(&mut self, ty: ValType) -> Local {
+        self.current.locals.push(ty);
+        Local(self.current.locals.len() as u32 - 1)
+    }
+
+    fn operand_type(&self, operand: Operand) -> ValType {
+        match operand {
+            Operand::Local(local) => self.current.locals.get(local.0 as usize).copied().unwrap_or(ValType::I32),
+            Operand::I32(_) => ValType::I32,
+            Operand::F32(_) => ValType::F32,
+        }
+    }
+
+    /// Checks that the value of the expression at `span` is represented as `ty`.
+    /// The checker accepts values of unknown type anywhere, but they are `i32`s.
+    fn expect_type(&mut self, operand: Operand, ty: ValType, span: Span) -> Option<Operand> {
+        let found = self.operand_type(operand);
+        if found == ty {
+            return Some(operand);
+        }
+
+        self.errors.push(LowerError {
+            kind: LowerErrorKind::Representation { expected: ty, found },
+            span,
+        });
+        None
+    }
+
+    /// Lowers into a separate instruction list, for the body of an `if` or `loop`.
+    fn nested<R, F>(&mut self, f: F) -> (Vec<Instruction>, R)
+    where
+        F: FnOnce(&mut Self) -> R,
+    {
+        let outer = std::mem::take(&mut self.current.body);
+        let result = f(self);
+        (std::mem::replace(&mut self.current.body, outer), result)
+    }
+
+    /// The IR type of an expression, `i32` when the checker found nothing better.
+    fn ty(&self, expression: &Expression) -> ValType {
+        self.types
+            .expressions
+            .get(&expression.id)
+            .and_then(ValType::of)
+            .unwrap_or(ValType::I32)
+    }
+
+    fn is_string(&self, expression: &Expression) -> bool {
+        self.types.expressions.get(&expression.id) == Some(&Type::String)
+    }
+
+    /// Declares a named local of the current function.
+    fn declare_local(&mut self, name: &Ident, index: u32, ty: ValType) {
+        let local = Local(index);
+        if let Some(slot) = self.current.locals.get_mut(index as usize) {
+            *slot = ty;
+        }
+        self.current.names.entry(local).or_insert_with(|| name.name.clone());
+        self.current.owned.insert(name.id);
+        if let Some(scope) = self.current.scopes.last_mut() {
+            scope.insert(name.name.clone(), local);
+        }
+    }
+
+    /// Lowers the body of a function, method, lambda or constructor into its place in the module.
+    fn body(&mut self, body: Body<'a>) {
+        let (key, locals_of) = match &body {
+            Body::Function(function) | Body::Method(function) => (function.name.id, function.id),
+            Body::Constructor(class) => (class.name.id, class.name.id),
+            Body::Lambda(expression, _) => (expression.id, expression.id),
+        };
+
+        let func = match self.ids.get(&key) {
+            Some(func) => *func,
+            None => return,
+        };
+
+        let signature = self.module.functions[func.0 as usize].signature.clone();
+        let count = self.resolution.local_counts.get(&locals_of).copied().unwrap_or(0) as usize;
+        let mut locals = vec![ValType::I32; count.max(signature.parameters.len())];
+        locals[..signature.parameters.len()].copy_from_slice(&signature.parameters);
+        self.current = Frame::new(locals);
+        self.current.result = signature.result;
+
+        match body {
+            Body::Function(function) => {
+                self.parameters(&function.parameters, 0, &signature);
+                self.block(&function.body);
+            }
+            Body::Method(method) => {
+                self.current.owned.insert(method.id);
+                if let Some(scope) = self.current.scopes.last_mut() {
+                    scope.insert("this".to_string(), Local(0));
+                }
+                self.current.names.insert(Local(0), "this".to_string());
+                self.parameters(&method.parameters, 1, &signature);
+                self.block(&method.body);
+            }
+            Body::Lambda(expression, lambda) => {
+                self.current.lambda = Some(expression.id);
+                self.parameters(&lambda.parameters, 1, &signature);
+                self.block(&lambda.body);
+            }
+            Body::Constructor(class) => self.constructor(class),
+        }
+
+        let function = &mut self.module.functions[func.0 as usize];
+        function.locals = std::mem::take(&mut self.current.locals);
+        function.local_names = std::mem::take(&mut self.current.names);
+        function.body = std::mem::take(&mut self.current.body);
+    }
+
+    fn parameters(&mut self, parameters: &[Parameter], offset: u32, signature: &Signature) {
+        for (i, parameter) in parameters.iter().enumerate() {
+            let index = offset + i as u32;
+            let ty = signature.parameters.get(index as usize).copied().unwrap_or(ValType::I32);
+            self.declare_local(&parameter.name, index, ty);
+        }
+    }
+
+    /// Allocates an instance in local 0, initializes its properties and returns it.
+    fn constructor(&mut self, class: &'a ClassDeclaration) {
+        let size = self.resolution.instance_sizes.get(&class.name.id).copied().unwrap_or(0);
+        let instance = self.temp(ValType::I32);
+        self.emit(Instruction::Alloc { dest: instance, size });
+
+        for member in class.members.iter() {
+            let variable = match member {
+                ClassMember::Property(variable) => variable,
+                _ => continue,
+            };
+
+            let offset = match self.resolution.declarations.get(&variable.name.id).map(|d| &d.kind) {
+                Some(DeclarationKind::Property(offset)) => *offset,
+                _ => continue,
+            };
+
+            if let Some(value) = &variable.value {
+                let ty = binding_type(self.types, variable.name.id);
+                let value = match self.expression(value) {
+                    Some(operand) => self.expect_type(operand, ty, value.span),
+                    None => None,
+                };
+                if let Some(value) = value {
+                    self.emit(Instruction::Store {
+                        ty,
+                        address: Operand::Local(instance),
+                        offset,
+                        value,
+                    });
+                }
+            }
+        }
+
+        self.emit(Instruction::Return(Some(Operand::Local(instance))));
+    }
+
+    fn statements(&mut self, statements: &'a [Statement]) {
+        for statement in statements.iter() {
+            self.statement(statement);
+        }
+    }
+
+    fn block(&mut self, block: &'a Block) {
+        self.current.scopes.push(HashMap::new());
+        self.statements(&block.statements);
+        self.current.scopes.pop();
+    }
+
+    fn statement(&mut self, statement: &'a Statement) {
+        match &statement.kind {
+            StatementKind::Import(_) => {}
+            StatementKind::Let(variable) => self.variable(variable),
+            StatementKind::Function(function) => self.queue.push(Body::Function(function)),
+            StatementKind::Class(class) => self.class(class),
+            StatementKind::If(condition, body, otherwise) => {
+                let condition = match self.expression(condition) {
+                    Some(condition) => condition,
+                    None => return,
+                };
+
+                let (then, _) = self.nested(|lowering| lowering.block(body));
+                let (otherwise, _) = self.nested(|lowering| {
+                    if let Some(otherwise) = otherwise {
+                        lowering.statement(otherwise);
+                    }
+                });
+
+                self.emit(Instruction::If {
+                    condition,
+                    then,
+                    otherwise,
+                });
+            }
+            StatementKind::While(condition, body) => {
+                let (body, _) = self.nested(|lowering| {
+                    if let Some(condition) = lowering.expression(condition) {
+                        let done = lowering.temp(ValType::I32);
+                        lowering.emit(Instruction::Unary {
+                            dest: done,
+                            op: UnaryOp::Eqz,
+                            ty: ValType::I32,
+                            operand: condition,
+                        });
+                        lowering.emit(Instruction::If {
+                            condition: Operand::Local(done),
+                            then: vec![Instruction::Break],
+                            otherwise: Vec::new(),
+                        });
+                    }
+                    lowering.block(body);
+                });
+
+                self.emit(Instruction::Loop(body));
+            }
+            StatementKind::Return(value) => {
+                let value = match value {
+                    Some(value) => {
+                        let ty = self.current.result.unwrap_or(ValType::I32);
+                        match self.expression(value) {
+                            Some(operand) => match self.expect_type(operand, ty, value.span) {
+                                Some(operand) => Some(operand),
+                                None => return,
+                            },
+                            None => return,
+                        }
+                    }
+                    None => None,
+                };
+                self.emit(Instruction::Return(value));
+            }
+            StatementKind::Break => self.emit(Instruction::Break),
+            StatementKind::Continue => self.emit(Instruction::Continue),
+            StatementKind::Block(block) => self.block(block),
+            StatementKind::Expression(expression) => {
+                let value = self.expression(expression);
+
+                // Calls and assignments are run for what they do, any other value is discarded.
+                let effect = matches!(
+                    expression.kind,
+                    ExpressionKind::Invocation(_) | ExpressionKind::Assignment(..)
+                );
+                if let Some(value) = value.filter(|_| !effect) {
+                    self.emit(Instruction::Drop(value));
+                }
+            }
+        }
+    }
+
+    fn variable(&mut self, variable: &'a VariableDeclaration) {
+        let (value, span) = match &variable.value {
+            Some(value) => (self.expression(value), value.span),
+            None => (None, variable.span),
+        };
+
+        let kind = self.resolution.declarations.get(&variable.name.id).map(|d| d.kind.clone());
+        let place = match kind {
+            Some(DeclarationKind::Local(index)) => {
+                let ty = binding_type(self.types, variable.name.id);
+                self.declare_local(&variable.name, index, ty);
+                Place::Local(Local(index))
+            }
+            Some(DeclarationKind::Global(index)) => {
+                let in_place = variable.constant || self.in_place.contains(&variable.name.id);
+                if let Some(value) = value.filter(|_| in_place) {
+                    if self.init_global(GlobalId(index), value, span) {
+                        self.module.globals[index as usize].mutable = !variable.constant;
+                        return;
+                    }
+                }
+                Place::Global(GlobalId(index))
+            }
+            _ => return,
+        };
+
+        if let Some(value) = value {
+            self.write(place, value, span);
+        }
+    }
+
+    /// Makes a folded value the one a global holds from the start, which needs no
+    /// code in the init function. Returns whether `value` is a constant.
+    fn init_global(&mut self, global: GlobalId, value: Operand, span: Span) -> bool {
+        if let Operand::Local(_) = value {
+            return false;
+        }
+
+        let ty = self.module.globals[global.0 as usize].ty;
+        if let Some(value) = self.expect_type(value, ty, span) {
+            self.module.globals[global.0 as usize].init = value;
+        }
+        true
+    }
+
+    /// Queues the constructor and methods of a class and initializes its statics,
+    /// which run again whenever the declaration does.
+    fn class(&mut self, class: &'a ClassDeclaration) {
+        self.queue.push(Body::Constructor(class));
+
+        for member in class.members.iter() {
+            match member {
+                ClassMember::Method(method) => self.queue.push(Body::Method(method)),
+                ClassMember::Static(variable) => {
+                    let kind = self.resolution.declarations.get(&variable.name.id).map(|d| d.kind.clone());
+                    if let (Some(DeclarationKind::Static(index)), Some(value)) = (kind, &variable.value) {
+                        if let Some(operand) = self.expression(value) {
+                            self.write(Place::Global(GlobalId(index)), operand, value.span);
+                        }
+                    }
+                }
+                ClassMember::Property(_) => {}
+            }
+        }
+    }
+
+    fn read(&mut self, place: Place) -> Operand {
+        match place {
+            Place::Local(local) => Operand::Local(local),
+            Place::Global(global) => {
+                let ty = self.module.globals.get(global.0 as usize).map_or(ValType::I32, |g| g.ty);
+                let dest = self.temp(ty);
+                self.emit(Instruction::GlobalGet { dest, global });
+                Operand::Local(dest)
+            }
+            Place::Memory { ty, address, offset } => {
+                let dest = self.temp(ty);
+                self.emit(Instruction::Load {
+                    dest,
+                    ty,
+                    address,
+                    offset,
+                });
+                Operand::Local(dest)
+            }
+        }
+    }
+
+    /// Writes the value of the expression at `span` to `place`.
+    fn write(&mut self, place: Place, value: Operand, span: Span) -> Option<()> {
+        let ty = match place {
+            Place::Local(local) => self.operand_type(Operand::Local(local)),
+            Place::Global(global) => self.module.globals.get(global.0 as usize).map_or(ValType::I32, |g| g.ty),
+            Place::Memory { ty, .. } => ty,
+        };
+        let value = self.expect_type(value, ty, span)?;
+
+        let instruction = match place {
+            Place::Local(dest) => Instruction::Copy { dest, value },
+            Place::Global(global) => Instruction::GlobalSet { global, value },
+            Place::Memory { ty, address, offset } => Instruction::Store {
+                ty,
+                address,
+                offset,
+                value,
+            },
+        };
+        self.emit(instruction);
+        Some(())
+    }
+
+    /// The slot of the current lambda's environment holding a captured name.
+    fn capture(&self, name: &str) -> Option<(ValType, u32)> {
+        let lambda = self.current.lambda?;
+        let index = self.captures.get(&lambda)?.iter().position(|n| n == name)?;
+        let ty = (*self.capture_types.get(&lambda)?.get(index)?)?;
+        Some((ty, 4 + 4 * index as u32))
+    }
+
+    /// Whether `ident` names a variable of an enclosing function, which the
+    /// current lambda holds a copy of.
+    fn is_captured(&self, ident: &Ident) -> bool {
+        let declaration = match self.resolution.references.get(&ident.id) {
+            Some(declaration) => declaration,
+            None => return false,
+        };
+        self.current.lambda.is_some()
+            && !self.current.owned.contains(declaration)
+            && matches!(self.kind(*declaration), Some(DeclarationKind::Local(_)))
+    }
+
+    /// The member `name` of `object` refers to, for `this`, class names and instances
+    /// of a known class.
+    fn member(&self, object: &Expression, name: &Ident) -> Option<NodeId> {
+        if let Some(member) = self.resolution.references.get(&name.id) {
+            return Some(*member);
+        }
+
+        match self.types.expressions.get(&object.id) {
+            Some(Type::Class(class)) => self.classes.get(class)?.get(&name.name).copied(),
+            _ => None,
+        }
+    }
+
+    fn kind(&self, declaration: NodeId) -> Option<&'a DeclarationKind> {
+        self.resolution.declarations.get(&declaration).map(|d| &d.kind)
+    }
+
+    /// The storage an identifier or member expression refers to.
+    fn place(&mut self, expression: &'a Expression) -> Option<Place> {
+        match &expression.kind {
+            ExpressionKind::Identifier(ident) => {
+                let declaration = *self.resolution.references.get(&ident.id)?;
+                match self.kind(declaration)? {
+                    DeclarationKind::Local(index) if self.current.owned.contains(&declaration) => {
+                        Some(Place::Local(Local(*index)))
+                    }
+                    DeclarationKind::Local(_) => match self.capture(&ident.name) {
+                        Some((ty, offset)) => Some(Place::Memory {
+                            ty,
+                            address: Operand::Local(Local(0)),
+                            offset,
+                        }),
+                        None => {
+                            self.unsupported("using variables of an enclosing named function", ident.span);
+                            None
+                        }
+                    },
+                    DeclarationKind::Global(index) => Some(Place::Global(GlobalId(*index))),
+                    _ => {
+                        self.unsupported("functions, classes and imports used as values", expression.span);
+                        None
+                    }
+                }
+            }
+            ExpressionKind::Member(object, name) => {
+                let member = match self.member(object, name) {
+                    Some(member) => member,
+                    None => {
+                        self.unsupported("members of values other than class instances", name.span);
+                        return None;
+                    }
+                };
+
+                match self.kind(member)? {
+                    DeclarationKind::Property(offset) => {
+                        let offset = *offset;
+                        let address = self.expression(object)?;
+                        Some(Place::Memory {
+                            ty: binding_type(self.types, member),
+                            address,
+                            offset,
+                        })
+                    }
+                    DeclarationKind::Static(index) => Some(Place::Global(GlobalId(*index))),
+                    _ => {
+                        self.unsupported("methods used as values", name.span);
+                        None
+                    }
+                }
+            }
+            ExpressionKind::Index(object, index) => {
+                let array = self.expression(object)?;
+                let index = self.expression(index)?;
+
+                let scaled = self.temp(ValType::I32);
+                self.emit(Instruction::Binary {
+                    dest: scaled,
+                    op: BinaryOp::Mul,
+                    ty: ValType::I32,
+                    lhs: index,
+                    rhs: Operand::I32(4),
+                });
+                let address = self.temp(ValType::I32);
+                self.emit(Instruction::Binary {
+                    dest: address,
+                    op: BinaryOp::Add,
+                    ty: ValType::I32,
+                    lhs: array,
+                    rhs: Operand::Local(scaled),
+                });
+
+                Some(Place::Memory {
+                    ty: self.ty(expression),
+                    address: Operand::Local(address),
+                    offset: 4,
+                })
+            }
+            _ => {
+                self.unsupported("assigning to this expression", expression.span);
+                None
+            }
+        }
+    }
+
+    /// Lowers an expression, returning the operand holding its value. `None` when
+    /// it couldn't be lowered, which has been reported.
+    fn expression(&mut self, expression: &'a Expression) -> Option<Operand> {
+        match &expression.kind {
+            ExpressionKind::Singleton(value) => match value {
+                ValueType::Number(n) => Some(Operand::I32(*n)),
+                ValueType::Float(x) => Some(Operand::F32(*x)),
+                ValueType::Boolean(b) => Some(Operand::I32(*b as i32)),
+                ValueType::String(value) => Some(Operand::I32(self.module.strings.intern(value) as i32)),
+            },
+            ExpressionKind::Identifier(_) | ExpressionKind::Member(..) | ExpressionKind::Index(..) => {
+                let place = self.place(expression)?;
+                Some(self.read(place))
+            }
+            ExpressionKind::Object(_) => {
+                self.unsupported("object literals", expression.span);
+                None
+            }
+            ExpressionKind::Array(elements) => self.array(expression, elements),
+            ExpressionKind::Invocation(invocation) => self.invocation(expression, invocation),
+            ExpressionKind::Unary(op, operand) => {
+                let ty = self.ty(operand);
+                let span = operand.span;
+                let operand = self.expression(operand)?;
+                let operand = self.expect_type(operand, ty, span)?;
+                let op = match op {
+                    Operator::Subtract(_) => UnaryOp::Neg,
+                    _ => UnaryOp::Eqz,
+                };
+                let dest = self.temp(ty);
+                self.emit(Instruction::Unary { dest, op, ty, operand });
+                Some(Operand::Local(dest))
+            }
+            ExpressionKind::Operation(lhs, op, rhs) => match op {
+                Operator::And(_) | Operator::Or(_) => self.short_circuit(lhs, op, rhs),
+                _ => {
+                    let lhs_operand = self.expression(lhs)?;
+                    let rhs_operand = self.expression(rhs)?;
+                    self.operation(expression, lhs, op, rhs, lhs_operand, rhs_operand)
+                }
+            },
+            ExpressionKind::Assignment(target, op, value) => {
+                if let ExpressionKind::Identifier(ident) = &target.kind {
+                    if self.is_captured(ident) {
+                        self.errors.push(LowerError {
+                            kind: LowerErrorKind::AssignedCapture(ident.name.clone()),
+                            span: target.span,
+                        });
+                        return None;
+                    }
+                }
+
+                let place = self.place(target)?;
+                let mut result = self.expression(value)?;
+
+                // The target is only evaluated once, a compound assignment reads
+                // the place it already found.
+                if !matches!(op, Operator::Assign(_)) {
+                    let current = self.read(place);
+                    result = self.operation(expression, target, op, value, current, result)?;
+                }
+
+                self.write(place, result, value.span)?;
+                Some(result)
+            }
+            ExpressionKind::Lambda(lambda) => self.closure(expression, lambda),
+        }
+    }
+
+    /// Allocates an array holding the values of `elements`.
+    fn array(&mut self, expression: &Expression, elements: &'a [Expression]) -> Option<Operand> {
+        let ty = match self.types.expressions.get(&expression.id) {
+            Some(Type::Array(element)) => ValType::of(element).unwrap_or(ValType::I32),
+            _ => ValType::I32,
+        };
+
+        let mut values = Vec::new();
+        for element in elements.iter() {
+            let operand = self.expression(element)?;
+            values.push(self.expect_type(operand, ty, element.span)?);
+        }
+
+        let array = self.temp(ValType::I32);
+        self.emit(Instruction::Alloc {
+            dest: array,
+            size: 4 + 4 * values.len() as u32,
+        });
+        self.emit(Instruction::Store {
+            ty: ValType::I32,
+            address: Operand::Local(array),
+            offset: 0,
+            value: Operand::I32(values.len() as i32),
+        });
+        for (index, value) in values.into_iter().enumerate() {
+            self.emit(Instruction::Store {
+                ty,
+                address: Operand::Local(array),
+                offset: 4 + 4 * index as u32,
+                value,
+            });
+        }
+
+        Some(Operand::Local(array))
+    }
+
+    /// Lowers a binary operator, or the operator of a compound assignment, on operands
+    /// already lowered from `lhs` and `rhs`.
+    fn operation(
+        &mut self,
+        expression: &Expression,
+        lhs: &Expression,
+        op: &Operator,
+        rhs: &Expression,
+        lhs_operand: Operand,
+        rhs_operand: Operand,
+    ) -> Option<Operand> {
+        if self.is_string(lhs) || self.is_string(rhs) {
+            self.unsupported("string operations", expression.span);
+            return None;
+        }
+
+        let op = match op {
+            Operator::Add(_) | Operator::AddAssign(_) => BinaryOp::Add,
+            Operator::Subtract(_) | Operator::SubtractAssign(_) => BinaryOp::Sub,
+            Operator::Multiply(_) | Operator::MultiplyAssign(_) => BinaryOp::Mul,
+            Operator::Divide(_) | Operator::DivideAssign(_) => BinaryOp::Div,
+            Operator::Equal(_) => BinaryOp::Eq,
+            Operator::NotEqual(_) => BinaryOp::Ne,
+            Operator::Less(_) => BinaryOp::Lt,
+            Operator::LessEqual(_) => BinaryOp::Le,
+            Operator::Greater(_) => BinaryOp::Gt,
+            Operator::GreaterEqual(_) => BinaryOp::Ge,
+            _ => {
+                self.unsupported("the `in` operator", expression.span);
+                return None;
+            }
+        };
+
+        // An operand of unknown type takes the type of the other one.
+        let ty = match self.types.expressions.get(&lhs.id) {
+            Some(Type::Unknown) | None => self.ty(rhs),
+            Some(_) => self.ty(lhs),
+        };
+        let result = match op {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => ty,
+            _ => ValType::I32,
+        };
+
+        let lhs_operand = self.expect_type(lhs_operand, ty, lhs.span)?;
+        let rhs_operand = self.expect_type(rhs_operand, ty, rhs.span)?;
+
+        let dest = self.temp(result);
+        self.emit(Instruction::Binary {
+            dest,
+            op,
+            ty,
+            lhs: lhs_operand,
+            rhs: rhs_operand,
+        });
+        Some(Operand::Local(dest))
+    }
+
+    /// `&&` and `||` only evaluate their right hand side when the left doesn't
+    /// decide the result.
+    fn short_circuit(&mut self, lhs: &'a Expression, op: &Operator, rhs: &'a Expression) -> Option<Operand> {
+        let condition = self.expression(lhs)?;
+        let dest = self.temp(ValType::I32);
+
+        let (evaluated, value) = self.nested(|lowering| {
+            let value = lowering.expression(rhs)?;
+            lowering.emit(Instruction::Copy { dest, value });
+            Some(value)
+        });
+        value?;
+
+        let decided = |value| {
+            vec![Instruction::Copy {
+                dest,
+                value: Operand::I32(value),
+            }]
+        };
+
+        let (then, otherwise) = match op {
+            Operator::And(_) => (evaluated, decided(0)),
+            _ => (decided(1), evaluated),
+        };
+
+        self.emit(Instruction::If {
+            condition,
+            then,
+            otherwise,
+        });
+        Some(Operand::Local(dest))
+    }
+
+    fn invocation(&mut self, expression: &'a Expression, invocation: &'a FunctionInvocation) -> Option<Operand> {
+        let callee = &invocation.func;
+
+        // Calls to functions known by name are direct.
+        let declaration = match &callee.kind {
+            ExpressionKind::Identifier(ident) => self.resolution.references.get(&ident.id).copied(),
+            ExpressionKind::Member(object, name) => self.member(object, name),
+            _ => None,
+        };
+
+        let (target, receiver) = match declaration.and_then(|id| self.kind(id).map(|kind| (id, kind))) {
+            Some((id, DeclarationKind::Function)) | Some((id, DeclarationKind::Class)) => {
+                (self.ids.get(&id).copied().map(Callee::Function), None)
+            }
+            Some((id, DeclarationKind::Method)) => match &callee.kind {
+                ExpressionKind::Member(object, _) => {
+                    let receiver = self.expression(object)?;
+                    (self.ids.get(&id).copied().map(Callee::Function), Some(receiver))
+                }
+                _ => (None, None),
+            },
+            Some((id, DeclarationKind::Import { module })) => (Some(self.import(id, module, expression, invocation)?), None),
+            _ => (None, None),
+        };
+
+        let mut arguments: Vec<Operand> = receiver.into_iter().collect();
+
+        let (callee, signature) = match target {
+            Some(Callee::Function(func)) => {
+                let signature = self.module.functions.get(func.0 as usize)?.signature.clone();
+                (Callee::Function(func), signature)
+            }
+            Some(Callee::Import(import)) => {
+                let signature = self.module.imports.get(import.0 as usize)?.signature.clone();
+                (Callee::Import(import), signature)
+            }
+            // Anything else is a closure, called through the table with its
+            // environment as the first argument.
+            _ => {
+                let closure = self.expression(callee)?;
+                let table_index = self.temp(ValType::I32);
+                self.emit(Instruction::Load {
+                    dest: table_index,
+                    ty: ValType::I32,
+                    address: closure,
+                    offset: 0,
+                });
+                arguments.push(closure);
+
+                let signature = match self.types.expressions.get(&callee.id) {
+                    ty @ Some(Type::Function(..)) => signature(ty, 0, true),
+                    _ => self.call_site_signature(expression, invocation, true),
+                };
+                let indirect = Callee::Indirect {
+                    table_index: Operand::Local(table_index),
+                    signature: signature.clone(),
+                };
+                (indirect, signature)
+            }
+        };
+
+        for argument in invocation.parameters.iter() {
+            let operand = self.expression(argument)?;
+            let ty = signature.parameters.get(arguments.len()).copied().unwrap_or(ValType::I32);
+            arguments.push(self.expect_type(operand, ty, argument.span)?);
+        }
+
+        let dest = signature.result.map(|ty| self.temp(ty));
+        self.emit(Instruction::Call {
+            dest,
+            callee,
+            arguments,
+        });
+
+        // The value of a call without a result is never used, the checker made sure.
+        Some(dest.map_or(Operand::I32(0), Operand::Local))
+    }
+
+    /// The signature a call's arguments and result imply, for callees the checker
+    /// knows nothing about.
+    fn call_site_signature(&self, expression: &Expression, invocation: &FunctionInvocation, receiver: bool) -> Signature {
+        let mut parameters = Vec::new();
+        if receiver {
+            parameters.push(ValType::I32);
+        }
+        parameters.extend(invocation.parameters.iter().map(|argument| self.ty(argument)));
+
+        let result = match self.types.expressions.get(&expression.id) {
+            Some(Type::Unknown) | None => None,
+            Some(ty) => ValType::of(ty),
+        };
+
+        Signature { parameters, result }
+    }
+
+    /// The import declared by `id`, added to the module the first time it is called.
+    /// Without an annotation that first call fixes its signature, later calls that
+    /// don't match it are reported.
+    fn import(
+        &mut self,
+        id: NodeId,
+        module: &str,
+        expression: &Expression,
+        invocation: &FunctionInvocation,
+    ) -> Option<Callee> {
+        let name = self.resolution.declarations.get(&id).map_or(String::new(), |d| d.name.clone());
+        let signature = match self.types.bindings.get(&id) {
+            ty @ Some(Type::Function(..)) => signature(ty, 0, false),
+            _ => self.call_site_signature(expression, invocation, false),
+        };
+
+        if let Some(import) = self.imports.get(&id).copied() {
+            let expected = &self.module.imports[import.0 as usize].signature;
+            if *expected != signature {
+                let kind = LowerErrorKind::ImportSignature {
+                    name,
+                    expected: expected.clone(),
+                    found: signature,
+                };
+                self.errors.push(LowerError {
+                    kind,
+                    span: expression.span,
+                });
+                return None;
+            }
+            return Some(Callee::Import(import));
+        }
+
+        let import = ImportId(self.module.imports.len() as u32);
+        self.module.imports.push(Import {
+            module: module.to_string(),
+            name,
+            signature,
+        });
+        self.imports.insert(id, import);
+        Some(Callee::Import(import))
+    }
+
+    /// Creates a closure, an environment holding the lambda's table index and the
+    /// values it captures, and queues the lambda's body.
+    fn closure(&mut self, expression: &'a Expression, lambda: &'a Lambda) -> Option<Operand> {
+        let func = *self.ids.get(&expression.id)?;
+        let table_index = self.module.table.iter().position(|f| *f == func)? as i32;
+        let captured: &'a [String] = self
+            .captures
+            .get(&expression.id)
+            .map_or(&[], |names| names.as_slice());
+
+        let environment = self.temp(ValType::I32);
+        self.emit(Instruction::Alloc {
+            dest: environment,
+            size: 4 + 4 * captured.len() as u32,
+        });
+        self.emit(Instruction::Store {
+            ty: ValType::I32,
+            address: Operand::Local(environment),
+            offset: 0,
+            value: Operand::I32(table_index),
+        });
+
+        let mut types = Vec::new();
+        for (index, name) in captured.iter().enumerate() {
+            let local = self.current.scopes.iter().rev().find_map(|scope| scope.get(name)).copied();
+
+            let value = match local {
+                Some(local) => Some((self.current.locals[local.0 as usize], Operand::Local(local))),
+                None => self.capture(name).map(|(ty, offset)| {
+                    let place = Place::Memory {
+                        ty,
+                        address: Operand::Local(Local(0)),
+                        offset,
+                    };
+                    (ty, self.read(place))
+                }),
+            };
+
+            // Functions, classes and imports are called by name rather than
+            // through the environment, their slots stay unused.
+            if let Some((ty, value)) = value {
+                self.emit(Instruction::Store {
+                    ty,
+                    address: Operand::Local(environment),
+                    offset: 4 + 4 * index as u32,
+                    value,
+                });
+            }
+            types.push(value.map(|(ty, _)| ty));
+        }
+
+        self.capture_types.insert(expression.id, types);
+        self.queue.push(Body::Lambda(expression, lambda));
+        Some(Operand::Local(environment))
+    }
+}
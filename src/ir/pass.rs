@@ -0,0 +1,125 @@
+//! Transformations over an IR module, run in order by a `PassManager`.
+
+use super::*;
+
+pub trait Pass {
+    fn name(&self) -> &'static str;
+
+    fn run(&mut self, module: &mut Module);
+}
+
+/// Runs a sequence of passes over a module.
+#[derive(Default)]
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        PassManager::default()
+    }
+
+    /// The passes every module goes through before it is emitted.
+    pub fn standard() -> Self {
+        let mut manager = PassManager::new();
+        manager.add(FoldBranches).add(RemoveUnreachable);
+        manager
+    }
+
+    pub fn add<P: Pass + 'static>(&mut self, pass: P) -> &mut Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    pub fn run(&mut self, module: &mut Module) {
+        for pass in self.passes.iter_mut() {
+            pass.run(module);
+        }
+    }
+}
+
+/// Applies `f` to every instruction list of every function, innermost first.
+fn for_each_block<F>(module: &mut Module, f: &mut F)
+where
+    F: FnMut(&mut Vec<Instruction>),
+{
+    fn visit<F>(instructions: &mut Vec<Instruction>, f: &mut F)
+    where
+        F: FnMut(&mut Vec<Instruction>),
+    {
+        for instruction in instructions.iter_mut() {
+            match instruction {
+                Instruction::If { then, otherwise, .. } => {
+                    visit(then, f);
+                    visit(otherwise, f);
+                }
+                Instruction::Loop(body) => visit(body, f),
+                _ => {}
+            }
+        }
+        f(instructions);
+    }
+
+    for function in module.functions.iter_mut() {
+        visit(&mut function.body, f);
+    }
+}
+
+/// Replaces an `if` on a constant with the branch it always takes, which constant
+/// folding leaves behind for conditions known at compile time.
+pub struct FoldBranches;
+
+impl Pass for FoldBranches {
+    fn name(&self) -> &'static str {
+        "fold-branches"
+    }
+
+    fn run(&mut self, module: &mut Module) {
+        for_each_block(module, &mut |instructions| {
+            let mut folded = Vec::with_capacity(instructions.len());
+
+            for instruction in instructions.drain(..) {
+                match instruction {
+                    Instruction::If {
+                        condition: Operand::I32(value),
+                        then,
+                        otherwise,
+                    } => folded.extend(if value != 0 { then } else { otherwise }),
+                    instruction => folded.push(instruction),
+                }
+            }
+
+            *instructions = folded;
+        });
+    }
+}
+
+/// Drops the instructions after a `return`, `break` or `continue` in the same
+/// list, and after an `if` whose branches both end in one.
+pub struct RemoveUnreachable;
+
+impl RemoveUnreachable {
+    fn diverges(instruction: &Instruction) -> bool {
+        match instruction {
+            Instruction::If { then, otherwise, .. } => {
+                let ends = |block: &Vec<Instruction>| block.last().is_some_and(Self::diverges);
+                ends(then) && ends(otherwise)
+            }
+            instruction => instruction.is_terminator(),
+        }
+    }
+}
+
+impl Pass for RemoveUnreachable {
+    fn name(&self) -> &'static str {
+        "remove-unreachable"
+    }
+
+    fn run(&mut self, module: &mut Module) {
+        for_each_block(module, &mut |instructions| {
+            if let Some(end) = instructions.iter().position(Self::diverges) {
+                instructions.truncate(end + 1);
+            }
+        });
+    }
+}
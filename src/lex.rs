@@ -1,16 +1,80 @@
 use itertools::Itertools;
 use std::fmt;
 
+/// A byte range into the source text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    /// The 1 based line and column the span starts at.
+    pub fn line_col(&self, src: &str) -> (usize, usize) {
+        let before = src.get(..self.start).unwrap_or(src);
+        let line = before.matches('\n').count() + 1;
+        let col = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+        (line, col)
+    }
+}
+
 #[derive(Debug, Clone)]
-pub struct LexError;
+pub enum LexErrorKind {
+    UnexpectedCharacter(char),
+    UnterminatedString,
+    IntegerOutOfRange,
+}
 
 #[derive(Debug, Clone)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            LexErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character `{}`", c),
+            LexErrorKind::UnterminatedString => write!(f, "unterminated string literal"),
+            LexErrorKind::IntegerOutOfRange => write!(f, "integer literal is out of range"),
+        }
+    }
+}
+
+/// Length in characters of the longest operator `Operator::match_string` accepts.
+const MAX_OPERATOR_LEN: usize = 2;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Operator {
     Add(String),
     Subtract(String),
     Multiply(String),
     Divide(String),
     Assign(String),
+    AddAssign(String),
+    SubtractAssign(String),
+    MultiplyAssign(String),
+    DivideAssign(String),
+    Equal(String),
+    NotEqual(String),
+    Less(String),
+    LessEqual(String),
+    Greater(String),
+    GreaterEqual(String),
+    In(String),
+    Not(String),
+    And(String),
+    Or(String),
+    Arrow(String),
 }
 
 impl Operator {
@@ -21,17 +85,88 @@ impl Operator {
             "*" => Some(Operator::Multiply("*".to_string())),
             "/" => Some(Operator::Divide("/".to_string())),
             "=" => Some(Operator::Assign("=".to_string())),
+            "+=" => Some(Operator::AddAssign("+=".to_string())),
+            "-=" => Some(Operator::SubtractAssign("-=".to_string())),
+            "*=" => Some(Operator::MultiplyAssign("*=".to_string())),
+            "/=" => Some(Operator::DivideAssign("/=".to_string())),
+            "==" => Some(Operator::Equal("==".to_string())),
+            "!=" => Some(Operator::NotEqual("!=".to_string())),
+            "<" => Some(Operator::Less("<".to_string())),
+            "<=" => Some(Operator::LessEqual("<=".to_string())),
+            ">" => Some(Operator::Greater(">".to_string())),
+            ">=" => Some(Operator::GreaterEqual(">=".to_string())),
+            "!" => Some(Operator::Not("!".to_string())),
+            "&&" => Some(Operator::And("&&".to_string())),
+            "||" => Some(Operator::Or("||".to_string())),
+            "=>" => Some(Operator::Arrow("=>".to_string())),
+            _ => None,
+        }
+    }
+
+    /// Matches operators spelled as words, which the lexer hands out as `Token::Word`.
+    pub fn match_keyword(value: &str) -> Option<Operator> {
+        match value {
+            "in" => Some(Operator::In("in".to_string())),
             _ => None,
         }
     }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Operator::Add(s)
+            | Operator::Subtract(s)
+            | Operator::Multiply(s)
+            | Operator::Divide(s)
+            | Operator::Assign(s)
+            | Operator::AddAssign(s)
+            | Operator::SubtractAssign(s)
+            | Operator::MultiplyAssign(s)
+            | Operator::DivideAssign(s)
+            | Operator::Equal(s)
+            | Operator::NotEqual(s)
+            | Operator::Less(s)
+            | Operator::LessEqual(s)
+            | Operator::Greater(s)
+            | Operator::GreaterEqual(s)
+            | Operator::In(s)
+            | Operator::Not(s)
+            | Operator::And(s)
+            | Operator::Or(s)
+            | Operator::Arrow(s) => s,
+        }
+    }
+
+    /// Whether this operator stores its result into its left hand side.
+    pub fn is_assignment(&self) -> bool {
+        matches!(
+            self,
+            Operator::Assign(_)
+                | Operator::AddAssign(_)
+                | Operator::SubtractAssign(_)
+                | Operator::MultiplyAssign(_)
+                | Operator::DivideAssign(_)
+        )
+    }
+
 }
 
-#[derive(Debug)]
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum Token {
     IntLiteral(i32),
+    FloatLiteral(f32),
+    StringLiteral(String),
     Word(String),
     Operator(Operator),
     Stop, // Semicolon
+    Comma,
+    Dot,
+    Colon,
     OpenParen,
     CloseParen,
     OpenBracket,
@@ -40,15 +175,42 @@ pub enum Token {
     CloseBrace,
 }
 
-impl Token {
-    pub fn is_int_literal(&self) -> bool {
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Token::IntLiteral(_) => true,
-            _ => false,
+            Token::IntLiteral(val) => write!(f, "{}", val),
+            Token::FloatLiteral(val) => write!(f, "{:?}", val),
+            Token::StringLiteral(val) => write!(f, "{:?}", val),
+            Token::Word(word) => write!(f, "{}", word),
+            Token::Operator(op) => write!(f, "{}", op),
+            Token::Stop => write!(f, ";"),
+            Token::Comma => write!(f, ","),
+            Token::Dot => write!(f, "."),
+            Token::Colon => write!(f, ":"),
+            Token::OpenParen => write!(f, "("),
+            Token::CloseParen => write!(f, ")"),
+            Token::OpenBracket => write!(f, "["),
+            Token::CloseBracket => write!(f, "]"),
+            Token::OpenBrace => write!(f, "{{"),
+            Token::CloseBrace => write!(f, "}}"),
         }
     }
 }
 
+impl Token {
+    pub fn is_int_literal(&self) -> bool {
+        matches!(self, Token::IntLiteral(_))
+    }
+
+    pub fn is_float_literal(&self) -> bool {
+        matches!(self, Token::FloatLiteral(_))
+    }
+
+    pub fn is_string_literal(&self) -> bool {
+        matches!(self, Token::StringLiteral(_))
+    }
+}
+
 pub struct TokenStream<'c> {
     src: &'c str,
     index: usize,
@@ -58,6 +220,24 @@ impl<'c> TokenStream<'c> {
     pub fn new(src: &'c str) -> Self {
         Self { src, index: 0 }
     }
+
+    /// Advances past whitespace, `// line` and `/* block */` comments.
+    fn skip_trivia(&mut self) {
+        loop {
+            let rest = &self.src[self.index..];
+            let trimmed = rest.trim_start();
+            self.index += rest.len() - trimmed.len();
+
+            if trimmed.starts_with("//") {
+                self.index += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else if let Some(comment) = trimmed.strip_prefix("/*") {
+                // An unterminated block comment runs to the end of the file.
+                self.index += comment.find("*/").map_or(trimmed.len(), |end| end + 4);
+            } else {
+                return;
+            }
+        }
+    }
 }
 
 trait TakeInt {
@@ -69,22 +249,23 @@ impl<'c> TakeInt for TokenStream<'c> {
         use std::num::ParseIntError;
         let iter = self.src[self.index..].char_indices();
 
-        let mut raw_val: Vec<(usize, char)> = iter
+        let raw_val: Vec<(usize, char)> = iter
             .skip_while(|(_, c)| c.is_whitespace())
-            .take_while_ref(|(_, c)| c.is_numeric())
+            .take_while_ref(|(_, c)| c.is_ascii_digit())
             .collect();
 
-        let last_index = raw_val.last().unwrap_or(&(0 as usize, 0 as char)).0;
-        let val: String = (&mut raw_val).iter().map(|(_, c)| c).collect();
+        // Byte offset just past the last character, which may be wider than one byte.
+        let end = raw_val.last().map_or(0, |(i, c)| i + c.len_utf8());
+        let val: String = raw_val.iter().map(|(_, c)| c).collect();
 
-        if val.len() == 0 {
+        if val.is_empty() {
             return None;
         }
 
         let val: Result<i32, ParseIntError> = val.parse();
 
         if val.is_ok() {
-            self.index += last_index + 1;
+            self.index += end;
         }
 
         match val {
@@ -94,28 +275,103 @@ impl<'c> TakeInt for TokenStream<'c> {
     }
 }
 
+trait TakeFloat {
+    fn take_float_literal(&mut self) -> Option<Token>;
+}
+
+impl<'c> TakeFloat for TokenStream<'c> {
+    /// Lexes `digits.digits`, a trailing dot on its own is left for member access.
+    fn take_float_literal(&mut self) -> Option<Token> {
+        let rest = &self.src[self.index..];
+
+        let int_len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        if int_len == 0 || !rest[int_len..].starts_with('.') {
+            return None;
+        }
+
+        let fraction = &rest[int_len + 1..];
+        let fraction_len = fraction
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(fraction.len());
+        if fraction_len == 0 {
+            return None;
+        }
+
+        let end = int_len + 1 + fraction_len;
+        let val: f32 = rest[..end].parse().ok()?;
+        self.index += end;
+
+        Some(Token::FloatLiteral(val))
+    }
+}
+
+trait TakeString {
+    fn take_string_literal(&mut self) -> Option<Token>;
+}
+
+impl<'c> TakeString for TokenStream<'c> {
+    fn take_string_literal(&mut self) -> Option<Token> {
+        let mut iter = self.src[self.index..].char_indices();
+
+        match iter.next() {
+            Some((_, '"')) => {}
+            _ => return None,
+        }
+
+        let mut val = String::new();
+
+        while let Some((i, c)) = iter.next() {
+            match c {
+                '"' => {
+                    self.index += i + 1;
+                    return Some(Token::StringLiteral(val));
+                }
+                '\\' => match iter.next() {
+                    Some((_, 'n')) => val.push('\n'),
+                    Some((_, 't')) => val.push('\t'),
+                    Some((_, 'r')) => val.push('\r'),
+                    Some((_, '0')) => val.push('\0'),
+                    Some((_, c)) => val.push(c),
+                    None => break,
+                },
+                c => val.push(c),
+            }
+        }
+
+        // Unterminated string literal.
+        None
+    }
+}
+
 trait TakeOperator {
     fn take_operator(&mut self) -> Option<Token>;
 }
 
 impl<'c> TakeOperator for TokenStream<'c> {
     fn take_operator(&mut self) -> Option<Token> {
-        let mut raw_val: Vec<(usize, char)> = self.src[self.index..]
+        let raw_val: Vec<(usize, char)> = self.src[self.index..]
             .char_indices()
             .skip_while(|(_, c)| c.is_whitespace())
             .take_while(|(_, c)| !c.is_whitespace() && !c.is_alphanumeric())
+            // Only look as far as the longest operator, long runs of symbols
+            // like `((((` would otherwise be rescanned for every token.
+            .take(MAX_OPERATOR_LEN)
             .collect();
 
-        let last_index = raw_val.last().unwrap_or(&(0 as usize, 0 as char)).0;
-        let val: String = (&mut raw_val).iter().map(|(_, c)| c).collect();
+        let val: String = raw_val.iter().map(|(_, c)| c).collect();
 
-        if val.len() == 0 {
+        if val.is_empty() {
             return None;
         }
 
-        if let Some(op) = Operator::match_string(&val) {
-            self.index += last_index + 1;
-            return Some(Token::Operator(op));
+        // The run of symbols may continue into punctuation (`+(`, `);`), so
+        // take the longest prefix that is an operator.
+        for (end, c) in raw_val.iter().rev() {
+            let end = end + c.len_utf8();
+            if let Some(op) = Operator::match_string(&val[..end - raw_val[0].0]) {
+                self.index += end;
+                return Some(Token::Operator(op));
+            }
         }
 
         None
@@ -150,20 +406,21 @@ trait TakeWord {
 
 impl<'c> TakeWord for TokenStream<'c> {
     fn take_word(&mut self) -> Option<Token> {
-        let mut raw_val: Vec<(usize, char)> = self.src[self.index..]
+        let raw_val: Vec<(usize, char)> = self.src[self.index..]
             .char_indices()
             .skip_while(|(_, c)| c.is_whitespace())
             .take_while_ref(|(_, c)| c.is_alphanumeric() || *c == '_')
             .collect();
 
-        let last_index = raw_val.last().unwrap_or(&(0 as usize, 0 as char)).0;
-        let val: String = (&mut raw_val).iter().map(|(_, c)| c).collect();
+        // Byte offset just past the last character, which may be wider than one byte.
+        let end = raw_val.last().map_or(0, |(i, c)| i + c.len_utf8());
+        let val: String = raw_val.iter().map(|(_, c)| c).collect();
 
-        if val.len() == 0 {
+        if val.is_empty() {
             return None;
         }
 
-        self.index += last_index + 1;
+        self.index += end;
         Some(Token::Word(val))
     }
 }
@@ -205,31 +462,54 @@ impl<'c> TakePunctuation for TokenStream<'c> {
                 self.index += i + 1;
                 Some(Token::CloseBrace)
             }
+            ',' => {
+                self.index += i + 1;
+                Some(Token::Comma)
+            }
+            '.' => {
+                self.index += i + 1;
+                Some(Token::Dot)
+            }
+            ':' => {
+                self.index += i + 1;
+                Some(Token::Colon)
+            }
             _ => None,
         }
     }
 }
 
-impl<'c> Iterator for TokenStream<'c> {
-    type Item = Token;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.index >= self.src.len() {
-            return None;
-        }
-
+impl<'c> TokenStream<'c> {
+    fn take_token(&mut self) -> Option<Token> {
         //Lex semicolons.
         let tok = self.take_stop();
         if tok.is_some() {
             return tok;
         }
 
+        // Lex float literals, before integers which would stop at the dot.
+        let tok = self.take_float_literal();
+        if tok.is_some() {
+            return tok;
+        }
+
         // Lex integer literals.
         let tok = self.take_int_literal();
         if tok.is_some() {
             return tok;
         }
 
+        // Digits that didn't make an integer literal would otherwise lex as a word.
+        if self.src[self.index..].starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+
+        // Lex string literals.
+        let tok = self.take_string_literal();
+        if tok.is_some() {
+            return tok;
+        }
+
         // Lex operators.
         let tok = self.take_operator();
         if tok.is_some() {
@@ -250,4 +530,48 @@ impl<'c> Iterator for TokenStream<'c> {
 
         None
     }
+
+    /// Skips over input that no token matched and describes why.
+    fn take_error(&mut self) -> LexError {
+        let start = self.index;
+        let rest = &self.src[start..];
+
+        let (kind, len) = match rest.chars().next() {
+            Some('"') => (LexErrorKind::UnterminatedString, rest.len()),
+            Some(c) if c.is_ascii_digit() => {
+                let len = rest
+                    .find(|c: char| !c.is_alphanumeric())
+                    .unwrap_or(rest.len());
+                (LexErrorKind::IntegerOutOfRange, len)
+            }
+            Some(c) => (LexErrorKind::UnexpectedCharacter(c), c.len_utf8()),
+            None => (LexErrorKind::UnexpectedCharacter('\0'), 0),
+        };
+
+        self.index += len;
+
+        LexError {
+            kind,
+            span: Span::new(start, self.index),
+        }
+    }
+}
+
+impl<'c> Iterator for TokenStream<'c> {
+    type Item = Result<(Token, Span), LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.skip_trivia();
+
+        if self.index >= self.src.len() {
+            return None;
+        }
+
+        let start = self.index;
+
+        match self.take_token() {
+            Some(tok) => Some(Ok((tok, Span::new(start, self.index)))),
+            None => Some(Err(self.take_error())),
+        }
+    }
 }
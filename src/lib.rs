@@ -0,0 +1,13 @@
+pub mod ast;
+pub mod capture;
+pub mod check;
+pub mod emit;
+pub mod flow;
+pub mod fold;
+pub mod ir;
+pub mod lex;
+pub mod lint;
+pub mod parse;
+pub mod resolve;
+pub mod suggest;
+pub mod types;
@@ -0,0 +1,110 @@
+//! Warnings about code that is allowed but probably a mistake.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::ast::visit::{self, Visitor};
+use crate::ast::*;
+use crate::suggest;
+
+#[derive(Clone, Debug)]
+pub enum WarningKind {
+    /// `object.name` is read, but no class, object literal or assignment anywhere
+    /// in the program gives anything a property called `name`.
+    UndeclaredProperty(String),
+    /// A statement after a `return`, `break` or `continue` that always runs, see `flow`.
+    Unreachable,
+}
+
+#[derive(Clone, Debug)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub span: Span,
+    /// A known name close to the one the warning is about.
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            WarningKind::UndeclaredProperty(name) => {
+                write!(f, "property `{}` is read but never declared or assigned", name)
+            }
+            WarningKind::Unreachable => write!(f, "unreachable statement"),
+        }
+    }
+}
+
+/// Lints a program that parsed without errors, returning every warning sorted by position.
+pub fn lint(program: &Program) -> Vec<Warning> {
+    let mut properties = Properties {
+        declared: HashSet::new(),
+        read: Vec::new(),
+    };
+    properties.visit_program(program);
+
+    let mut warnings: Vec<Warning> = properties
+        .read
+        .iter()
+        .filter(|name| !properties.declared.contains(&name.name))
+        .map(|name| {
+            let declared = properties.declared.iter().map(String::as_str);
+            Warning {
+                kind: WarningKind::UndeclaredProperty(name.name.clone()),
+                span: name.span,
+                suggestion: suggest::closest(&name.name, declared).map(str::to_string),
+            }
+        })
+        .collect();
+
+    warnings.sort_by_key(|w| w.span.start);
+    warnings
+}
+
+/// Collects every property name that is declared or assigned, and every property read.
+struct Properties {
+    declared: HashSet<String>,
+    read: Vec<Ident>,
+}
+
+impl Visitor for Properties {
+    fn visit_class(&mut self, class: &ClassDeclaration) {
+        for member in class.members.iter() {
+            let name = match member {
+                ClassMember::Property(variable) | ClassMember::Static(variable) => &variable.name,
+                ClassMember::Method(method) => &method.name,
+            };
+            self.declared.insert(name.name.clone());
+        }
+
+        visit::walk_class(self, class);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        match &expression.kind {
+            ExpressionKind::Object(properties) => {
+                for property in properties.iter() {
+                    self.declared.insert(property.key.name.clone());
+                }
+            }
+            ExpressionKind::Assignment(target, _, value) => {
+                if let ExpressionKind::Member(object, name) = &target.kind {
+                    self.declared.insert(name.name.clone());
+                    self.visit_expression(object);
+                    self.visit_expression(value);
+                    return;
+                }
+            }
+            ExpressionKind::Member(object, name) => {
+                // Members of `this` are checked against the class by the resolver.
+                let on_this = matches!(&object.kind, ExpressionKind::Identifier(ident) if ident.name == "this");
+                if !on_this {
+                    self.read.push(name.clone());
+                }
+            }
+            _ => {}
+        }
+
+        visit::walk_expression(self, expression);
+    }
+}
@@ -1,54 +1,236 @@
-use std::{env, error::Error, fs, fs::File, io::Write};
+use std::{env, error::Error, fmt::Display, fs, io, path::Path, process};
+
+use lsc::ast::Span;
+use lsc::capture;
+use lsc::check;
+use lsc::emit::{decode, wat, Entry, ModuleEmitter};
+use lsc::flow::{self, FlowErrorKind};
+use lsc::fold;
+use lsc::ir::{lower, pass::PassManager};
+use lsc::lex::TokenStream;
+use lsc::lint;
+use lsc::parse::Parser;
+use lsc::resolve::{self, ResolveErrorKind};
 
-mod lex;
-use lex::{Token, TokenStream};
+fn print_usage(command: &str) {
+    println!("USAGE: {} [--emit=wasm|wat|wat-folded|ast|ir] [--entry=_start|main|start] source_files", command);
+    println!("       {} objdump wasm_files", command);
+}
 
-mod emit;
-use emit::ModuleEmitter;
+/// Lists the sections of wasm modules and disassembles their code, returning
+/// whether every file decoded.
+fn objdump(paths: &[String]) -> Result<bool, Box<dyn Error>> {
+    let mut decoded = true;
 
-mod parse;
-use parse::Parser;
+    for path in paths {
+        match decode::decode(&fs::read(path)?) {
+            Ok(module) => {
+                println!("{}: file format wasm 0x1\n", path);
+                print!("{}", module.objdump());
+            }
+            Err(e) => {
+                eprintln!("{}: error: {}", path, e);
+                decoded = false;
+            }
+        }
+    }
 
-fn print_usage(command: &str) {
-    println!("USAGE: {} source_files", command);
+    Ok(decoded)
+}
+
+/// What to output for each file that compiles.
+#[derive(Clone, Copy, PartialEq)]
+enum Emit {
+    /// A wasm module written next to the source file.
+    Wasm,
+    /// The module in the text format.
+    Wat(wat::Style),
+    /// The syntax tree after constant folding.
+    Ast,
+    /// The IR after the standard passes.
+    Ir,
+}
+
+fn report(path: &str, src: &str, span: Span, severity: &str, message: impl Display) {
+    let (line, col) = span.line_col(src);
+    eprintln!("{}:{}:{}: {}: {}", path, line, col, severity, message);
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() < 2 {
+    if args.get(1).map(String::as_str) == Some("objdump") {
+        if args.len() == 2 {
+            print_usage(args[0].as_ref());
+        }
+        if !objdump(&args[2..])? {
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let mut emit = Emit::Wasm;
+    let mut emitter = ModuleEmitter::default();
+    let mut file_paths: Vec<&String> = Vec::new();
+
+    for arg in args[1..].iter() {
+        if let Some(output) = arg.strip_prefix("--emit=") {
+            emit = match output {
+                "wasm" => Emit::Wasm,
+                "wat" => Emit::Wat(wat::Style::Flat),
+                "wat-folded" => Emit::Wat(wat::Style::Folded),
+                "ast" => Emit::Ast,
+                "ir" => Emit::Ir,
+                _ => {
+                    print_usage(args[0].as_ref());
+                    return Err(format!("unknown output `{}`", arg).into());
+                }
+            };
+        } else if let Some(entry) = arg.strip_prefix("--entry=") {
+            emitter.entry = match entry {
+                "start" => Entry::Start,
+                "_start" | "main" => Entry::Export(entry.to_string()),
+                _ => {
+                    print_usage(args[0].as_ref());
+                    return Err(format!("unknown entry point `{}`", arg).into());
+                }
+            };
+        } else {
+            file_paths.push(arg);
+        }
+    }
+
+    if file_paths.is_empty() {
         print_usage(args[0].as_ref());
     }
 
-    let file_paths: &[String] = &args[1..];
+    let mut failed = false;
 
     for path in file_paths {
-        let src = fs::read_to_string(path)?;
+        let output = Path::new(path).with_extension("wasm");
+
+        // Invalid UTF-8 is replaced rather than rejected, the lexer reports the
+        // replacement characters like any other character it doesn't understand.
+        let src = String::from_utf8_lossy(&fs::read(path)?).into_owned();
 
         let tokstrm = TokenStream::new(&src);
 
         let mut parser = Parser::new(tokstrm);
 
-        let parse_result = parser.parse();
+        let (mut program, errors) = parser.parse();
 
-        match parse_result {
-            Err(e) => {
-                println!("ERR");
-            },
-            _ => {
-                println!("goodnight!");
-            }
+        for e in errors.iter() {
+            report(path, &src, e.span(), "error", e);
         }
+        let mut has_errors = !errors.is_empty();
+
+        // Statements with syntax errors are missing from the tree, which would
+        // only lead to misleading semantic errors.
+        if !has_errors {
+            let (resolution, resolve_errors) = resolve::resolve(&program);
+
+            for e in resolve_errors.iter() {
+                report(path, &src, e.span, "error", e);
+                if let ResolveErrorKind::Duplicate { previous, .. } = &e.kind {
+                    report(path, &src, *previous, "note", "previously declared here");
+                }
+                if let Some(suggestion) = &e.suggestion {
+                    report(path, &src, e.span, "help", format!("did you mean `{}`?", suggestion));
+                }
+            }
+            has_errors |= !resolve_errors.is_empty();
 
-    }
+            for w in lint::lint(&program).iter() {
+                report(path, &src, w.span, "warning", w);
+                if let Some(suggestion) = &w.suggestion {
+                    report(path, &src, w.span, "help", format!("did you mean `{}`?", suggestion));
+                }
+            }
 
-    //let emitter = ModuleEmitter;
+            let (types, type_errors) = check::check(&program);
 
-    //let bincode = emitter.emit();
+            for e in type_errors.iter() {
+                report(path, &src, e.span, "error", e);
+            }
+            has_errors |= !type_errors.is_empty();
+
+            // Folding relies on every identifier being bound to its declaration.
+            if resolve_errors.is_empty() {
+                let (folded, fold_errors) = fold::fold(program, &resolution);
+
+                for e in fold_errors.iter() {
+                    report(path, &src, e.span, "error", e);
+                }
+                has_errors |= !fold_errors.is_empty();
+
+                program = folded;
+
+                // Conditions are analyzed after folding, `while true` never exits.
+                let (flow_errors, flow_warnings) = flow::analyze(&program, &resolution);
+
+                for e in flow_errors.iter() {
+                    report(path, &src, e.span, "error", e);
+                    if let FlowErrorKind::UseBeforeAssignment { declaration, .. } = &e.kind {
+                        report(path, &src, *declaration, "note", "declared here");
+                    }
+                }
+                has_errors |= !flow_errors.is_empty();
+
+                for w in flow_warnings.iter() {
+                    report(path, &src, w.span, "warning", w);
+                }
+
+                if emit != Emit::Ast && !has_errors {
+                    let captures = capture::analyze(&program);
+                    let (mut module, lower_errors) = lower::lower(&program, &resolution, &types, &captures);
+
+                    for e in lower_errors.iter() {
+                        report(path, &src, e.span, "error", e);
+                    }
+                    has_errors |= !lower_errors.is_empty();
+
+                    if lower_errors.is_empty() {
+                        PassManager::standard().run(&mut module);
+
+                        match emit {
+                            Emit::Ir => print!("{}", module),
+                            Emit::Wat(style) => match emitter.wat(&module, style) {
+                                Ok(text) => print!("{}", text),
+                                Err(e) => {
+                                    eprintln!("{}: error: {}", path, e);
+                                    has_errors = true;
+                                }
+                            },
+                            _ => match emitter.emit(&module) {
+                                Ok(bytes) => fs::write(&output, bytes)?,
+                                Err(e) => {
+                                    eprintln!("{}: error: {}", path, e);
+                                    has_errors = true;
+                                }
+                            },
+                        }
+                    }
+                }
+            }
+        }
+
+        if emit == Emit::Ast {
+            println!("{:#?}", program);
+        }
+
+        // A module left over from an earlier build would pass for the output of this one.
+        if has_errors && emit == Emit::Wasm {
+            match fs::remove_file(&output) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        failed |= has_errors;
+    }
 
-    //println!("{:?}", bincode);
+    if failed {
+        process::exit(1);
+    }
 
-    //let mut out = File::create("a.wasm")?;
-    //out.write(&bincode)?;
     Ok(())
 }
@@ -1,36 +1,99 @@
-use crate::lex::{Operator, Token, TokenStream};
+use crate::ast::*;
+use crate::lex::{LexError, LexErrorKind, Token, TokenStream};
+use std::fmt;
 
-#[derive(Debug)]
-struct FunctionPointer;
+mod statement;
 
-#[derive(Debug)]
-struct FunctionInvocation {
-    func: FunctionPointer,
-    parameters: Vec<Expression>,
+#[derive(Clone, Debug)]
+pub enum ParseError {
+    /// The parser found `found` (or the end of the file) where it expected one of `expected`.
+    Unexpected {
+        expected: Vec<&'static str>,
+        found: Option<Token>,
+        span: Span,
+    },
+    InvalidAssignmentTarget {
+        op: Operator,
+        span: Span,
+    },
+    /// Expressions or blocks nested deeper than `MAX_NESTING`.
+    TooDeeplyNested {
+        span: Span,
+    },
+    Lex(LexError),
 }
 
-#[derive(Debug)]
-enum ValueType {
-    Number(i32),
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::Unexpected { span, .. } => *span,
+            ParseError::InvalidAssignmentTarget { span, .. } => *span,
+            ParseError::TooDeeplyNested { span } => *span,
+            ParseError::Lex(e) => e.span,
+        }
+    }
 }
 
-#[derive(Debug)]
-enum Expression {
-    Singleton(ValueType),
-    Invocation(FunctionInvocation),
-    Operation(Box<Expression>, Operator, Box<Expression>),
-}
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Unexpected {
+                expected, found, ..
+            } => {
+                match expected.as_slice() {
+                    [] => write!(f, "unexpected ")?,
+                    [one] => write!(f, "expected {}, found ", one)?,
+                    [init @ .., last] => write!(f, "expected {} or {}, found ", init.join(", "), last)?,
+                }
 
-#[derive(Clone, Debug)]
-pub enum ParseError {
-    Unexpected,
+                match found {
+                    Some(tok) => write!(f, "`{}`", tok),
+                    None => write!(f, "end of file"),
+                }
+            }
+            ParseError::InvalidAssignmentTarget { op, .. } => write!(
+                f,
+                "invalid left hand side of `{}`, expected a variable, member or index",
+                op
+            ),
+            ParseError::TooDeeplyNested { .. } => {
+                write!(f, "nesting exceeds the limit of {} levels", MAX_NESTING)
+            }
+            ParseError::Lex(e) => write!(f, "{}", e),
+        }
+    }
 }
 
 type PResult<T> = Result<T, ParseError>;
 
+/// Binding power of a binary operator, higher binds tighter.
+fn infix_precedence(op: &Operator) -> Option<u8> {
+    match op {
+        Operator::Or(_) => Some(1),
+        Operator::And(_) => Some(2),
+        Operator::Equal(_) | Operator::NotEqual(_) => Some(3),
+        Operator::Less(_)
+        | Operator::LessEqual(_)
+        | Operator::Greater(_)
+        | Operator::GreaterEqual(_)
+        | Operator::In(_) => Some(4),
+        Operator::Add(_) | Operator::Subtract(_) => Some(5),
+        Operator::Multiply(_) | Operator::Divide(_) => Some(6),
+        _ => None,
+    }
+}
+
+/// How deeply blocks and expressions may nest. The parser and every pass over the
+/// tree recurse once per level, so this keeps hostile input from overflowing the stack.
+const MAX_NESTING: usize = 256;
+
 pub struct Parser {
     toks: Vec<Token>,
+    spans: Vec<Span>,
     index: usize,
+    errors: Vec<ParseError>,
+    depth: usize,
+    next_id: u32,
 }
 
 impl Parser {
@@ -44,11 +107,11 @@ impl Parser {
         }
     }
 
-    fn peek(&mut self) -> Option<&Token> {
+    fn peek(&self) -> Option<&Token> {
         self.npeek(0)
     }
 
-    fn npeek(&mut self, n: usize) -> Option<&Token> {
+    fn npeek(&self, n: usize) -> Option<&Token> {
         if self.index + n >= self.toks.len() {
             None
         } else {
@@ -57,101 +120,617 @@ impl Parser {
     }
 
     pub fn new(tokstrm: TokenStream) -> Self {
-        let toks = tokstrm.collect();
+        let mut toks = Vec::new();
+        let mut spans = Vec::new();
+        let mut errors = Vec::new();
+
+        for lexed in tokstrm {
+            match lexed {
+                Ok((tok, span)) => {
+                    toks.push(tok);
+                    spans.push(span);
+                }
+                Err(e) => {
+                    // Stand in for malformed literals so they aren't reported a second
+                    // time as a missing expression.
+                    let placeholder = match e.kind {
+                        LexErrorKind::IntegerOutOfRange => Some(Token::IntLiteral(0)),
+                        LexErrorKind::UnterminatedString => Some(Token::StringLiteral(String::new())),
+                        LexErrorKind::UnexpectedCharacter(_) => None,
+                    };
+
+                    if let Some(tok) = placeholder {
+                        toks.push(tok);
+                        spans.push(e.span);
+                    }
+
+                    errors.push(ParseError::Lex(e));
+                }
+            }
+        }
+
+        Self {
+            toks,
+            spans,
+            index: 0,
+            errors,
+            depth: 0,
+            next_id: 0,
+        }
+    }
+
+    /// Parses every statement in the file, returning what could be parsed along
+    /// with every error encountered. Statements containing a syntax error are left out.
+    pub fn parse(&mut self) -> (Program, Vec<ParseError>) {
+        let mut statements = Vec::new();
+
+        while self.peek().is_some() {
+            if let Some(statement) = self.parse_statement_or_recover() {
+                statements.push(statement);
+            }
+        }
+
+        let mut errors = std::mem::take(&mut self.errors);
+        errors.sort_by_key(|e| e.span().start);
+
+        (Program { statements }, errors)
+    }
+
+    fn new_id(&mut self) -> NodeId {
+        let id = NodeId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    fn expression(&mut self, start: usize, kind: ExpressionKind) -> Expression {
+        Expression {
+            id: self.new_id(),
+            span: self.span_from(start),
+            kind,
+        }
+    }
 
-        Self { toks, index: 0 }
+    /// The span of the next token, or an empty span at the end of the file.
+    fn peek_span(&self) -> Span {
+        match self.spans.get(self.index) {
+            Some(span) => *span,
+            None => {
+                let end = self.spans.last().map_or(0, |span| span.end);
+                Span::new(end, end)
+            }
+        }
     }
 
-    pub fn parse(&mut self) -> PResult<()> {
-        let expr = self.try_parse_expression();
+    /// The span from the start of the token at `start` to the end of the last consumed token.
+    fn span_from(&self, start: usize) -> Span {
+        let first = self.spans.get(start).copied().unwrap_or_else(|| self.peek_span());
+        match self.index.checked_sub(1).and_then(|last| self.spans.get(last)) {
+            Some(last) if self.index > start => first.to(*last),
+            _ => first,
+        }
+    }
 
-        println!("{:?}", expr);
+    /// An error for the next token, which is left unconsumed.
+    fn unexpected(&self, expected: &[&'static str]) -> ParseError {
+        ParseError::Unexpected {
+            expected: expected.to_vec(),
+            found: self.peek().cloned(),
+            span: self.peek_span(),
+        }
+    }
 
+    /// Consumes the next token if it is the same kind of token as `tok`.
+    fn expect(&mut self, tok: Token, description: &'static str) -> PResult<()> {
+        match self.peek() {
+            Some(next) if std::mem::discriminant(next) == std::mem::discriminant(&tok) => {
+                self.next();
+                Ok(())
+            }
+            _ => Err(self.unexpected(&[description])),
+        }
+    }
+
+    /// Enters one more level of nesting, failing once `MAX_NESTING` is exceeded.
+    /// Callers that descend save `self.depth` beforehand and restore it when done.
+    fn descend(&mut self) -> PResult<()> {
+        if self.depth >= MAX_NESTING {
+            return Err(ParseError::TooDeeplyNested {
+                span: self.peek_span(),
+            });
+        }
+
+        self.depth += 1;
         Ok(())
     }
 
-    fn peek_operator(&mut self) -> bool {
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(word)) if word == keyword)
+    }
+
+    /// Consumes the next token if it is the given keyword.
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek_keyword(keyword);
+        if found {
+            self.next();
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &'static str) -> PResult<()> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&[keyword]))
+        }
+    }
+
+    fn expect_ident(&mut self) -> PResult<Ident> {
         match self.peek() {
-            None => {
-                println!("Matched none");
-                return false;
+            Some(Token::Word(word)) => {
+                let name = word.clone();
+                let span = self.peek_span();
+                self.next();
+                Ok(Ident {
+                    id: self.new_id(),
+                    name,
+                    span,
+                })
             }
-            Some(tok) => match tok {
-                Token::Operator(_) => {
-                    println!("Matched op");
-                    return true;
-                }
-                _ => {
-                    return false;
+            _ => Err(self.unexpected(&["identifier"])),
+        }
+    }
+
+    fn expect_stop(&mut self) -> PResult<()> {
+        self.expect(Token::Stop, "`;`")
+    }
+
+    /// Parses an optional `: T`.
+    fn try_parse_type_annotation(&mut self) -> PResult<Option<TypeAnnotation>> {
+        match self.peek() {
+            Some(Token::Colon) => {
+                self.next();
+                Ok(Some(self.try_parse_type()?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn try_parse_type(&mut self) -> PResult<TypeAnnotation> {
+        let depth = self.depth;
+        let ty = self.try_parse_type_kind();
+        self.depth = depth;
+        ty
+    }
+
+    fn try_parse_type_kind(&mut self) -> PResult<TypeAnnotation> {
+        // name | [type] | {type} | func(type,*) (: type)?
+        self.descend()?;
+        let start = self.index;
+
+        let kind = match self.peek() {
+            Some(Token::OpenBracket) => {
+                self.next();
+                let element = self.try_parse_type()?;
+                self.expect(Token::CloseBracket, "`]`")?;
+                TypeAnnotationKind::Array(Box::new(element))
+            }
+            Some(Token::OpenBrace) => {
+                self.next();
+                let element = self.try_parse_type()?;
+                self.expect(Token::CloseBrace, "`}`")?;
+                TypeAnnotationKind::Map(Box::new(element))
+            }
+            Some(Token::Word(word)) if word == "func" => {
+                self.next();
+                self.expect(Token::OpenParen, "`(`")?;
+
+                let mut parameters = Vec::new();
+
+                loop {
+                    match self.peek() {
+                        Some(Token::CloseParen) => {
+                            self.next();
+                            break;
+                        }
+                        _ => parameters.push(self.try_parse_type()?),
+                    }
+
+                    match self.peek() {
+                        Some(Token::Comma) => {
+                            self.next();
+                        }
+                        Some(Token::CloseParen) => {
+                            self.next();
+                            break;
+                        }
+                        _ => return Err(self.unexpected(&["`,`", "`)`"])),
+                    }
                 }
-            },
+
+                let result = self.try_parse_type_annotation()?.map(Box::new);
+                TypeAnnotationKind::Function(parameters, result)
+            }
+            Some(Token::Word(_)) => TypeAnnotationKind::Named(self.expect_ident()?),
+            _ => return Err(self.unexpected(&["type"])),
+        };
+
+        Ok(TypeAnnotation {
+            id: self.new_id(),
+            span: self.span_from(start),
+            kind,
+        })
+    }
+
+    fn peek_operator(&self) -> Option<Operator> {
+        match self.peek() {
+            Some(Token::Operator(op)) => Some(op.clone()),
+            Some(Token::Word(word)) => Operator::match_keyword(word),
+            _ => None,
         }
     }
 
     fn try_parse_valtype(&mut self) -> PResult<ValueType> {
-        let t = self.peek();
+        match self.peek() {
+            Some(Token::IntLiteral(val)) => {
+                let val = *val;
+                self.next();
+                Ok(ValueType::Number(val))
+            }
+            Some(Token::FloatLiteral(val)) => {
+                let val = *val;
+                self.next();
+                Ok(ValueType::Float(val))
+            }
+            Some(Token::StringLiteral(val)) => {
+                let val = val.clone();
+                self.next();
+                Ok(ValueType::String(val))
+            }
+            _ => Err(self.unexpected(&["literal"])),
+        }
+    }
+
+    fn try_parse_expression(&mut self) -> PResult<Expression> {
+        let depth = self.depth;
+        let expr = self.try_parse_assignment();
+        self.depth = depth;
+        expr
+    }
+
+    fn try_parse_assignment(&mut self) -> PResult<Expression> {
+        // lvalue (assign_op) expr | expr (op) expr | postfix
+        self.descend()?;
+        let start = self.index;
+        let lhs = self.try_parse_operation(0)?;
 
-        if t.is_some() {
-            let t = t.unwrap();
-            println!("{:?}", t);
-            if t.is_int_literal() {
-                let t = self.next();
-                println!("{:?}", t);
-                match t.unwrap() {
-                    Token::IntLiteral(val) => Ok(ValueType::Number(*val)),
-                    _ => Err(ParseError::Unexpected),
+        match self.peek_operator() {
+            Some(op) if op.is_assignment() => {
+                if !lhs.is_lvalue() {
+                    return Err(ParseError::InvalidAssignmentTarget { op, span: lhs.span });
                 }
-            } else {
-                Err(ParseError::Unexpected)
+
+                self.next();
+
+                // Assignment is right associative, `a = b = c` assigns `c` to both.
+                let rhs = self.try_parse_expression()?;
+
+                let kind = ExpressionKind::Assignment(Box::new(lhs), op, Box::new(rhs));
+                Ok(self.expression(start, kind))
             }
-        } else {
-            Err(ParseError::Unexpected)
+            _ => Ok(lhs),
         }
     }
 
-    fn try_parse_expression(&mut self) -> PResult<Expression> {
-        // expr (op) expr | (expr) | valtype | funccall
+    fn try_parse_operation(&mut self, min_precedence: u8) -> PResult<Expression> {
+        let start = self.index;
+        let mut lhs = self.try_parse_unary()?;
 
-        {
-            let valtype = self.try_parse_valtype();
-
-            println!("{:?}", valtype);
-
-            if valtype.is_ok() {
-                println!("Read valtype");
-                if self.peek_operator() {
-                    match self.next().unwrap() {
-                        Token::Operator(op) => {
-                            println!("Parsing operation");
-                            let owned_op = op.clone();
-                            let rhs = self.try_parse_expression();
-                            let lhs = Expression::Singleton(valtype.unwrap());
-
-                            if rhs.is_ok() {
-                                return Ok(Expression::Operation(
-                                    Box::new(lhs),
-                                    owned_op,
-                                    Box::new(rhs.unwrap()),
-                                ));
-                            } else {
-                                return Err(ParseError::Unexpected);
-                            }
+        while let Some(op) = self.peek_operator() {
+            let precedence = match infix_precedence(&op) {
+                Some(p) if p > min_precedence => p,
+                _ => break,
+            };
+
+            // Each operator wraps `lhs` one level deeper.
+            self.descend()?;
+            self.next();
+
+            let rhs = self.try_parse_operation(precedence)?;
+            let kind = ExpressionKind::Operation(Box::new(lhs), op, Box::new(rhs));
+            lhs = self.expression(start, kind);
+        }
+
+        Ok(lhs)
+    }
+
+    fn try_parse_unary(&mut self) -> PResult<Expression> {
+        // (- | !) unary | postfix
+        let start = self.index;
+
+        match self.peek_operator() {
+            Some(op @ Operator::Subtract(_)) | Some(op @ Operator::Not(_)) => {
+                self.descend()?;
+                self.next();
+                let operand = self.try_parse_unary()?;
+                Ok(self.expression(start, ExpressionKind::Unary(op, Box::new(operand))))
+            }
+            _ => self.try_parse_postfix(),
+        }
+    }
+
+    fn try_parse_postfix(&mut self) -> PResult<Expression> {
+        // primary ( (args) | .name | [expr] )*
+        let start = self.index;
+        let mut expr = self.try_parse_primary()?;
+
+        loop {
+            if let Some(Token::OpenParen) | Some(Token::Dot) | Some(Token::OpenBracket) = self.peek() {
+                // Each postfix wraps `expr` one level deeper.
+                self.descend()?;
+            }
+
+            let kind = match self.peek() {
+                Some(Token::OpenParen) => {
+                    self.next();
+                    let parameters = self.try_parse_arguments()?;
+
+                    ExpressionKind::Invocation(FunctionInvocation {
+                        func: Box::new(expr),
+                        parameters,
+                    })
+                }
+                Some(Token::Dot) => {
+                    self.next();
+                    let name = self.expect_ident()?;
+                    ExpressionKind::Member(Box::new(expr), name)
+                }
+                Some(Token::OpenBracket) => {
+                    self.next();
+                    let index = self.try_parse_expression()?;
+                    self.expect(Token::CloseBracket, "`]`")?;
+                    ExpressionKind::Index(Box::new(expr), Box::new(index))
+                }
+                _ => return Ok(expr),
+            };
+
+            expr = self.expression(start, kind);
+        }
+    }
+
+    /// Parses a comma separated argument list, the opening paren has already been consumed.
+    fn try_parse_arguments(&mut self) -> PResult<Vec<Expression>> {
+        self.try_parse_expression_list(Token::CloseParen, "`)`")
+    }
+
+    /// Parses comma separated expressions up to and including the closing token,
+    /// a trailing comma before the closing token is allowed.
+    fn try_parse_expression_list(
+        &mut self,
+        close: Token,
+        description: &'static str,
+    ) -> PResult<Vec<Expression>> {
+        let is_close =
+            |tok: &Token| std::mem::discriminant(tok) == std::mem::discriminant(&close);
+        let mut expressions = Vec::new();
+
+        loop {
+            match self.peek() {
+                Some(tok) if is_close(tok) => {
+                    self.next();
+                    return Ok(expressions);
+                }
+                _ => expressions.push(self.try_parse_expression()?),
+            }
+
+            match self.peek() {
+                Some(Token::Comma) => {
+                    self.next();
+                }
+                Some(tok) if is_close(tok) => {
+                    self.next();
+                    return Ok(expressions);
+                }
+                _ => return Err(self.unexpected(&["`,`", description])),
+            }
+        }
+    }
+
+    /// Parses the entries of an object literal, the opening brace has already been consumed.
+    fn try_parse_object(&mut self) -> PResult<Vec<ObjectProperty>> {
+        // { (key: expr | "key": expr | key),* }
+        let mut properties = Vec::new();
+
+        loop {
+            let start = self.index;
+
+            let (name, is_identifier) = match self.peek() {
+                Some(Token::CloseBrace) => {
+                    self.next();
+                    return Ok(properties);
+                }
+                Some(Token::Word(key)) => (key.clone(), true),
+                Some(Token::StringLiteral(key)) => (key.clone(), false),
+                _ => return Err(self.unexpected(&["property name", "`}`"])),
+            };
+
+            let key = Ident {
+                id: self.new_id(),
+                name,
+                span: self.peek_span(),
+            };
+            self.next();
+
+            let value = match self.peek() {
+                Some(Token::Colon) => {
+                    self.next();
+                    self.try_parse_expression()?
+                }
+                // Only identifiers can be used as shorthand properties.
+                _ if is_identifier => {
+                    let ident = Ident {
+                        id: self.new_id(),
+                        ..key.clone()
+                    };
+                    self.expression(start, ExpressionKind::Identifier(ident))
+                }
+                _ => return Err(self.unexpected(&["`:`"])),
+            };
+
+            properties.push(ObjectProperty { key, value });
+
+            match self.peek() {
+                Some(Token::Comma) => {
+                    self.next();
+                }
+                Some(Token::CloseBrace) => {
+                    self.next();
+                    return Ok(properties);
+                }
+                _ => return Err(self.unexpected(&["`,`", "`}`"])),
+            }
+        }
+    }
+
+    /// Whether the tokens ahead start an arrow function, `name =>`, `(params) =>`
+    /// or `(params): T =>`.
+    fn peek_arrow(&self) -> bool {
+        let is_arrow = |tok: Option<&Token>| matches!(tok, Some(Token::Operator(Operator::Arrow(_))));
+
+        match self.peek() {
+            Some(Token::Word(_)) => is_arrow(self.npeek(1)),
+            Some(Token::OpenParen) => {
+                // A parameter list holds only names, commas and type annotations, so
+                // the scan stops at the first token that can't be part of one.
+                let mut depth = 0;
+                let mut n = 1;
+
+                loop {
+                    match self.npeek(n) {
+                        Some(Token::CloseParen) if depth == 0 => {
+                            let after = self.npeek(n + 1);
+                            return is_arrow(after) || matches!(after, Some(Token::Colon));
+                        }
+                        Some(Token::OpenParen)
+                            if matches!(self.npeek(n - 1), Some(Token::Word(w)) if w == "func") =>
+                        {
+                            depth += 1
+                        }
+                        Some(Token::OpenBracket) | Some(Token::OpenBrace) => depth += 1,
+                        Some(Token::CloseParen) | Some(Token::CloseBracket) | Some(Token::CloseBrace)
+                            if depth > 0 =>
+                        {
+                            depth -= 1
                         }
-                        _ => return Err(ParseError::Unexpected),
+                        Some(Token::Word(_)) | Some(Token::Comma) | Some(Token::Colon) => {}
+                        _ => return false,
                     }
-                } else {
-                    return Ok(Expression::Singleton(valtype.unwrap()));
+
+                    n += 1;
                 }
             }
+            _ => false,
+        }
+    }
 
-            unimplemented!()
+    fn try_parse_lambda(&mut self) -> PResult<Lambda> {
+        // func (params) { body } | (params) => body | name => body
+        self.descend()?;
+
+        if self.eat_keyword("func") {
+            let parameters = self.try_parse_parameters()?;
+            let return_type = self.try_parse_type_annotation()?;
+            let body = self.try_parse_block()?;
+            return Ok(Lambda {
+                parameters,
+                return_type,
+                body,
+            });
         }
 
-        /*let func_call = self.try_parse_func_call();
-        if func_call.is_ok() {
+        let (parameters, return_type) = match self.peek() {
+            Some(Token::Word(_)) => {
+                let name = self.expect_ident()?;
+                (vec![Parameter { name, ty: None }], None)
+            }
+            _ => (self.try_parse_parameters()?, self.try_parse_type_annotation()?),
+        };
 
+        match self.peek() {
+            Some(Token::Operator(Operator::Arrow(_))) => {
+                self.next();
+            }
+            _ => return Err(self.unexpected(&["`=>`"])),
+        }
 
-        } else {
-        }*/
+        let body = match self.peek() {
+            Some(Token::OpenBrace) => self.try_parse_block()?,
+            _ => {
+                // `=> expr` is shorthand for `=> { return expr; }`.
+                let start = self.index;
+                let value = self.try_parse_expression()?;
+                let span = value.span;
+                let statement = Statement {
+                    id: self.new_id(),
+                    span,
+                    kind: StatementKind::Return(Some(value)),
+                };
+
+                Block {
+                    id: self.new_id(),
+                    span: self.span_from(start),
+                    statements: vec![statement],
+                }
+            }
+        };
+
+        Ok(Lambda {
+            parameters,
+            return_type,
+            body,
+        })
+    }
+
+    fn try_parse_primary(&mut self) -> PResult<Expression> {
+        // valtype | true | false | lambda | identifier | (expr) | { object } | [ array ]
+        let start = self.index;
+
+        if self.peek_arrow()
+            || (self.peek_keyword("func") && matches!(self.npeek(1), Some(Token::OpenParen)))
+        {
+            let lambda = self.try_parse_lambda()?;
+            return Ok(self.expression(start, ExpressionKind::Lambda(lambda)));
+        }
+
+        let kind = match self.peek() {
+            Some(t) if t.is_int_literal() || t.is_float_literal() || t.is_string_literal() => {
+                ExpressionKind::Singleton(self.try_parse_valtype()?)
+            }
+            Some(Token::OpenBrace) => {
+                self.next();
+                ExpressionKind::Object(self.try_parse_object()?)
+            }
+            Some(Token::OpenBracket) => {
+                self.next();
+                ExpressionKind::Array(self.try_parse_expression_list(Token::CloseBracket, "`]`")?)
+            }
+            Some(Token::Word(word)) if word == "true" || word == "false" => {
+                let val = word == "true";
+                self.next();
+                ExpressionKind::Singleton(ValueType::Boolean(val))
+            }
+            Some(Token::Word(_)) => ExpressionKind::Identifier(self.expect_ident()?),
+            Some(Token::OpenParen) => {
+                self.next();
+                let mut expr = self.try_parse_expression()?;
+                self.expect(Token::CloseParen, "`)`")?;
+
+                // Keep the parentheses in the span, so diagnostics cover what was written.
+                expr.span = self.span_from(start);
+                return Ok(expr);
+            }
+            _ => return Err(self.unexpected(&["expression"])),
+        };
+
+        Ok(self.expression(start, kind))
     }
 }
@@ -0,0 +1,410 @@
+//! The statement grammar: declarations, control flow and blocks, along with the
+//! error recovery that resumes parsing at the next statement.
+
+use super::*;
+
+/// Keywords that begin a declaration or statement, error recovery resumes parsing at these.
+const SYNC_KEYWORDS: &[&str] = &[
+    "import", "from", "let", "const", "func", "class", "prop", "if", "while", "return",
+    "break", "continue",
+];
+
+impl Parser {
+    pub(super) fn parse_statement_or_recover(&mut self) -> Option<Statement> {
+        let start = self.index;
+
+        match self.try_parse_statement() {
+            Ok(statement) => Some(statement),
+            Err(e) => {
+                self.errors.push(e);
+                self.synchronize(start);
+                None
+            }
+        }
+    }
+
+    /// Skips tokens until the parser is somewhere a new statement can begin: after a
+    /// `;`, before a `}` closing the enclosing block or before a declaration keyword.
+    /// Always consumes at least one token past `start` so parsing makes progress.
+    fn synchronize(&mut self, start: usize) {
+        let mut depth = 0usize;
+
+        while let Some(tok) = self.peek() {
+            match tok {
+                Token::OpenBrace => depth += 1,
+                // Leave the brace for the enclosing block, unless it is the
+                // token the failed statement started at.
+                Token::CloseBrace if depth == 0 && self.index > start => return,
+                Token::CloseBrace if depth == 0 => {}
+                Token::CloseBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.next();
+                        return;
+                    }
+                }
+                Token::Stop if depth == 0 => {
+                    self.next();
+                    return;
+                }
+                Token::Word(word)
+                    if depth == 0 && self.index > start && SYNC_KEYWORDS.contains(&word.as_str()) =>
+                {
+                    return;
+                }
+                _ => {}
+            }
+
+            self.next();
+        }
+    }
+
+    fn try_parse_statement(&mut self) -> PResult<Statement> {
+        let start = self.index;
+        let kind = self.try_parse_statement_kind()?;
+
+        Ok(Statement {
+            id: self.new_id(),
+            span: self.span_from(start),
+            kind,
+        })
+    }
+
+    fn try_parse_statement_kind(&mut self) -> PResult<StatementKind> {
+        let start = self.index;
+
+        match self.peek() {
+            // A brace at the start of a statement always opens a block, an
+            // object literal statement has to be wrapped in parentheses.
+            Some(Token::OpenBrace) => Ok(StatementKind::Block(self.try_parse_block()?)),
+            Some(Token::Word(word)) => match word.as_str() {
+                "import" => self.try_parse_import(),
+                "from" => self.try_parse_from_import(),
+                "let" => {
+                    self.next();
+                    Ok(StatementKind::Let(self.try_parse_variable(start, false)?))
+                }
+                "const" => {
+                    self.next();
+                    Ok(StatementKind::Let(self.try_parse_variable(start, true)?))
+                }
+                // `func (` starts an anonymous function rather than a declaration.
+                "func" if !matches!(self.npeek(1), Some(Token::OpenParen)) => {
+                    self.next();
+                    Ok(StatementKind::Function(self.try_parse_function(start)?))
+                }
+                "class" => self.try_parse_class(),
+                "if" => self.try_parse_if(),
+                "while" => {
+                    self.next();
+                    let condition = self.try_parse_expression()?;
+                    let body = self.try_parse_block()?;
+                    Ok(StatementKind::While(condition, body))
+                }
+                "return" => {
+                    self.next();
+                    let value = match self.peek() {
+                        Some(Token::Stop) | Some(Token::CloseBrace) | None => None,
+                        _ => Some(self.try_parse_expression()?),
+                    };
+                    self.expect_stop()?;
+                    Ok(StatementKind::Return(value))
+                }
+                "break" => {
+                    self.next();
+                    self.expect_stop()?;
+                    Ok(StatementKind::Break)
+                }
+                "continue" => {
+                    self.next();
+                    self.expect_stop()?;
+                    Ok(StatementKind::Continue)
+                }
+                _ => self.try_parse_expression_statement(),
+            },
+            _ => self.try_parse_expression_statement(),
+        }
+    }
+
+    fn try_parse_expression_statement(&mut self) -> PResult<StatementKind> {
+        let expr = self.try_parse_expression()?;
+
+        // The final statement of a file may leave off its semicolon.
+        match self.peek() {
+            Some(Token::Stop) => {
+                self.next();
+                Ok(StatementKind::Expression(expr))
+            }
+            None => Ok(StatementKind::Expression(expr)),
+            Some(_) => Err(self.unexpected(&["`;`", "operator"])),
+        }
+    }
+
+    /// Parses `{ statements }`, recovering from errors in the statements it contains.
+    pub(super) fn try_parse_block(&mut self) -> PResult<Block> {
+        let depth = self.depth;
+        let start = self.index;
+        let statements = self.try_parse_block_statements();
+        self.depth = depth;
+        let statements = statements?;
+
+        Ok(Block {
+            id: self.new_id(),
+            span: self.span_from(start),
+            statements,
+        })
+    }
+
+    fn try_parse_block_statements(&mut self) -> PResult<Vec<Statement>> {
+        self.descend()?;
+        self.expect(Token::OpenBrace, "`{`")?;
+
+        let mut statements = Vec::new();
+
+        loop {
+            match self.peek() {
+                Some(Token::CloseBrace) => {
+                    self.next();
+                    return Ok(statements);
+                }
+                None => return Err(self.unexpected(&["`}`"])),
+                _ => {
+                    if let Some(statement) = self.parse_statement_or_recover() {
+                        statements.push(statement);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Parses `{ a, b }`, the names of an import list.
+    fn try_parse_import_names(&mut self) -> PResult<Vec<Ident>> {
+        self.expect(Token::OpenBrace, "`{`")?;
+
+        let mut names = Vec::new();
+
+        loop {
+            match self.peek() {
+                Some(Token::CloseBrace) => {
+                    self.next();
+                    return Ok(names);
+                }
+                _ => names.push(self.expect_ident()?),
+            }
+
+            match self.peek() {
+                Some(Token::Comma) => {
+                    self.next();
+                }
+                Some(Token::CloseBrace) => {
+                    self.next();
+                    return Ok(names);
+                }
+                _ => return Err(self.unexpected(&["`,`", "`}`"])),
+            }
+        }
+    }
+
+    /// Imports may be terminated with a semicolon.
+    fn eat_stop(&mut self) {
+        if let Some(Token::Stop) = self.peek() {
+            self.next();
+        }
+    }
+
+    fn try_parse_import(&mut self) -> PResult<StatementKind> {
+        // import { names } from module
+        self.next();
+        let names = self.try_parse_import_names()?;
+
+        self.expect_keyword("from")?;
+
+        let module = self.expect_ident()?;
+        self.eat_stop();
+
+        Ok(StatementKind::Import(ImportDeclaration { names, module }))
+    }
+
+    fn try_parse_from_import(&mut self) -> PResult<StatementKind> {
+        // from module import { names }
+        self.next();
+        let module = self.expect_ident()?;
+
+        self.expect_keyword("import")?;
+
+        let names = self.try_parse_import_names()?;
+        self.eat_stop();
+
+        Ok(StatementKind::Import(ImportDeclaration { names, module }))
+    }
+
+    /// Parses `name = value;` after a `let`, `const` or `prop` keyword, which starts at
+    /// the token `start`. A `const` has to have a value.
+    fn try_parse_variable(&mut self, start: usize, constant: bool) -> PResult<VariableDeclaration> {
+        let name = self.expect_ident()?;
+        let ty = self.try_parse_type_annotation()?;
+
+        let value = match self.peek_operator() {
+            Some(Operator::Assign(_)) => {
+                self.next();
+                Some(self.try_parse_expression()?)
+            }
+            _ if constant => return Err(self.unexpected(&["`=`"])),
+            _ => None,
+        };
+
+        match self.peek() {
+            Some(Token::Stop) => {
+                self.next();
+                Ok(VariableDeclaration {
+                    id: self.new_id(),
+                    span: self.span_from(start),
+                    name,
+                    ty,
+                    value,
+                    constant,
+                })
+            }
+            _ if value.is_none() && ty.is_none() => Err(self.unexpected(&["`:`", "`=`", "`;`"])),
+            _ if value.is_none() => Err(self.unexpected(&["`=`", "`;`"])),
+            _ => Err(self.unexpected(&["`;`", "operator"])),
+        }
+    }
+
+    /// Parses `name(params) { body }` after a `func` keyword, which starts at the token `start`.
+    fn try_parse_function(&mut self, start: usize) -> PResult<FunctionDeclaration> {
+        let name = self.expect_ident()?;
+        let parameters = self.try_parse_parameters()?;
+        let return_type = self.try_parse_type_annotation()?;
+        let body = self.try_parse_block()?;
+
+        Ok(FunctionDeclaration {
+            id: self.new_id(),
+            span: self.span_from(start),
+            name,
+            parameters,
+            return_type,
+            body,
+        })
+    }
+
+    /// Parses a parenthesised, comma separated list of parameters, `(a, b: T)`.
+    pub(super) fn try_parse_parameters(&mut self) -> PResult<Vec<Parameter>> {
+        self.expect(Token::OpenParen, "`(`")?;
+
+        let mut parameters = Vec::new();
+
+        loop {
+            match self.peek() {
+                Some(Token::CloseParen) => {
+                    self.next();
+                    return Ok(parameters);
+                }
+                _ => {
+                    let name = self.expect_ident()?;
+                    let ty = self.try_parse_type_annotation()?;
+                    parameters.push(Parameter { name, ty });
+                }
+            }
+
+            match self.peek() {
+                Some(Token::Comma) => {
+                    self.next();
+                }
+                Some(Token::CloseParen) => {
+                    self.next();
+                    return Ok(parameters);
+                }
+                _ => return Err(self.unexpected(&["`,`", "`:`", "`)`"])),
+            }
+        }
+    }
+
+    fn try_parse_class(&mut self) -> PResult<StatementKind> {
+        // class Name { (prop var | let var | func function)* }
+        let start = self.index;
+        self.next();
+        let name = self.expect_ident()?;
+
+        self.expect(Token::OpenBrace, "`{`")?;
+
+        let mut members = Vec::new();
+
+        loop {
+            match self.peek() {
+                Some(Token::CloseBrace) => {
+                    self.next();
+                    break;
+                }
+                None => return Err(self.unexpected(&["`}`"])),
+                _ => {}
+            }
+
+            let start = self.index;
+
+            match self.try_parse_class_member() {
+                Ok(member) => members.push(member),
+                Err(e) => {
+                    self.errors.push(e);
+                    self.synchronize(start);
+                }
+            }
+        }
+
+        Ok(StatementKind::Class(ClassDeclaration {
+            id: self.new_id(),
+            span: self.span_from(start),
+            name,
+            members,
+        }))
+    }
+
+    fn try_parse_class_member(&mut self) -> PResult<ClassMember> {
+        let start = self.index;
+
+        if self.eat_keyword("prop") {
+            Ok(ClassMember::Property(self.try_parse_variable(start, false)?))
+        } else if self.eat_keyword("let") {
+            Ok(ClassMember::Static(self.try_parse_variable(start, false)?))
+        } else if self.eat_keyword("func") {
+            Ok(ClassMember::Method(self.try_parse_function(start)?))
+        } else {
+            Err(self.unexpected(&["`prop`", "`let`", "`func`", "`}`"]))
+        }
+    }
+
+    fn try_parse_if(&mut self) -> PResult<StatementKind> {
+        let depth = self.depth;
+        let statement = self.try_parse_if_chain();
+        self.depth = depth;
+        statement
+    }
+
+    fn try_parse_if_chain(&mut self) -> PResult<StatementKind> {
+        // if expr { body } (else (if ... | { body }))?
+        self.descend()?;
+        self.next();
+        let condition = self.try_parse_expression()?;
+        let body = self.try_parse_block()?;
+
+        let otherwise = if self.eat_keyword("else") {
+            let start = self.index;
+
+            let kind = if self.peek_keyword("if") {
+                self.try_parse_if_chain()?
+            } else {
+                StatementKind::Block(self.try_parse_block()?)
+            };
+
+            Some(Box::new(Statement {
+                id: self.new_id(),
+                span: self.span_from(start),
+                kind,
+            }))
+        } else {
+            None
+        };
+
+        Ok(StatementKind::If(condition, body, otherwise))
+    }
+}
@@ -0,0 +1,531 @@
+//! Name resolution.
+//!
+//! Binds every identifier to the declaration it refers to, through the lexical
+//! scopes of the module, classes, functions and blocks, and assigns each variable
+//! its wasm storage: module level variables become globals, everything declared
+//! inside a function becomes a local of that function.
+//!
+//! Functions, classes and imports can be used anywhere in the block that declares
+//! them, as can every declaration at module level. Variables declared inside a
+//! function are only in scope after their declaration.
+//!
+//! Class members are reached through `this` for properties and methods, and
+//! through the class name for statics. Statics are globals, properties are
+//! fields at a fixed offset of every instance. Members whose name starts with an
+//! underscore are private, only `this` (or the class name, inside the class) can
+//! access them.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::visit::{self, Visitor};
+use crate::ast::*;
+use crate::suggest;
+
+/// The modules the host provides, with the names each of them exports.
+pub const HOST_MODULES: &[(&str, &[&str])] = &[("io", &["print"])];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeclarationKind {
+    /// A module level variable, stored in the global at this index.
+    Global(u32),
+    /// A parameter or variable, stored in the local at this index of the enclosing function.
+    Local(u32),
+    Function,
+    Class,
+    Import { module: String },
+    /// `prop name`, a field at this byte offset of every instance.
+    Property(u32),
+    /// `let name` in a class, shared by every instance and stored in the global at this index.
+    Static(u32),
+    Method,
+}
+
+#[derive(Clone, Debug)]
+pub struct Declaration {
+    pub name: String,
+    pub span: Span,
+    pub kind: DeclarationKind,
+}
+
+/// The result of resolving a program.
+#[derive(Debug, Default)]
+pub struct Resolution {
+    /// Every declaration, keyed by the `NodeId` of the `Ident` declaring it. The
+    /// implicit `this` of a method is keyed by the method's `NodeId`.
+    pub declarations: HashMap<NodeId, Declaration>,
+    /// The declaration each identifier refers to, keyed by the `NodeId` of the
+    /// identifier. Covers variable uses and `this.member` accesses.
+    pub references: HashMap<NodeId, NodeId>,
+    /// The number of locals, parameters included, of every function, method and
+    /// lambda, keyed by its declaration or lambda expression.
+    pub local_counts: HashMap<NodeId, u32>,
+    pub global_count: u32,
+    /// The size in bytes of an instance of every class, keyed by the `Ident` naming the class.
+    pub instance_sizes: HashMap<NodeId, u32>,
+}
+
+#[derive(Clone, Debug)]
+pub enum ResolveErrorKind {
+    Undefined(String),
+    /// The name is already declared in the same scope, at `previous`.
+    Duplicate { name: String, previous: Span },
+    /// `this.member` where the class declares no such member.
+    UndefinedMember { class: String, member: String },
+    /// A private member accessed other than through `this`.
+    PrivateMember(String),
+    /// A static accessed through `this` rather than the class name.
+    StaticThroughInstance { class: String, member: String },
+    /// A property or method accessed through the class name rather than an instance.
+    InstanceThroughClass { class: String, member: String },
+    UnknownModule(String),
+    /// An import of a name the module doesn't export.
+    UnknownImport { module: String, name: String },
+}
+
+#[derive(Clone, Debug)]
+pub struct ResolveError {
+    pub kind: ResolveErrorKind,
+    pub span: Span,
+    /// A known name close to the one that failed to resolve.
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ResolveErrorKind::Undefined(name) => write!(f, "cannot find `{}` in this scope", name),
+            ResolveErrorKind::Duplicate { name, .. } => {
+                write!(f, "`{}` is declared more than once in this scope", name)
+            }
+            ResolveErrorKind::UndefinedMember { class, member } => {
+                write!(f, "class `{}` has no member `{}`", class, member)
+            }
+            ResolveErrorKind::PrivateMember(member) => write!(
+                f,
+                "`{}` is private and can only be accessed by its own class",
+                member
+            ),
+            ResolveErrorKind::StaticThroughInstance { class, member } => write!(
+                f,
+                "`{}` is a static of `{}`, access it through `{}.{}`",
+                member, class, class, member
+            ),
+            ResolveErrorKind::InstanceThroughClass { class, member } => write!(
+                f,
+                "`{}` belongs to instances of `{}` and cannot be accessed through the class",
+                member, class
+            ),
+            ResolveErrorKind::UnknownModule(module) => write!(f, "unknown module `{}`", module),
+            ResolveErrorKind::UnknownImport { module, name } => {
+                write!(f, "module `{}` has no export `{}`", module, name)
+            }
+        }
+    }
+}
+
+/// Resolves a program that parsed without errors, returning every error sorted by position.
+pub fn resolve(program: &Program) -> (Resolution, Vec<ResolveError>) {
+    let mut resolver = Resolver {
+        scopes: vec![HashMap::new()],
+        frames: Vec::new(),
+        classes: HashMap::new(),
+        enclosing: Vec::new(),
+        resolution: Resolution::default(),
+        errors: Vec::new(),
+    };
+
+    resolver.hoist(&program.statements, true);
+    resolver.visit_program(program);
+
+    resolver.errors.sort_by_key(|e| e.span.start);
+    (resolver.resolution, resolver.errors)
+}
+
+/// A function whose locals are being allocated.
+struct Frame {
+    id: NodeId,
+    next_local: u32,
+}
+
+/// The members a class declares.
+struct Class {
+    name: String,
+    members: HashMap<String, NodeId>,
+}
+
+struct Resolver {
+    scopes: Vec<HashMap<String, NodeId>>,
+    frames: Vec<Frame>,
+    /// Every class declared so far, keyed by the `Ident` naming it.
+    classes: HashMap<NodeId, Class>,
+    /// The classes whose bodies are being resolved, innermost last.
+    enclosing: Vec<NodeId>,
+    resolution: Resolution,
+    errors: Vec<ResolveError>,
+}
+
+impl Resolver {
+    fn error(&mut self, kind: ResolveErrorKind, span: Span, suggestion: Option<&str>) {
+        self.errors.push(ResolveError {
+            kind,
+            span,
+            suggestion: suggestion.map(str::to_string),
+        });
+    }
+
+    /// Records a declaration, reporting it if the scope already declares the name.
+    fn insert(
+        scope: &mut HashMap<String, NodeId>,
+        declaration: Declaration,
+        id: NodeId,
+        resolution: &mut Resolution,
+        errors: &mut Vec<ResolveError>,
+    ) {
+        if let Some(previous) = scope.get(&declaration.name) {
+            if let Some(previous) = resolution.declarations.get(previous) {
+                errors.push(ResolveError {
+                    kind: ResolveErrorKind::Duplicate {
+                        name: declaration.name.clone(),
+                        previous: previous.span,
+                    },
+                    span: declaration.span,
+                    suggestion: None,
+                });
+            }
+            return;
+        }
+
+        scope.insert(declaration.name.clone(), id);
+        resolution.declarations.insert(id, declaration);
+    }
+
+    fn declare(&mut self, name: &Ident, kind: DeclarationKind) {
+        let declaration = Declaration {
+            name: name.name.clone(),
+            span: name.span,
+            kind,
+        };
+
+        if let Some(scope) = self.scopes.last_mut() {
+            Self::insert(scope, declaration, name.id, &mut self.resolution, &mut self.errors);
+        }
+    }
+
+    /// Declares a variable in the innermost function, or as a global outside of any.
+    fn declare_variable(&mut self, name: &Ident) {
+        let kind = match self.frames.last_mut() {
+            Some(frame) => {
+                frame.next_local += 1;
+                DeclarationKind::Local(frame.next_local - 1)
+            }
+            None => {
+                self.resolution.global_count += 1;
+                DeclarationKind::Global(self.resolution.global_count - 1)
+            }
+        };
+
+        self.declare(name, kind);
+    }
+
+    /// Declares the names usable throughout a block before any of its statements,
+    /// `lets` too at module level.
+    fn hoist(&mut self, statements: &[Statement], lets: bool) {
+        for statement in statements.iter() {
+            match &statement.kind {
+                StatementKind::Function(function) => {
+                    self.declare(&function.name, DeclarationKind::Function)
+                }
+                StatementKind::Class(class) => {
+                    self.declare(&class.name, DeclarationKind::Class);
+                    self.declare_members(class);
+                }
+                StatementKind::Import(import) => {
+                    self.check_import(import);
+
+                    for name in import.names.iter() {
+                        let kind = DeclarationKind::Import {
+                            module: import.module.name.clone(),
+                        };
+                        self.declare(name, kind);
+                    }
+                }
+                StatementKind::Let(variable) if lets => self.declare_variable(&variable.name),
+                _ => {}
+            }
+        }
+    }
+
+    /// Declares the members of a class, assigning statics their globals and
+    /// properties their place in an instance.
+    fn declare_members(&mut self, class: &ClassDeclaration) {
+        let mut members = HashMap::new();
+        let mut instance_size = 0;
+
+        for member in class.members.iter() {
+            let (name, kind) = match member {
+                ClassMember::Property(variable) => {
+                    // Every value is an i32 or f32, four bytes either way.
+                    instance_size += 4;
+                    (&variable.name, DeclarationKind::Property(instance_size - 4))
+                }
+                ClassMember::Static(variable) => {
+                    self.resolution.global_count += 1;
+                    (&variable.name, DeclarationKind::Static(self.resolution.global_count - 1))
+                }
+                ClassMember::Method(method) => (&method.name, DeclarationKind::Method),
+            };
+
+            let declaration = Declaration {
+                name: name.name.clone(),
+                span: name.span,
+                kind,
+            };
+            Self::insert(&mut members, declaration, name.id, &mut self.resolution, &mut self.errors);
+        }
+
+        self.resolution.instance_sizes.insert(class.name.id, instance_size);
+        self.classes.insert(
+            class.name.id,
+            Class {
+                name: class.name.name.clone(),
+                members,
+            },
+        );
+    }
+
+    /// Resolves `object.name` where the object is `this` or a class name, and
+    /// enforces who may access which members.
+    fn member(&mut self, object: &Expression, name: &Ident) {
+        let target = match &object.kind {
+            ExpressionKind::Identifier(ident) if ident.name == "this" => {
+                self.enclosing.last().map(|class_id| (*class_id, true))
+            }
+            ExpressionKind::Identifier(ident) => self
+                .resolution
+                .references
+                .get(&ident.id)
+                .filter(|id| self.classes.contains_key(id))
+                .map(|class_id| (*class_id, false)),
+            _ => None,
+        };
+
+        let is_private = name.name.starts_with('_');
+
+        // Members of other values are only known at run time.
+        let (class_id, through_this, class) = match target {
+            Some((class_id, through_this)) => match self.classes.get(&class_id) {
+                Some(class) => (class_id, through_this, class),
+                None => return,
+            },
+            None => {
+                if is_private {
+                    self.error(ResolveErrorKind::PrivateMember(name.name.clone()), name.span, None);
+                }
+                return;
+            }
+        };
+
+        let member = match class.members.get(&name.name) {
+            Some(member) => *member,
+            None => {
+                let known = class.members.keys().map(String::as_str);
+                let suggestion = suggest::closest(&name.name, known).map(str::to_string);
+                let kind = ResolveErrorKind::UndefinedMember {
+                    class: class.name.clone(),
+                    member: name.name.clone(),
+                };
+                self.error(kind, name.span, suggestion.as_deref());
+                return;
+            }
+        };
+
+        self.resolution.references.insert(name.id, member);
+
+        let class_name = class.name.clone();
+        let kind = self.resolution.declarations.get(&member).map(|d| d.kind.clone());
+
+        let error = match (kind, through_this) {
+            (Some(DeclarationKind::Static(_)), true) => Some(ResolveErrorKind::StaticThroughInstance {
+                class: class_name,
+                member: name.name.clone(),
+            }),
+            (Some(DeclarationKind::Static(_)), false) => {
+                if is_private && !self.enclosing.contains(&class_id) {
+                    Some(ResolveErrorKind::PrivateMember(name.name.clone()))
+                } else {
+                    None
+                }
+            }
+            (_, false) => Some(ResolveErrorKind::InstanceThroughClass {
+                class: class_name,
+                member: name.name.clone(),
+            }),
+            (_, true) => None,
+        };
+
+        if let Some(kind) = error {
+            self.error(kind, name.span, None);
+        }
+    }
+
+    /// Reports imports of modules or names the host doesn't provide.
+    fn check_import(&mut self, import: &ImportDeclaration) {
+        let module = &import.module;
+        let exports = match HOST_MODULES.iter().find(|(name, _)| *name == module.name) {
+            Some((_, exports)) => exports,
+            None => {
+                let suggestion = suggest::closest(&module.name, HOST_MODULES.iter().map(|(name, _)| *name));
+                let kind = ResolveErrorKind::UnknownModule(module.name.clone());
+                self.error(kind, module.span, suggestion);
+                return;
+            }
+        };
+
+        for name in import.names.iter() {
+            if !exports.contains(&name.name.as_str()) {
+                let suggestion = suggest::closest(&name.name, exports.iter().copied());
+                let kind = ResolveErrorKind::UnknownImport {
+                    module: module.name.clone(),
+                    name: name.name.clone(),
+                };
+                self.error(kind, name.span, suggestion);
+            }
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<NodeId> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name)).copied()
+    }
+
+    fn reserve_local(&mut self) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.next_local += 1;
+        }
+    }
+
+    /// Resolves a function body in a new frame, its parameters take the first locals.
+    fn function(&mut self, id: NodeId, receiver: Receiver, parameters: &[Parameter], body: &Block) {
+        self.frames.push(Frame { id, next_local: 0 });
+        self.scopes.push(HashMap::new());
+
+        match receiver {
+            Receiver::This(method) => {
+                let declaration = Declaration {
+                    name: "this".to_string(),
+                    span: body.span,
+                    kind: DeclarationKind::Local(0),
+                };
+                if let Some(scope) = self.scopes.last_mut() {
+                    Self::insert(scope, declaration, method, &mut self.resolution, &mut self.errors);
+                }
+                self.reserve_local();
+            }
+            Receiver::Environment => self.reserve_local(),
+            Receiver::None => {}
+        }
+
+        for parameter in parameters.iter() {
+            self.declare_variable(&parameter.name);
+        }
+
+        self.visit_block(body);
+
+        self.scopes.pop();
+        if let Some(frame) = self.frames.pop() {
+            self.resolution.local_counts.insert(frame.id, frame.next_local);
+        }
+    }
+
+    fn resolve(&mut self, ident: &Ident) {
+        match self.lookup(&ident.name) {
+            Some(declaration) => {
+                self.resolution.references.insert(ident.id, declaration);
+            }
+            None => {
+                let visible = self.scopes.iter().flat_map(|scope| scope.keys()).map(String::as_str);
+                let suggestion = suggest::closest(&ident.name, visible).map(str::to_string);
+                let kind = ResolveErrorKind::Undefined(ident.name.clone());
+                self.error(kind, ident.span, suggestion.as_deref());
+            }
+        }
+    }
+}
+
+/// What a function receives in local 0, ahead of its parameters.
+enum Receiver {
+    None,
+    /// A method receives its instance, the `NodeId` is the method's.
+    This(NodeId),
+    /// A lambda receives its closure environment.
+    Environment,
+}
+
+impl Visitor for Resolver {
+    fn visit_block(&mut self, block: &Block) {
+        self.scopes.push(HashMap::new());
+        self.hoist(&block.statements, false);
+        visit::walk_block(self, block);
+        self.scopes.pop();
+    }
+
+    // Imports, like functions and classes, are declared when their block is entered.
+    fn visit_import(&mut self, _import: &ImportDeclaration) {}
+
+    fn visit_variable(&mut self, variable: &VariableDeclaration) {
+        if let Some(value) = &variable.value {
+            self.visit_expression(value);
+        }
+
+        // Module level variables are declared up front.
+        let hoisted = self.frames.is_empty() && self.scopes.len() == 1;
+        if !hoisted {
+            self.declare_variable(&variable.name);
+        }
+    }
+
+    fn visit_function(&mut self, function: &FunctionDeclaration) {
+        self.function(function.id, Receiver::None, &function.parameters, &function.body);
+    }
+
+    fn visit_class(&mut self, class: &ClassDeclaration) {
+        self.enclosing.push(class.name.id);
+
+        for member in class.members.iter() {
+            match member {
+                ClassMember::Property(variable) | ClassMember::Static(variable) => {
+                    if let Some(value) = &variable.value {
+                        self.visit_expression(value);
+                    }
+                }
+                ClassMember::Method(method) => {
+                    let receiver = Receiver::This(method.id);
+                    self.function(method.id, receiver, &method.parameters, &method.body);
+                }
+            }
+        }
+
+        self.enclosing.pop();
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        match &expression.kind {
+            ExpressionKind::Identifier(ident) => self.resolve(ident),
+            ExpressionKind::Member(object, name) => {
+                self.visit_expression(object);
+
+                self.member(object, name);
+            }
+            ExpressionKind::Object(properties) => {
+                for property in properties.iter() {
+                    self.visit_expression(&property.value);
+                }
+            }
+            ExpressionKind::Lambda(lambda) => {
+                let receiver = Receiver::Environment;
+                self.function(expression.id, receiver, &lambda.parameters, &lambda.body);
+            }
+            _ => visit::walk_expression(self, expression),
+        }
+    }
+}
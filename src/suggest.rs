@@ -0,0 +1,54 @@
+//! "Did you mean" suggestions for misspelled names.
+
+/// The candidate closest to `name`, if any is close enough to be a likely typo.
+/// A candidate may be at most a third of the name's length in edits away, at least
+/// one edit but never all of it, ties go to the alphabetically first candidate.
+pub fn closest<'a, I>(name: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let limit = (name.chars().count() / 3).max(1);
+
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        // The distance is at least the difference in length.
+        .filter(|candidate| candidate.chars().count().abs_diff(name.chars().count()) <= limit)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit && *distance < name.chars().count())
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// The Damerau-Levenshtein distance between two strings, counting insertions,
+/// deletions, substitutions and swaps of adjacent characters as one edit each.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // Three rows of the distance matrix, for the swap two rows back.
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current: Vec<usize> = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        current[0] = i;
+
+        for j in 1..=b.len() {
+            let substitution = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + substitution);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
@@ -0,0 +1,109 @@
+//! The types the checker assigns to values.
+
+use std::fmt;
+
+use crate::ast::{Ident, TypeAnnotation, TypeAnnotationKind};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    I32,
+    F32,
+    Bool,
+    String,
+    /// `[T]`
+    Array(Box<Type>),
+    /// `{T}`, string keys to values of `T`.
+    Map(Box<Type>),
+    Function(Vec<Type>, Box<Type>),
+    /// An instance of the named class.
+    Class(String),
+    /// The named class itself, calling it creates an instance.
+    Constructor(String),
+    /// The result of a function that doesn't return a value.
+    Void,
+    /// Nothing is known about the value, it is accepted wherever a type is expected.
+    Unknown,
+}
+
+impl Type {
+    /// Resolves an annotation, `class_exists` decides which other names are class types.
+    /// Fails with the first name that is neither a builtin type nor a class.
+    pub fn from_annotation<'a, F>(annotation: &'a TypeAnnotation, class_exists: &F) -> Result<Type, &'a Ident>
+    where
+        F: Fn(&str) -> bool,
+    {
+        Ok(match &annotation.kind {
+            TypeAnnotationKind::Named(name) => match name.name.as_str() {
+                "i32" => Type::I32,
+                "f32" => Type::F32,
+                "bool" => Type::Bool,
+                "string" => Type::String,
+                class if class_exists(class) => Type::Class(class.to_string()),
+                _ => return Err(name),
+            },
+            TypeAnnotationKind::Array(element) => {
+                Type::Array(Box::new(Type::from_annotation(element, class_exists)?))
+            }
+            TypeAnnotationKind::Map(element) => {
+                Type::Map(Box::new(Type::from_annotation(element, class_exists)?))
+            }
+            TypeAnnotationKind::Function(parameters, result) => {
+                let parameters = parameters
+                    .iter()
+                    .map(|parameter| Type::from_annotation(parameter, class_exists))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let result = match result {
+                    Some(result) => Type::from_annotation(result, class_exists)?,
+                    None => Type::Void,
+                };
+                Type::Function(parameters, Box::new(result))
+            }
+        })
+    }
+
+    /// Whether a value of type `other` can be used where `self` is expected.
+    /// `Unknown` is compatible with everything, at any depth.
+    pub fn accepts(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Unknown, _) | (_, Type::Unknown) => true,
+            (Type::Array(a), Type::Array(b)) | (Type::Map(a), Type::Map(b)) => a.accepts(b),
+            (Type::Function(a_params, a_result), Type::Function(b_params, b_result)) => {
+                a_params.len() == b_params.len()
+                    && a_params.iter().zip(b_params.iter()).all(|(a, b)| b.accepts(a))
+                    && a_result.accepts(b_result)
+            }
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::I32 => write!(f, "i32"),
+            Type::F32 => write!(f, "f32"),
+            Type::Bool => write!(f, "bool"),
+            Type::String => write!(f, "string"),
+            Type::Array(element) => write!(f, "[{}]", element),
+            Type::Map(element) => write!(f, "{{{}}}", element),
+            Type::Function(parameters, result) => {
+                write!(f, "func(")?;
+                for (i, parameter) in parameters.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", parameter)?;
+                }
+                write!(f, ")")?;
+                match result.as_ref() {
+                    Type::Void => Ok(()),
+                    result => write!(f, ": {}", result),
+                }
+            }
+            Type::Class(name) => write!(f, "{}", name),
+            Type::Constructor(name) => write!(f, "class {}", name),
+            Type::Void => write!(f, "void"),
+            Type::Unknown => write!(f, "unknown"),
+        }
+    }
+}
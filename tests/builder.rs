@@ -0,0 +1,176 @@
+use lsc::emit::builder::ModuleBuilder;
+use lsc::emit::instruction::Instruction;
+use lsc::emit::{decode, wat, CustomSection, EmitError, Placement};
+use lsc::ir::{Signature, ValType};
+
+#[test]
+fn imports_come_first_in_every_index_space() {
+    let mut builder = ModuleBuilder::new();
+    let imported_table = builder.import_table("env", "table", 1, None);
+    let imported_memory = builder.import_memory("env", "memory", 1, Some(2));
+    let imported_global = builder.import_global("env", "base", ValType::I32, false);
+
+    // The MVP allows one table and one memory, only the indices matter here.
+    let table = builder.table(0, Some(0));
+    let memory = builder.memory(1, None);
+    let global = builder.global(ValType::I32, true, Instruction::I32Const(7));
+    assert_eq!([imported_table.index(), table.index()], [0, 1]);
+    assert_eq!([imported_memory.index(), memory.index()], [0, 1]);
+    assert_eq!([imported_global.index(), global.index()], [0, 1]);
+
+    let signature = Signature {
+        parameters: Vec::new(),
+        result: Some(ValType::I32),
+    };
+    let function = builder.function(&signature);
+    builder.define(function, &[], vec![Instruction::GlobalGet(imported_global.index())]);
+    builder.export_function("base", function);
+    builder.export_table("table", imported_table);
+    builder.export_global("counter", global);
+
+    let text = builder.wat(wat::Style::Flat).unwrap();
+    for expected in [
+        "(import \"env\" \"table\" (table (;0;) 1 funcref))",
+        "(import \"env\" \"memory\" (memory (;0;) 1 2))",
+        "(import \"env\" \"base\" (global $env.base i32))",
+        "global.get $env.base",
+        "(export \"table\" (table 0))",
+        "(export \"counter\" (global 1))",
+    ] {
+        assert!(text.contains(expected), "no `{}` in\n{}", expected, text);
+    }
+}
+
+#[test]
+fn imports_after_definitions_are_rejected() {
+    let mut builder = ModuleBuilder::new();
+    builder.global(ValType::F32, false, Instruction::F32Const(1.0));
+    builder.import_global("env", "late", ValType::F32, false);
+    assert_eq!(builder.finish(), Err(EmitError::LateImport("env.late".to_string())));
+}
+
+#[test]
+fn imported_and_exported_globals_round_trip() {
+    let mut builder = ModuleBuilder::new();
+    let global = builder.import_global("env", "base", ValType::F32, true);
+    builder.export_global("base", global);
+
+    let bytes = builder.finish().unwrap();
+    assert_eq!(decode::decode(&bytes).unwrap().encode(), bytes);
+}
+
+fn void() -> Signature {
+    Signature {
+        parameters: Vec::new(),
+        result: None,
+    }
+}
+
+fn custom(name: &str, placement: Placement) -> CustomSection {
+    CustomSection {
+        name: name.to_string(),
+        content: vec![1, 2, 3],
+        placement,
+    }
+}
+
+/// The id of every section of an encoded module, in order.
+fn section_ids(bytes: &[u8]) -> Vec<u8> {
+    let mut ids = Vec::new();
+    let mut position = 8;
+    while position < bytes.len() {
+        ids.push(bytes[position]);
+        position += 1;
+
+        let (mut size, mut shift) = (0usize, 0);
+        loop {
+            let byte = bytes[position];
+            position += 1;
+            size |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        position += size;
+    }
+    ids
+}
+
+#[test]
+fn sections_are_encoded_in_the_order_the_spec_requires() {
+    let mut builder = ModuleBuilder::new();
+    builder.custom(custom("last", Placement::Last));
+    builder.custom(custom("after_data_count", Placement::After(12)));
+    builder.custom(custom("first", Placement::First));
+
+    let print = builder.import_function("io", "print", &void());
+    let main = builder.function(&void());
+    builder.define(main, &[], vec![Instruction::Call(print.index())]);
+    let table = builder.table(1, Some(1));
+    builder.elements(table, 0, &[main]);
+    builder.memory(1, None);
+    builder.data(8, b"data".to_vec());
+    builder.global(ValType::I32, true, Instruction::I32Const(0));
+    builder.export_function("main", main);
+    builder.start(main);
+
+    // The data count section, id 12, precedes the code, and custom sections
+    // follow the section they are placed after.
+    let bytes = builder.finish().unwrap();
+    assert_eq!(section_ids(&bytes), [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 12, 0, 10, 11, 0]);
+    assert_eq!(decode::decode(&bytes).unwrap().encode(), bytes);
+}
+
+#[test]
+fn modules_without_tables_or_data_have_no_such_sections() {
+    let mut builder = ModuleBuilder::new();
+    let main = builder.function(&void());
+    builder.define(main, &[], Vec::new());
+    builder.export_function("main", main);
+
+    let bytes = builder.finish().unwrap();
+    assert_eq!(section_ids(&bytes), [1, 3, 7, 10]);
+}
+
+#[test]
+fn exporting_a_name_twice_is_rejected() {
+    let mut builder = ModuleBuilder::new();
+    let memory = builder.memory(1, None);
+    builder.export_memory("memory", memory);
+    builder.export_memory("memory", memory);
+    assert_eq!(builder.finish(), Err(EmitError::DuplicateExport("memory".to_string())));
+}
+
+#[test]
+fn custom_sections_after_unknown_sections_are_rejected() {
+    let mut builder = ModuleBuilder::new();
+    builder.custom(custom("nowhere", Placement::After(13)));
+    assert_eq!(
+        builder.finish(),
+        Err(EmitError::UnknownPlacement {
+            name: "nowhere".to_string(),
+            after: 13,
+        })
+    );
+}
+
+#[test]
+fn functions_need_exactly_one_body() {
+    // Imports shift the index of the missing body.
+    let mut builder = ModuleBuilder::new();
+    builder.import_function("io", "print", &void());
+    builder.function(&void());
+    assert_eq!(builder.finish(), Err(EmitError::Undefined(1)));
+
+    let mut builder = ModuleBuilder::new();
+    let main = builder.function(&void());
+    builder.define(main, &[], Vec::new());
+    builder.define(main, &[], Vec::new());
+    assert_eq!(builder.finish(), Err(EmitError::Redefined(0)));
+
+    let mut builder = ModuleBuilder::new();
+    let print = builder.import_function("io", "print", &void());
+    builder.define(print, &[], Vec::new());
+    assert_eq!(builder.finish(), Err(EmitError::Redefined(0)));
+}
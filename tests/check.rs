@@ -0,0 +1,25 @@
+use lsc::check::{self, TypeErrorKind};
+use lsc::lex::TokenStream;
+use lsc::parse::Parser;
+use lsc::types::Type;
+
+#[test]
+fn members_of_primitives_are_type_errors() {
+    let src = "let a: i32 = 12;\nlet s = a.b + (a);\nlet t: bool = true;\nt.c;\n";
+    let (program, _) = Parser::new(TokenStream::new(src)).parse();
+    let (_, errors) = check::check(&program);
+
+    // Reported at the member's name.
+    let errors: Vec<_> = errors.iter().map(|e| (e.kind.clone(), e.span.line_col(src))).collect();
+    assert!(
+        matches!(
+            &errors[..],
+            [
+                (TypeErrorKind::NoMembers(Type::I32), (2, 11)),
+                (TypeErrorKind::NoMembers(Type::Bool), (4, 3)),
+            ]
+        ),
+        "{:?}",
+        errors
+    );
+}
@@ -0,0 +1,40 @@
+//! Compiles sources the way the driver does, for tests of the later passes.
+
+use lsc::ir::{lower, pass::PassManager, Module};
+use lsc::lex::TokenStream;
+use lsc::parse::Parser;
+use lsc::{capture, check, flow, fold, resolve};
+
+/// Lowers a source file and runs the standard passes over it, or returns the
+/// messages of the errors that stopped it, in the order the driver reports them.
+pub fn compile(src: &str) -> Result<Module, Vec<String>> {
+    let (program, errors) = Parser::new(TokenStream::new(src)).parse();
+    if !errors.is_empty() {
+        return Err(errors.iter().map(ToString::to_string).collect());
+    }
+
+    let (resolution, resolve_errors) = resolve::resolve(&program);
+    let (types, type_errors) = check::check(&program);
+    let mut errors: Vec<String> = resolve_errors.iter().map(ToString::to_string).collect();
+    errors.extend(type_errors.iter().map(ToString::to_string));
+    if !resolve_errors.is_empty() {
+        return Err(errors);
+    }
+
+    let (program, fold_errors) = fold::fold(program, &resolution);
+    let (flow_errors, _) = flow::analyze(&program, &resolution);
+    errors.extend(fold_errors.iter().map(ToString::to_string));
+    errors.extend(flow_errors.iter().map(ToString::to_string));
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let captures = capture::analyze(&program);
+    let (mut module, lower_errors) = lower::lower(&program, &resolution, &types, &captures);
+    if !lower_errors.is_empty() {
+        return Err(lower_errors.iter().map(ToString::to_string).collect());
+    }
+
+    PassManager::standard().run(&mut module);
+    Ok(module)
+}
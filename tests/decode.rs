@@ -0,0 +1,133 @@
+mod common;
+
+use lsc::emit::builder::ModuleBuilder;
+use lsc::emit::instruction::Instruction;
+use lsc::emit::{decode, Entry, ModuleEmitter};
+use lsc::ir::{Signature, ValType};
+
+/// The examples that compile, and a program with classes, a closure and globals.
+const PROGRAMS: &[&str] = &[
+    "examples/hello_world.ls",
+    "examples/simple_algebra.ls",
+    "examples/simple_arithmetic.ls",
+    "fuzz/corpus/parse/lowering",
+];
+
+fn emit(src: &str, entry: Entry) -> Vec<u8> {
+    let module = common::compile(src).unwrap_or_else(|errors| panic!("{:?}", errors));
+    let emitter = ModuleEmitter {
+        entry,
+        ..ModuleEmitter::default()
+    };
+    emitter.emit(&module).unwrap()
+}
+
+/// The name and `count` of every section `objdump` lists.
+fn sections(bytes: &[u8]) -> Vec<String> {
+    let objdump = decode::decode(bytes).unwrap().objdump();
+    objdump
+        .lines()
+        .skip_while(|line| !line.starts_with("Sections:"))
+        .skip(2)
+        .take_while(|line| !line.is_empty())
+        .map(|line| {
+            let name = line.split_whitespace().next().unwrap_or("");
+            let count = line.rsplit("count: ").next().filter(|_| line.contains("count: "));
+            match count {
+                Some(count) => format!("{} {}", name, count),
+                None => name.to_string(),
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn emitted_programs_round_trip() {
+    for path in PROGRAMS {
+        for entry in [Entry::default(), Entry::Export("main".to_string()), Entry::Start] {
+            let src = std::fs::read_to_string(path).unwrap();
+            let bytes = emit(&src, entry.clone());
+            let decoded = decode::decode(&bytes).unwrap_or_else(|e| panic!("{} with {:?}: {}", path, entry, e));
+            assert_eq!(decoded.encode(), bytes, "{} with {:?}", path, entry);
+        }
+    }
+}
+
+#[test]
+fn element_data_and_start_sections_round_trip() {
+    let src = r#"
+        from io import { print };
+        let greeting = "hello";
+        let base = 2;
+        let add = (x: i32) => x + base;
+        print(add(1));
+    "#;
+    let bytes = emit(src, Entry::Start);
+    let sections = sections(&bytes);
+
+    for expected in ["Start", "Elem 1", "DataCount 1", "Data 1"] {
+        assert!(sections.iter().any(|s| s == expected), "no `{}` in {:?}", expected, sections);
+    }
+    assert_eq!(decode::decode(&bytes).unwrap().encode(), bytes);
+}
+
+#[test]
+fn element_segment_naming_table_zero() {
+    let header = [0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+    let types = [0x01, 0x04, 0x01, 0x60, 0x00, 0x00];
+    let functions = [0x03, 0x02, 0x01, 0x00];
+    let tables = [0x04, 0x04, 0x01, 0x70, 0x00, 0x01];
+    let code = [0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b];
+
+    // Flag 2 names table 0 and the kind of its elements, flag 0 leaves both implied.
+    let explicit = [0x09, 0x09, 0x01, 0x02, 0x00, 0x41, 0x00, 0x0b, 0x00, 0x01, 0x00];
+    let implicit = [0x09, 0x07, 0x01, 0x00, 0x41, 0x00, 0x0b, 0x01, 0x00];
+
+    let module = |elements: &[u8]| -> Vec<u8> {
+        [&header[..], &types, &functions, &tables, elements, &code].concat()
+    };
+
+    let decoded = decode::decode(&module(&explicit)).unwrap();
+    assert_eq!(decoded.encode(), module(&implicit));
+}
+
+#[test]
+fn multi_byte_immediates_round_trip() {
+    let signature = Signature {
+        parameters: Vec::new(),
+        result: None,
+    };
+
+    let mut builder = ModuleBuilder::new();
+    let functions: Vec<_> = (0..130).map(|_| builder.function(&signature)).collect();
+    let globals: Vec<_> = (0..129).map(|_| builder.global(ValType::I32, true, Instruction::I32Const(-1))).collect();
+    for function in functions[1..].iter() {
+        builder.define(*function, &[], Vec::new());
+    }
+    builder.define(
+        functions[0],
+        &[ValType::I32; 200],
+        vec![
+            Instruction::I32Const(64),
+            Instruction::LocalSet(199),
+            Instruction::I32Const(-65),
+            Instruction::GlobalSet(globals[128].index()),
+            Instruction::Call(functions[129].index()),
+        ],
+    );
+
+    let bytes = builder.finish().unwrap();
+    let decoded = decode::decode(&bytes).unwrap();
+    assert_eq!(decoded.encode(), bytes);
+
+    let objdump = decoded.objdump();
+    for expected in [
+        "41 c0 00                   | i32.const 64",
+        "21 c7 01                   | local.set 199",
+        "41 bf 7f                   | i32.const -65",
+        "24 80 01                   | global.set 128",
+        "10 81 01                   | call 129",
+    ] {
+        assert!(objdump.contains(expected), "no `{}` in\n{}", expected, objdump);
+    }
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use lsc::emit::{decode, wat, EmitError, Entry, ModuleEmitter};
+
+#[test]
+fn functions_named_like_the_emitters_exports_are_reported() {
+    let module = common::compile("func main(): i32 { return 1; } main();").unwrap();
+
+    let emitter = ModuleEmitter {
+        entry: Entry::Export("main".to_string()),
+        ..ModuleEmitter::default()
+    };
+    assert_eq!(emitter.emit(&module), Err(EmitError::ReservedExport("main".to_string())));
+    assert!(ModuleEmitter::default().emit(&module).is_ok());
+
+    let module = common::compile("func memory(): i32 { return 1; }").unwrap();
+    assert_eq!(
+        ModuleEmitter::default().emit(&module),
+        Err(EmitError::ReservedExport("memory".to_string()))
+    );
+}
+
+#[test]
+fn the_heap_never_starts_at_the_null_address() {
+    let module = common::compile("class P { prop x = 1; } let p = P();").unwrap();
+    let text = ModuleEmitter::default().wat(&module, wat::Style::Flat).unwrap();
+    assert!(text.contains("(global $heap_pointer (mut i32) (i32.const 8))"), "{}", text);
+}
+
+#[test]
+fn strings_are_placed_past_the_null_address_and_the_heap_after_them() {
+    let module = common::compile(r#"let s = "hi"; let t = "there";"#).unwrap();
+    let addresses: Vec<_> = module.strings.segments.iter().map(|s| s.address).collect();
+    assert_eq!(addresses, [8, 16]);
+
+    let text = ModuleEmitter::default().wat(&module, wat::Style::Flat).unwrap();
+    for expected in [
+        "(global $s (mut i32) (i32.const 8))",
+        r#"(data (;0;) (i32.const 8) "\02\00\00\00hi")"#,
+        "(global $heap_pointer (mut i32) (i32.const 28))",
+    ] {
+        assert!(text.contains(expected), "no `{}` in\n{}", expected, text);
+    }
+}
+
+#[test]
+fn imports_take_the_first_function_indices() {
+    let src = "from io import { print }; func twice(x: i32): i32 { return x * 2; } print(twice(4));";
+    let module = common::compile(src).unwrap();
+
+    let text = ModuleEmitter::default().wat(&module, wat::Style::Flat).unwrap();
+    assert!(text.contains(r#"(import "io" "print" (func $io.print (type 0) (param i32)))"#), "{}", text);
+
+    let bytes = ModuleEmitter::default().emit(&module).unwrap();
+    let objdump = decode::decode(&bytes).unwrap().objdump();
+    for expected in ["func[1] <twice>", "func[2] <_start>", "call 1 <twice>", "call 0 <io.print>"] {
+        assert!(objdump.contains(expected), "no `{}` in\n{}", expected, objdump);
+    }
+}
+
+#[test]
+fn only_modules_with_closures_have_a_table() {
+    let module = common::compile("let x = 1 + 2;").unwrap();
+    let text = ModuleEmitter::default().wat(&module, wat::Style::Flat).unwrap();
+    assert!(!text.contains("(table"), "{}", text);
+
+    let module = common::compile("let f = (x: i32) => x + 1; let y = f(2);").unwrap();
+    let text = ModuleEmitter::default().wat(&module, wat::Style::Flat).unwrap();
+    assert!(text.contains("(table (;0;) 1 1 funcref)"), "{}", text);
+}
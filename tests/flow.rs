@@ -0,0 +1,99 @@
+use lsc::lex::TokenStream;
+use lsc::parse::Parser;
+use lsc::{flow, fold, resolve};
+
+/// The errors and warnings of a program that parses and resolves, each as
+/// `line:column: message`, after folding like the driver does.
+fn analyze(src: &str) -> (Vec<String>, Vec<String>) {
+    let (program, errors) = Parser::new(TokenStream::new(src)).parse();
+    assert!(errors.is_empty(), "{:?}", errors);
+    let (resolution, errors) = resolve::resolve(&program);
+    assert!(errors.is_empty(), "{:?}", errors);
+    let (program, _) = fold::fold(program, &resolution);
+
+    let (errors, warnings) = flow::analyze(&program, &resolution);
+    let at = |span: lsc::ast::Span, message: String| {
+        let (line, column) = span.line_col(src);
+        format!("{}:{}: {}", line, column, message)
+    };
+    (
+        errors.iter().map(|e| at(e.span, e.to_string())).collect(),
+        warnings.iter().map(|w| at(w.span, w.to_string())).collect(),
+    )
+}
+
+fn errors(src: &str) -> Vec<String> {
+    analyze(src).0
+}
+
+#[test]
+fn functions_returning_a_value_must_return_on_every_path() {
+    assert_eq!(
+        errors("func f(x: i32): i32 {\n  if x > 0 { return 1; }\n}"),
+        ["1:6: not all paths through `f` return a value"]
+    );
+    assert_eq!(
+        errors("let g = (x: i32): i32 => {\n  if x > 0 { return 1; }\n};"),
+        ["1:9: not all paths through this function return a value"]
+    );
+
+    // Both branches return, and a loop that never exits never reaches the end.
+    let src = "
+        func f(x: i32): i32 { if x > 0 { return 1; } else { return 2; } }
+        func g(): i32 { while true {} }
+        func h() { if true { return; } }
+    ";
+    assert!(errors(src).is_empty(), "{:?}", errors(src));
+}
+
+#[test]
+fn variables_must_be_assigned_on_every_path_before_they_are_read() {
+    assert_eq!(
+        errors("func f(b: bool): i32 {\n  let x;\n  if b { x = 1; }\n  return x;\n}"),
+        ["4:10: `x` is used before it is definitely assigned"]
+    );
+
+    let src = "func f(b: bool): i32 { let x; if b { x = 1; } else { x = 2; } let y; y = x; return y; }";
+    assert!(errors(src).is_empty(), "{:?}", errors(src));
+}
+
+#[test]
+fn conditional_and_deferred_assignments_are_not_definite() {
+    // The right hand side of `&&` and `||` may not run.
+    assert_eq!(
+        errors("func f(b: bool): i32 {\n  let x;\n  if b && (x = 1) > 0 { return x; }\n  return 0;\n}"),
+        ["3:32: `x` is used before it is definitely assigned"]
+    );
+    assert_eq!(
+        errors("func f(b: bool): i32 {\n  let x;\n  if b || (x = 1) > 0 { return 0; }\n  return x;\n}"),
+        ["4:10: `x` is used before it is definitely assigned"]
+    );
+
+    // The left hand side always runs.
+    let src = "func f(b: bool): i32 { let x; if (x = 1) > 0 && b { return x; } return x; }";
+    assert!(errors(src).is_empty(), "{:?}", errors(src));
+
+    // A lambda may run any time, or never.
+    assert_eq!(
+        errors("func f(): i32 {\n  let x;\n  let set = () => { x = 1; };\n  set();\n  return x;\n}"),
+        ["5:10: `x` is used before it is definitely assigned"]
+    );
+}
+
+#[test]
+fn statements_after_a_jump_are_unreachable() {
+    let src = "
+func f(): i32 {
+  return 1;
+  let x = 2;
+}
+while true {
+  break;
+  x = 3;
+}
+let x = 1;
+";
+    let (errors, warnings) = analyze(src);
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(warnings, ["4:3: unreachable statement", "8:3: unreachable statement"]);
+}
@@ -0,0 +1,251 @@
+mod common;
+
+use lsc::emit::{wat, ModuleEmitter};
+use lsc::ir::{GlobalId, Instruction, Module, Operand, ValType};
+
+fn function<'m>(module: &'m Module, name: &str) -> &'m lsc::ir::Function {
+    module.functions.iter().find(|f| f.name == name).unwrap()
+}
+
+#[test]
+fn logical_operators_only_evaluate_what_decides_the_result() {
+    let module = common::compile("func f(x: i32, b: bool): bool { return !b && -x < 0 || b; }").unwrap();
+    let body = &function(&module, "f").body;
+
+    // `!b` decides whether `-x < 0` runs, their result whether `b` is read again.
+    let ifs: Vec<_> = body
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::If { then, otherwise, .. } => Some((then, otherwise)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(ifs.len(), 2);
+    assert!(matches!(ifs[0].0[0], Instruction::Unary { .. }));
+    assert!(matches!(ifs[0].1[..], [Instruction::Copy { .. }]));
+    assert!(matches!(ifs[1].0[..], [Instruction::Copy { .. }]));
+
+    let text = ModuleEmitter::default().wat(&module, wat::Style::Flat).unwrap();
+    for expected in ["i32.eqz", "i32.sub", "i32.lt_s", "if", "else"] {
+        assert!(text.contains(expected), "no `{}` in\n{}", expected, text);
+    }
+}
+
+#[test]
+fn lambdas_cannot_assign_their_captures() {
+    let src = "func mk(): i32 { let x = 0; let inc = () => { x += 1; return x; }; return inc(); }";
+    let errors = common::compile(src).unwrap_err();
+    assert_eq!(errors, ["cannot assign to `x`, lambdas capture variables by value"]);
+
+    // Their own variables and parameters are theirs to assign.
+    let src = "func mk(): i32 { let x = 0; let inc = (y: i32) => { let z = x; z += y; y = z; return y; }; return inc(1); }";
+    assert!(common::compile(src).is_ok());
+}
+
+#[test]
+fn closures_are_called_through_the_table() {
+    let src = "func adder(base: i32) { return (x: i32) => x + base; } let f = adder(2); let r = f(3);";
+    let module = common::compile(src).unwrap();
+
+    // The environment holds the table index of the body and the captured `base`.
+    let lambda = function(&module, "lambda0");
+    assert_eq!(module.table.len(), 1);
+    assert_eq!(lambda.signature.parameters.len(), 2);
+    assert!(function(&module, "adder").body.iter().any(|instruction| matches!(
+        instruction,
+        Instruction::Alloc { size: 8, .. }
+    )));
+    assert!(lambda.body.iter().any(|instruction| matches!(instruction, Instruction::Load { offset: 4, .. })));
+
+    let text = ModuleEmitter::default().wat(&module, wat::Style::Flat).unwrap();
+    for expected in ["call_indirect (type 1)", "(table (;0;) 1 1 funcref)", "(elem (;0;) (i32.const 0) $lambda0)"] {
+        assert!(text.contains(expected), "no `{}` in\n{}", expected, text);
+    }
+}
+
+#[test]
+fn signatures_and_locals_take_the_checked_types() {
+    let src = "func scale(x: f32, n: i32): f32 { let k = 2.5; let m: i32 = n; return x * k; } func noop() { }";
+    let module = common::compile(src).unwrap();
+
+    let scale = function(&module, "scale");
+    assert_eq!(scale.signature.parameters, [ValType::F32, ValType::I32]);
+    assert_eq!(scale.signature.result, Some(ValType::F32));
+    assert_eq!(scale.locals[2..4], [ValType::F32, ValType::I32]);
+    assert_eq!(function(&module, "noop").signature.result, None);
+
+    // A value of unknown type is an i32, it can't stand in for an f32.
+    let errors = common::compile("func scale(x: f32): f32 { return x; } func twice(y) { return scale(y); }").unwrap_err();
+    assert_eq!(
+        errors,
+        ["expected an `f32`, found an `i32`, values of unknown type are `i32`s and need an annotation"]
+    );
+}
+
+#[test]
+fn properties_live_in_the_instance_and_statics_in_globals() {
+    let src = "class Counter {
+        prop count = 0;
+        prop scale: f32 = 1.5;
+        let created = 0;
+        func add(step: i32) { this.count += step; Counter.created = Counter.created + 1; }
+    }
+    let c = Counter();
+    c.add(2);";
+    let module = common::compile(src).unwrap();
+
+    let constructor = &function(&module, "Counter.new").body;
+    assert!(matches!(constructor[0], Instruction::Alloc { size: 8, .. }));
+    assert!(matches!(constructor[1], Instruction::Store { ty: ValType::I32, offset: 0, .. }));
+    assert!(matches!(constructor[2], Instruction::Store { ty: ValType::F32, offset: 4, .. }));
+
+    let add = &function(&module, "Counter.add").body;
+    assert!(matches!(add[0], Instruction::Load { offset: 0, .. }));
+    assert!(matches!(add[2], Instruction::Store { offset: 0, .. }));
+    assert!(add.iter().any(|instruction| matches!(instruction, Instruction::GlobalSet { .. })));
+    assert_eq!(module.globals[0].name, "created");
+}
+
+#[test]
+fn module_level_expressions_keep_their_folded_value() {
+    let src = std::fs::read_to_string("examples/simple_arithmetic.ls").unwrap();
+    let module = common::compile(&src).unwrap();
+
+    let init = &module.functions[module.init.unwrap().0 as usize];
+    assert_eq!(init.body, [Instruction::Drop(Operand::I32(155))]);
+}
+
+#[test]
+fn the_ir_dump_lists_imports_globals_and_functions() {
+    let src = "
+        from io import { print };
+        let total = 0;
+        func add(x: i32): i32 { return x + 1; }
+        while total < 3 { total = add(total); }
+        print(total);
+        total;
+    ";
+    let module = common::compile(src).unwrap();
+
+    let expected = "\
+import $io.print (i32)
+global mut @0 $total: i32 = 0
+
+func $add(%0: i32) -> i32 export {
+  local %1: i32
+  %1 = i32.add %0, 1
+  return %1
+}
+
+func $<init>() init {
+  local %0: i32
+  local %1: i32
+  local %2: i32
+  local %3: i32
+  local %4: i32
+  local %5: i32
+  local %6: i32
+  loop {
+    %0 = global.get @0
+    %1 = i32.lt %0, 3
+    %2 = i32.eqz %1
+    if %2 {
+      break
+    }
+    %3 = global.get @0
+    %4 = call $add(%3)
+    global.set @0, %4
+  }
+  %5 = global.get @0
+  call $io.print(%5)
+  %6 = global.get @0
+  drop %6
+}
+";
+    assert_eq!(module.to_string(), expected);
+}
+
+#[test]
+fn folded_globals_are_initialized_in_place() {
+    let module = common::compile("let a = 12; const b = 2.5; class K { let s = 3; } let c = a + 1;").unwrap();
+
+    let globals: Vec<_> = module.globals.iter().map(|g| (g.name.as_str(), g.mutable, g.init)).collect();
+    assert_eq!(
+        globals,
+        [
+            ("a", true, Operand::I32(12)),
+            ("b", false, Operand::F32(2.5)),
+            ("s", true, Operand::I32(0)),
+            ("c", true, Operand::I32(0)),
+        ]
+    );
+
+    // Statics are set whenever their class declaration runs, `c` is computed.
+    let init = &module.functions[module.init.unwrap().0 as usize];
+    assert_eq!(global_sets(init), 2);
+}
+
+#[test]
+fn variables_declared_in_module_level_loops_are_set_every_iteration() {
+    let src = "from io import { print }; let i = 0; while i < 3 { let x = 5; x = x + 1; print(x); i = i + 1; }";
+    let module = common::compile(src).unwrap();
+
+    let x = module.globals.iter().find(|g| g.name == "x").unwrap();
+    assert_eq!(x.init, Operand::I32(0));
+
+    let init = &module.functions[module.init.unwrap().0 as usize];
+    let body = match &init.body[..] {
+        [Instruction::Loop(body)] => body,
+        body => panic!("expected a single loop, found {:?}", body),
+    };
+    assert!(body.contains(&Instruction::GlobalSet {
+        global: GlobalId(x_index(&module)),
+        value: Operand::I32(5),
+    }));
+}
+
+#[test]
+fn variables_assigned_before_their_declaration_are_set_where_declared() {
+    let module = common::compile("x = 3; let x = 5; print(x); from io import { print };").unwrap();
+
+    let index = x_index(&module);
+    assert_eq!(module.globals[index as usize].init, Operand::I32(0));
+
+    let init = &module.functions[module.init.unwrap().0 as usize];
+    let sets: Vec<_> = init
+        .body
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::GlobalSet { global, value } if global.0 == index => Some(*value),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(sets, [Operand::I32(3), Operand::I32(5)]);
+}
+
+#[test]
+fn calls_must_match_the_signature_an_import_got_from_its_first_call() {
+    let module = common::compile("from io import { print }; print(1); print(2);").unwrap();
+    assert_eq!(module.imports.len(), 1);
+    assert_eq!(module.imports[0].signature.to_string(), "(i32)");
+
+    let errors = common::compile("from io import { print }; print(1); print(1, 2);").unwrap_err();
+    assert_eq!(
+        errors,
+        ["`print` was first called as `(i32)`, this call needs `(i32, i32)`, an import keeps the signature of its first call"]
+    );
+
+    let errors = common::compile(r#"from io import { print }; print("a"); print();"#).unwrap_err();
+    assert_eq!(
+        errors,
+        ["`print` was first called as `(i32)`, this call needs `()`, an import keeps the signature of its first call"]
+    );
+}
+
+fn global_sets(function: &lsc::ir::Function) -> usize {
+    function.body.iter().filter(|i| matches!(i, Instruction::GlobalSet { .. })).count()
+}
+
+fn x_index(module: &Module) -> u32 {
+    module.globals.iter().position(|g| g.name == "x").unwrap() as u32
+}
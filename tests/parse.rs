@@ -0,0 +1,189 @@
+use lsc::ast::{ClassMember, Expression, ExpressionKind, Program, Statement, StatementKind};
+use lsc::lex::TokenStream;
+use lsc::parse::{ParseError, Parser};
+
+fn parse(src: &str) -> (Program, Vec<ParseError>) {
+    Parser::new(TokenStream::new(src)).parse()
+}
+
+/// A program that must parse without errors.
+fn parsed(src: &str) -> Program {
+    let (program, errors) = parse(src);
+    assert!(errors.is_empty(), "{:?}", errors);
+    program
+}
+
+/// Every error as `line:column: message`.
+fn errors(src: &str) -> Vec<String> {
+    let (_, errors) = parse(src);
+    errors
+        .iter()
+        .map(|e| {
+            let (line, column) = e.span().line_col(src);
+            format!("{}:{}: {}", line, column, e)
+        })
+        .collect()
+}
+
+/// The statements of a program by their kind and name, with the bodies of
+/// functions, classes and blocks, for comparing what recovery kept.
+fn outline(statements: &[Statement]) -> String {
+    let outlines: Vec<String> = statements
+        .iter()
+        .map(|statement| match &statement.kind {
+            StatementKind::Let(variable) => format!("let {}", variable.name.name),
+            StatementKind::Function(function) => {
+                format!("func {} {}", function.name.name, braced(&function.body.statements))
+            }
+            StatementKind::Class(class) => {
+                let members: Vec<String> = class
+                    .members
+                    .iter()
+                    .map(|member| match member {
+                        ClassMember::Property(variable) => format!("prop {}", variable.name.name),
+                        ClassMember::Static(variable) => format!("let {}", variable.name.name),
+                        ClassMember::Method(method) => {
+                            format!("func {} {}", method.name.name, braced(&method.body.statements))
+                        }
+                    })
+                    .collect();
+                format!("class {} {{ {} }}", class.name.name, members.join("; "))
+            }
+            StatementKind::Block(block) => braced(&block.statements),
+            StatementKind::Return(_) => "return".to_string(),
+            StatementKind::Expression(_) => "expression".to_string(),
+            kind => format!("{:?}", kind),
+        })
+        .collect();
+    outlines.join("; ")
+}
+
+fn braced(statements: &[Statement]) -> String {
+    match statements {
+        [] => "{}".to_string(),
+        _ => format!("{{ {} }}", outline(statements)),
+    }
+}
+
+/// The initializer of the `let` statement at `index`.
+fn initializer(program: &Program, index: usize) -> &Expression {
+    match &program.statements[index].kind {
+        StatementKind::Let(variable) => variable.value.as_ref().unwrap(),
+        kind => panic!("expected a `let`, found {:?}", kind),
+    }
+}
+
+#[test]
+fn only_variables_members_and_indices_can_be_assigned() {
+    assert_eq!(
+        errors("1 = 2;\nf() += 3;\n"),
+        [
+            "1:1: invalid left hand side of `=`, expected a variable, member or index",
+            "2:1: invalid left hand side of `+=`, expected a variable, member or index",
+        ]
+    );
+
+    let program = parsed("a = 1; a.b += 2; a[0] -= 3;");
+    for statement in program.statements.iter() {
+        assert!(matches!(
+            statement.kind,
+            StatementKind::Expression(Expression {
+                kind: ExpressionKind::Assignment(..),
+                ..
+            })
+        ));
+    }
+}
+
+#[test]
+fn object_literals_take_named_quoted_and_shorthand_keys() {
+    let program = parsed(r#"let o = { a: 1, "b c": 2, d, e: { f: [1, 2,], }, };"#);
+
+    let properties = match &initializer(&program, 0).kind {
+        ExpressionKind::Object(properties) => properties,
+        kind => panic!("expected an object, found {:?}", kind),
+    };
+    let keys: Vec<_> = properties.iter().map(|p| p.key.name.as_str()).collect();
+    assert_eq!(keys, ["a", "b c", "d", "e"]);
+
+    // A shorthand property is the variable of the same name.
+    assert!(matches!(&properties[2].value.kind, ExpressionKind::Identifier(ident) if ident.name == "d"));
+    assert!(matches!(
+        &properties[3].value.kind,
+        ExpressionKind::Object(nested) if matches!(&nested[0].value.kind, ExpressionKind::Array(elements) if elements.len() == 2)
+    ));
+
+    // Only names can be shorthand, and every entry is separated by a comma.
+    assert_eq!(errors(r#"let o = { "a" };"#)[0], "1:15: expected `:`, found `}`");
+    assert_eq!(errors("let o = { a: 1 b: 2 };")[0], "1:16: expected `,` or `}`, found `b`");
+}
+
+#[test]
+fn array_literals_nest_and_allow_a_trailing_comma() {
+    let program = parsed("let xs = [1, [2, 3], [], ];");
+
+    let elements = match &initializer(&program, 0).kind {
+        ExpressionKind::Array(elements) => elements,
+        kind => panic!("expected an array, found {:?}", kind),
+    };
+    let lengths: Vec<_> = elements
+        .iter()
+        .map(|element| match &element.kind {
+            ExpressionKind::Array(elements) => Some(elements.len()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(lengths, [None, Some(2), Some(0)]);
+
+    assert_eq!(errors("let xs = [1, 2;")[0], "1:15: expected `,` or `]`, found `;`");
+}
+
+#[test]
+fn a_brace_starting_a_statement_opens_a_block() {
+    let program = parsed("{ a; }\n({ a: 1 });\nlet o = { a: 1 };");
+    assert!(matches!(program.statements[0].kind, StatementKind::Block(_)));
+    assert!(matches!(
+        &program.statements[1].kind,
+        StatementKind::Expression(Expression {
+            kind: ExpressionKind::Object(_),
+            ..
+        })
+    ));
+    assert!(matches!(initializer(&program, 2).kind, ExpressionKind::Object(_)));
+
+    // Unparenthesized, an object literal statement is a block with a stray `:`.
+    assert_eq!(errors("{ a: 1 };")[0], "1:4: expected `;` or operator, found `:`");
+}
+
+#[test]
+fn recovery_resumes_at_the_next_statement() {
+    let src = "let = 1;\nlet b = 2;";
+    assert_eq!(errors(src), ["1:5: expected identifier, found `=`"]);
+    assert_eq!(outline(&parse(src).0.statements), "let b");
+}
+
+#[test]
+fn recovery_inside_a_block_leaves_its_closing_brace() {
+    let src = "func f() {\n  let x = ;\n  return 1;\n}\nlet c = 3;";
+    assert_eq!(errors(src), ["2:11: expected expression, found `;`"]);
+    assert_eq!(outline(&parse(src).0.statements), "func f { return }; let c");
+
+    let src = "func f() { { 1 + ; } return 1; }";
+    assert_eq!(errors(src), ["1:18: expected expression, found `;`"]);
+    assert_eq!(outline(&parse(src).0.statements), "func f { {}; return }");
+}
+
+#[test]
+fn recovery_inside_a_class_keeps_the_other_members() {
+    let src = "class A {\n  prop = 1;\n  func m() { return 1; }\n}\nlet d = 4;";
+    assert_eq!(errors(src), ["2:8: expected identifier, found `=`"]);
+    assert_eq!(outline(&parse(src).0.statements), "class A { func m { return } }; let d");
+}
+
+#[test]
+fn recovery_stops_at_a_declaration_keyword() {
+    // Without a `;` to stop at, the `let` on the next line starts over.
+    let src = "let x = 1 2\nlet y = 2;\nfunc g() {}";
+    assert_eq!(errors(src), ["1:11: expected `;` or operator, found `2`"]);
+    assert_eq!(outline(&parse(src).0.statements), "let y; func g {}");
+}
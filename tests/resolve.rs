@@ -0,0 +1,90 @@
+use lsc::lex::TokenStream;
+use lsc::parse::Parser;
+use lsc::resolve::{self, DeclarationKind, ResolveError, ResolveErrorKind, Resolution};
+
+fn resolve(src: &str) -> (Resolution, Vec<ResolveError>) {
+    let (program, errors) = Parser::new(TokenStream::new(src)).parse();
+    assert!(errors.is_empty(), "{:?}", errors);
+    resolve::resolve(&program)
+}
+
+/// Every error as `line:column: message`.
+fn errors(src: &str) -> Vec<String> {
+    let (_, errors) = resolve(src);
+    errors
+        .iter()
+        .map(|e| {
+            let (line, column) = e.span.line_col(src);
+            format!("{}:{}: {}", line, column, e)
+        })
+        .collect()
+}
+
+#[test]
+fn names_resolve_through_every_enclosing_scope() {
+    let src = "
+        from io import { print };
+        let total = 0;
+        func add(x) { let y = x; { let z = y + total; print(z); } return add; }
+        class C { let count = 0; prop n = 1; func get() { return this.n + C.count; } }
+        print(later);
+        let later = 1;
+    ";
+    assert!(errors(src).is_empty(), "{:?}", errors(src));
+
+    // Module level variables and statics are globals in order of declaration,
+    // the variables of functions are their locals.
+    let (resolution, _) = resolve(src);
+    let mut kinds: Vec<_> = resolution.declarations.values().map(|d| (d.name.as_str(), d.kind.clone())).collect();
+    kinds.sort_by_key(|(name, _)| *name);
+    for (name, kind) in [
+        ("total", DeclarationKind::Global(0)),
+        ("count", DeclarationKind::Static(1)),
+        ("later", DeclarationKind::Global(2)),
+        ("n", DeclarationKind::Property(0)),
+        ("x", DeclarationKind::Local(0)),
+        ("z", DeclarationKind::Local(2)),
+    ] {
+        assert!(kinds.contains(&(name, kind.clone())), "no {} {:?} in {:?}", name, kind, kinds);
+    }
+}
+
+#[test]
+fn unresolved_names_are_reported_where_they_are_used() {
+    let src = "func f() { let a = b; { let c = 1; } return c; }\nd = 1;\n";
+    assert_eq!(
+        errors(src),
+        [
+            "1:20: cannot find `b` in this scope",
+            "1:45: cannot find `c` in this scope",
+            "2:1: cannot find `d` in this scope",
+        ]
+    );
+
+    // Variables of a function are only in scope after their declaration.
+    assert_eq!(errors("func f() { g = 1; let g = 2; }"), ["1:12: cannot find `g` in this scope"]);
+}
+
+#[test]
+fn duplicates_in_one_scope_are_reported_but_shadowing_is_allowed() {
+    let src = "let a = 1;\nfunc a() {}\nfunc f(x, x) { let a = 2; { let a = 3; } }\nclass C { prop p; func p() {} }";
+    let (_, found) = resolve(src);
+    assert_eq!(
+        errors(src),
+        [
+            "2:6: `a` is declared more than once in this scope",
+            "3:11: `x` is declared more than once in this scope",
+            "4:24: `p` is declared more than once in this scope",
+        ]
+    );
+
+    // Each points back at the declaration it collides with.
+    let previous: Vec<_> = found
+        .iter()
+        .map(|e| match &e.kind {
+            ResolveErrorKind::Duplicate { previous, .. } => previous.line_col(src),
+            kind => panic!("expected a duplicate, found {:?}", kind),
+        })
+        .collect();
+    assert_eq!(previous, [(1, 5), (3, 8), (4, 16)]);
+}
@@ -0,0 +1,45 @@
+use lsc::lex::TokenStream;
+use lsc::parse::Parser;
+use lsc::resolve;
+use lsc::suggest::{closest, edit_distance};
+
+#[test]
+fn swaps_of_adjacent_characters_are_one_edit() {
+    assert_eq!(edit_distance("_hanlders", "_handlers"), 1);
+    assert_eq!(edit_distance("print", "pritn"), 1);
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(edit_distance("", "abc"), 3);
+    assert_eq!(edit_distance("same", "same"), 0);
+}
+
+#[test]
+fn suggestions_are_at_most_a_third_of_the_name_away() {
+    // Six characters allow two edits, but not three.
+    assert_eq!(closest("abcdef", ["abcdxy"]), Some("abcdxy"));
+    assert_eq!(closest("abcdef", ["abcxyz"]), None);
+
+    // Short names allow one edit, as long as something of the name is left.
+    assert_eq!(closest("ab", ["ac"]), Some("ac"));
+    assert_eq!(closest("ab", ["abcd"]), None);
+    assert_eq!(closest("x", ["y"]), None);
+
+    // The name itself is no suggestion, and ties go to the first alphabetically.
+    assert_eq!(closest("total", ["total"]), None);
+    assert_eq!(closest("cat", ["hat", "bat", "cast"]), Some("bat"));
+}
+
+#[test]
+fn misspelled_names_and_members_come_with_a_suggestion() {
+    let src = "
+        from io import { print };
+        class Emitter { prop _handlers = 1; func on() { return this._hanlders; } }
+        prnt(1);
+        let counter = 0;
+        x;
+    ";
+    let (program, _) = Parser::new(TokenStream::new(src)).parse();
+    let (_, errors) = resolve::resolve(&program);
+
+    let suggestions: Vec<_> = errors.iter().map(|e| e.suggestion.as_deref()).collect();
+    assert_eq!(suggestions, [Some("_handlers"), Some("print"), None]);
+}
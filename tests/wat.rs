@@ -0,0 +1,92 @@
+mod common;
+
+use lsc::emit::{wat, ModuleEmitter};
+
+const SRC: &str = r#"
+    from io import { print };
+    func square(x: i32): i32 { if x < 0 { return 0; } return x * x; }
+    let s = "hi";
+    print(square(3));
+"#;
+
+fn render(style: wat::Style) -> String {
+    let module = common::compile(SRC).unwrap();
+    ModuleEmitter::default().wat(&module, style).unwrap()
+}
+
+#[test]
+fn flat_instructions_are_listed_one_per_line() {
+    let expected = r#"
+(module
+  (type (;0;) (func (param i32)))
+  (type (;1;) (func (param i32) (result i32)))
+  (type (;2;) (func))
+  (import "io" "print" (func $io.print (type 0) (param i32)))
+  (func $square (type 1) (param $x i32) (result i32)
+    (local i32 i32)
+    local.get $x
+    i32.const 0
+    i32.lt_s
+    local.set 1
+    local.get 1
+    if
+      i32.const 0
+      return
+    end
+    local.get $x
+    local.get $x
+    i32.mul
+    local.set 2
+    local.get 2
+    return
+    unreachable)
+  (func $<init> (type 2)
+    (local i32)
+    i32.const 3
+    call $square
+    local.set 0
+    local.get 0
+    call $io.print)
+  (memory (;0;) 2 10)
+  (global $s (mut i32) (i32.const 8))
+  (global $heap_pointer (mut i32) (i32.const 16))
+  (export "memory" (memory 0))
+  (export "_start" (func $<init>))
+  (export "square" (func $square))
+  (data (;0;) (i32.const 8) "\02\00\00\00hi"))
+"#;
+    assert_eq!(render(wat::Style::Flat), expected.trim_start());
+}
+
+#[test]
+fn folded_instructions_nest_their_operands() {
+    let expected = r#"
+(module
+  (type (;0;) (func (param i32)))
+  (type (;1;) (func (param i32) (result i32)))
+  (type (;2;) (func))
+  (import "io" "print" (func $io.print (type 0) (param i32)))
+  (func $square (type 1) (param $x i32) (result i32)
+    (local i32 i32)
+    (local.set 1 (i32.lt_s (local.get $x) (i32.const 0)))
+    (if
+      (local.get 1)
+      (then
+        (return (i32.const 0))))
+    (local.set 2 (i32.mul (local.get $x) (local.get $x)))
+    (return (local.get 2))
+    (unreachable))
+  (func $<init> (type 2)
+    (local i32)
+    (local.set 0 (call $square (i32.const 3)))
+    (call $io.print (local.get 0)))
+  (memory (;0;) 2 10)
+  (global $s (mut i32) (i32.const 8))
+  (global $heap_pointer (mut i32) (i32.const 16))
+  (export "memory" (memory 0))
+  (export "_start" (func $<init>))
+  (export "square" (func $square))
+  (data (;0;) (i32.const 8) "\02\00\00\00hi"))
+"#;
+    assert_eq!(render(wat::Style::Folded), expected.trim_start());
+}